@@ -0,0 +1,119 @@
+use async_sqlite::{rusqlite::types::Value, Client, ClientBuilder};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use tokio::runtime::{Builder, Runtime};
+
+const ROWS: i64 = 10_000;
+
+fn runtime() -> Runtime {
+    Builder::new_current_thread()
+        .build()
+        .expect("building runtime")
+}
+
+async fn open_client() -> Client {
+    open_client_with(ClientBuilder::new()).await
+}
+
+async fn open_client_with(builder: ClientBuilder) -> Client {
+    let client = builder.open().await.expect("client unable to be opened");
+    client
+        .execute(
+            "CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL)",
+            [],
+        )
+        .await
+        .expect("writing schema");
+    client
+}
+
+fn bench_insert_rows(c: &mut Criterion) {
+    let rt = runtime();
+    let mut group = c.benchmark_group("insert_10k_rows");
+    group.sample_size(10);
+
+    group.bench_function("execute_batch_params", |b| {
+        b.iter_batched(
+            || rt.block_on(open_client()),
+            |client| {
+                rt.block_on(async {
+                    let rows = (0..ROWS).map(|id| (id, "value"));
+                    client
+                        .execute_batch_params("INSERT INTO testing VALUES (?, ?)", rows)
+                        .await
+                        .expect("inserting rows");
+                })
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.bench_function("bulk_insert", |b| {
+        b.iter_batched(
+            || rt.block_on(open_client()),
+            |client| {
+                rt.block_on(async {
+                    let rows = (0..ROWS)
+                        .map(|id| vec![Value::Integer(id), Value::from("value".to_owned())]);
+                    client
+                        .bulk_insert("testing", &["id", "val"], rows, 500)
+                        .await
+                        .expect("inserting rows");
+                })
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.bench_function("execute_per_row", |b| {
+        b.iter_batched(
+            || rt.block_on(open_client()),
+            |client| {
+                rt.block_on(async {
+                    for id in 0..ROWS {
+                        client
+                            .execute("INSERT INTO testing VALUES (?, ?)", (id, "value"))
+                            .await
+                            .expect("inserting row");
+                    }
+                })
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.finish();
+}
+
+fn bench_query_loop(c: &mut Criterion) {
+    let rt = runtime();
+    let mut group = c.benchmark_group("query_10k_rows");
+
+    for (name, full_mutex) in [("no_mutex", false), ("full_mutex", true)] {
+        let client = rt.block_on(async {
+            let client = open_client_with(ClientBuilder::new().full_mutex(full_mutex)).await;
+            let rows = (0..ROWS).map(|id| (id, "value"));
+            client
+                .execute_batch_params("INSERT INTO testing VALUES (?, ?)", rows)
+                .await
+                .expect("inserting rows");
+            client
+        });
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                rt.block_on(client.conn(|conn| {
+                    let mut stmt = conn.prepare_cached("SELECT val FROM testing WHERE id = ?")?;
+                    for id in 0..ROWS {
+                        stmt.query_row([id], |row| row.get_ref(0).map(|_| ()))?;
+                    }
+                    Ok(())
+                }))
+                .expect("querying rows")
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_insert_rows, bench_query_loop);
+criterion_main!(benches);
@@ -1,13 +1,39 @@
 use std::{
+    cell::Cell,
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    fmt,
+    future::Future,
+    mem,
+    panic::{self, AssertUnwindSafe},
     path::{Path, PathBuf},
+    sync::{
+        atomic::{
+            AtomicBool,
+            Ordering::{Acquire, Release},
+        },
+        Arc, Mutex, OnceLock, PoisonError, RwLock,
+    },
+    task::{Context, Poll, Wake, Waker},
     thread,
+    time::{Duration, Instant},
 };
 
-use crate::Error;
+use crate::{Error, Migrations};
 
-use crossbeam_channel::{bounded, unbounded, Sender};
-use futures_channel::oneshot;
-use rusqlite::{Connection, OpenFlags};
+use crossbeam_channel::{
+    bounded, unbounded, Receiver, RecvTimeoutError, Select, SendError, Sender, TrySendError,
+};
+use futures_channel::{mpsc, oneshot};
+use futures_util::future::{poll_fn, select, Either};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use rusqlite::{
+    config::DbConfig,
+    params_from_iter,
+    types::{ToSql, ToSqlOutput, Value, ValueRef},
+    Connection, InterruptHandle, OpenFlags, OptionalExtension, Params, Row, Savepoint, Transaction,
+    TransactionBehavior,
+};
 
 /// A `ClientBuilder` can be used to create a [`Client`] with custom
 /// configuration.
@@ -28,12 +54,94 @@ use rusqlite::{Connection, OpenFlags};
 /// # Ok(())
 /// # }
 /// ```
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Default)]
 pub struct ClientBuilder {
     pub(crate) path: Option<PathBuf>,
     pub(crate) flags: OpenFlags,
+    pub(crate) page_size: Option<u32>,
     pub(crate) journal_mode: Option<JournalMode>,
+    pub(crate) synchronous: Option<Synchronous>,
+    pub(crate) temp_store: Option<TempStore>,
+    pub(crate) cache_size: Option<i64>,
+    pub(crate) mmap_size: Option<i64>,
+    pub(crate) foreign_keys: Option<bool>,
+    pub(crate) statement_cache_capacity: Option<usize>,
+    pub(crate) soft_heap_limit: Option<i64>,
+    pub(crate) pragmas: Vec<(String, String)>,
+    pub(crate) db_configs: Vec<(DbConfig, bool)>,
+    pub(crate) attachments: Vec<(PathBuf, String)>,
+    pub(crate) skip_pragma_verification: bool,
     pub(crate) vfs: Option<String>,
+    pub(crate) open_retry: Option<(usize, Duration)>,
+    pub(crate) open_timeout: Option<Duration>,
+    pub(crate) queue_capacity: Option<usize>,
+    pub(crate) thread_name: Option<String>,
+    pub(crate) thread_stack_size: Option<usize>,
+    #[cfg(feature = "load_extension")]
+    pub(crate) extensions: Vec<(PathBuf, Option<String>)>,
+    pub(crate) on_open: Option<OnOpen>,
+    pub(crate) on_close: Option<OnClose>,
+    pub(crate) busy_handler: Option<BusyHandler>,
+    // The queue shared with the other connections of a pool, if any.
+    pub(crate) shared_queue: Option<SharedQueue>,
+    #[cfg(feature = "hooks")]
+    pub(crate) statement_timeout: Option<Duration>,
+    #[cfg(feature = "trace")]
+    pub(crate) profile: Option<ProfileFn>,
+    #[cfg(feature = "trace")]
+    pub(crate) trace: Option<TraceFn>,
+    pub(crate) auto_reconnect: bool,
+}
+
+/// A callback invoked with every newly opened connection.
+pub(crate) type OnOpen = Arc<dyn Fn(&mut Connection) -> Result<(), rusqlite::Error> + Send + Sync>;
+
+/// A callback invoked with every connection right before it is closed.
+pub(crate) type OnClose = Arc<dyn Fn(&mut Connection) + Send + Sync>;
+
+/// A callback invoked with the number of prior invocations whenever a table
+/// is locked.
+pub(crate) type BusyHandler = Arc<dyn Fn(i32) -> bool + Send + Sync>;
+
+/// A callback invoked with the SQL and execution time of every statement.
+#[cfg(feature = "trace")]
+pub(crate) type ProfileFn = Arc<dyn Fn(&str, Duration) + Send + Sync>;
+
+/// A callback invoked with the expanded SQL of every statement.
+#[cfg(feature = "trace")]
+pub(crate) type TraceFn = Arc<dyn Fn(&str) + Send + Sync>;
+
+impl fmt::Debug for ClientBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut s = f.debug_struct("ClientBuilder");
+        s.field("path", &self.path)
+            .field("flags", &self.flags)
+            .field("page_size", &self.page_size)
+            .field("journal_mode", &self.journal_mode)
+            .field("synchronous", &self.synchronous)
+            .field("temp_store", &self.temp_store)
+            .field("cache_size", &self.cache_size)
+            .field("mmap_size", &self.mmap_size)
+            .field("foreign_keys", &self.foreign_keys)
+            .field("statement_cache_capacity", &self.statement_cache_capacity)
+            .field("soft_heap_limit", &self.soft_heap_limit)
+            .field("pragmas", &self.pragmas)
+            .field("db_configs", &self.db_configs)
+            .field("attachments", &self.attachments)
+            .field("skip_pragma_verification", &self.skip_pragma_verification)
+            .field("vfs", &self.vfs)
+            .field("open_retry", &self.open_retry)
+            .field("open_timeout", &self.open_timeout)
+            .field("queue_capacity", &self.queue_capacity)
+            .field("thread_name", &self.thread_name)
+            .field("thread_stack_size", &self.thread_stack_size);
+        #[cfg(feature = "load_extension")]
+        s.field("extensions", &self.extensions);
+        #[cfg(feature = "hooks")]
+        s.field("statement_timeout", &self.statement_timeout);
+        s.field("auto_reconnect", &self.auto_reconnect)
+            .finish_non_exhaustive()
+    }
 }
 
 impl ClientBuilder {
@@ -58,6 +166,141 @@ impl ClientBuilder {
         self
     }
 
+    /// Specify whether to open connections in read-only mode.
+    ///
+    /// When enabled, [`OpenFlags::SQLITE_OPEN_READ_ONLY`] is set and the
+    /// read-write and create flags are cleared, so the database must already
+    /// exist. When disabled, the read-write and create flags are restored.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        if read_only {
+            self.flags
+                .remove(OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE);
+            self.flags.insert(OpenFlags::SQLITE_OPEN_READ_ONLY);
+        } else {
+            self.flags.remove(OpenFlags::SQLITE_OPEN_READ_ONLY);
+            self.flags
+                .insert(OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE);
+        }
+        self
+    }
+
+    /// Specify whether to open connections in sqlite's
+    /// [serialized](https://www.sqlite.org/threadsafe.html) threading mode.
+    ///
+    /// As each connection is confined to its own worker thread, connections
+    /// are opened with [`OpenFlags::SQLITE_OPEN_NO_MUTEX`] by default, which
+    /// avoids the overhead of sqlite's internal mutexes, even if custom
+    /// [`flags`](ClientBuilder::flags) don't include it. When enabled,
+    /// [`OpenFlags::SQLITE_OPEN_FULL_MUTEX`] is set instead, which is only
+    /// needed if the connection is accessed from other threads, such as
+    /// through its raw handle.
+    pub fn full_mutex(mut self, full_mutex: bool) -> Self {
+        self.flags
+            .set(OpenFlags::SQLITE_OPEN_FULL_MUTEX, full_mutex);
+        self.flags.set(OpenFlags::SQLITE_OPEN_NO_MUTEX, !full_mutex);
+        self
+    }
+
+    /// Specify whether the path is interpreted as a
+    /// [URI filename](https://www.sqlite.org/uri.html).
+    ///
+    /// When enabled, [`OpenFlags::SQLITE_OPEN_URI`] is set, so a path such as
+    /// `file:data.db?mode=ro&immutable=1` can use query parameters to
+    /// configure the connection. When disabled, the URI flag is cleared.
+    ///
+    /// The flag is part of [`OpenFlags::default()`], so this is mostly useful
+    /// after specifying custom [`flags`](ClientBuilder::flags).
+    pub fn uri(mut self, uri: bool) -> Self {
+        self.flags.set(OpenFlags::SQLITE_OPEN_URI, uri);
+        self
+    }
+
+    /// Specify whether the database file must already exist.
+    ///
+    /// When enabled, [`OpenFlags::SQLITE_OPEN_CREATE`] is cleared so that
+    /// opening a nonexistent path returns an error instead of creating an
+    /// empty database. When disabled, the create flag is restored.
+    pub fn must_exist(mut self, must_exist: bool) -> Self {
+        self.flags.set(OpenFlags::SQLITE_OPEN_CREATE, !must_exist);
+        self
+    }
+
+    /// Specify whether to create the database file if it doesn't exist.
+    ///
+    /// Toggles [`OpenFlags::SQLITE_OPEN_CREATE`], which is set by default.
+    /// This is the inverse of [`must_exist`](ClientBuilder::must_exist).
+    pub fn create(mut self, create: bool) -> Self {
+        self.flags.set(OpenFlags::SQLITE_OPEN_CREATE, create);
+        self
+    }
+
+    /// Specify whether to open connections for reading and writing.
+    ///
+    /// Toggles [`OpenFlags::SQLITE_OPEN_READ_WRITE`], which is set by
+    /// default. Unlike [`read_only`](ClientBuilder::read_only), no other flags
+    /// are changed.
+    pub fn read_write(mut self, read_write: bool) -> Self {
+        self.flags
+            .set(OpenFlags::SQLITE_OPEN_READ_WRITE, read_write);
+        self
+    }
+
+    /// Specify whether to open connections in sqlite's
+    /// [multi-thread](https://www.sqlite.org/threadsafe.html) threading mode.
+    ///
+    /// Toggles [`OpenFlags::SQLITE_OPEN_NO_MUTEX`], which is set by default.
+    /// Connections are opened with this flag regardless unless
+    /// [`full_mutex`](ClientBuilder::full_mutex) is enabled, so disabling it
+    /// on its own has no effect.
+    pub fn no_mutex(mut self, no_mutex: bool) -> Self {
+        self.flags.set(OpenFlags::SQLITE_OPEN_NO_MUTEX, no_mutex);
+        self
+    }
+
+    /// Specify whether to open connections with sqlite's
+    /// [shared cache](https://www.sqlite.org/sharedcache.html) enabled.
+    ///
+    /// Toggles [`OpenFlags::SQLITE_OPEN_SHARED_CACHE`]. As a connection can't
+    /// use both a shared and a private cache, enabling it clears
+    /// [`OpenFlags::SQLITE_OPEN_PRIVATE_CACHE`].
+    pub fn shared_cache(mut self, shared_cache: bool) -> Self {
+        self.flags
+            .set(OpenFlags::SQLITE_OPEN_SHARED_CACHE, shared_cache);
+        if shared_cache {
+            self.flags.remove(OpenFlags::SQLITE_OPEN_PRIVATE_CACHE);
+        }
+        self
+    }
+
+    /// Specify whether to open connections with a private cache, even if
+    /// shared cache mode is enabled process-wide.
+    ///
+    /// Toggles [`OpenFlags::SQLITE_OPEN_PRIVATE_CACHE`]. Enabling it clears
+    /// [`OpenFlags::SQLITE_OPEN_SHARED_CACHE`].
+    pub fn private_cache(mut self, private_cache: bool) -> Self {
+        self.flags
+            .set(OpenFlags::SQLITE_OPEN_PRIVATE_CACHE, private_cache);
+        if private_cache {
+            self.flags.remove(OpenFlags::SQLITE_OPEN_SHARED_CACHE);
+        }
+        self
+    }
+
+    /// Specify the [`page_size`](https://www.sqlite.org/pragma.html#pragma_page_size)
+    /// in bytes to set when opening a new connection.
+    ///
+    /// The page size can only be changed before the database is first
+    /// written to, so it is set before any other configuration, including the
+    /// [`journal_mode`](ClientBuilder::journal_mode). Changing the page size of
+    /// an existing database requires a `VACUUM`, and isn't possible at all in
+    /// WAL mode, so opening such a database returns an
+    /// [`Error::PragmaUpdate`] unless pragma verification is skipped. By
+    /// default, no `page_size` is explicitly set.
+    pub fn page_size(mut self, page_size: u32) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
     /// Specify the [`JournalMode`] to set when opening a new connection.
     ///
     /// By default, no `journal_mode` is explicity set.
@@ -66,12 +309,310 @@ impl ClientBuilder {
         self
     }
 
+    /// Specify the [`Synchronous`] setting to set when opening a new
+    /// connection.
+    ///
+    /// By default, no `synchronous` setting is explicitly set.
+    pub fn synchronous(mut self, synchronous: Synchronous) -> Self {
+        self.synchronous = Some(synchronous);
+        self
+    }
+
+    /// Specify the [`TempStore`] setting to set when opening a new
+    /// connection, which controls where temporary tables and indices are
+    /// stored.
+    ///
+    /// By default, no `temp_store` setting is explicitly set.
+    pub fn temp_store(mut self, temp_store: TempStore) -> Self {
+        self.temp_store = Some(temp_store);
+        self
+    }
+
+    /// Specify the [`cache_size`](https://www.sqlite.org/pragma.html#pragma_cache_size)
+    /// to set when opening a new connection.
+    ///
+    /// Positive values are a number of pages, while negative values are a
+    /// number of KiB. By default, no `cache_size` is explicitly set.
+    pub fn cache_size(mut self, cache_size: i64) -> Self {
+        self.cache_size = Some(cache_size);
+        self
+    }
+
+    /// Specify the [`mmap_size`](https://www.sqlite.org/pragma.html#pragma_mmap_size)
+    /// in bytes to set when opening a new connection.
+    ///
+    /// sqlite may clamp the requested value to a compile-time maximum, so the
+    /// effective value is only verified when memory-mapped I/O is disabled by
+    /// requesting zero. By default, no `mmap_size` is explicitly set.
+    pub fn mmap_size(mut self, mmap_size: i64) -> Self {
+        self.mmap_size = Some(mmap_size);
+        self
+    }
+
+    /// Specify whether to enforce [foreign key](https://www.sqlite.org/foreignkeys.html)
+    /// constraints on a new connection.
+    ///
+    /// By default, sqlite doesn't enforce foreign keys, and no
+    /// `foreign_keys` setting is explicitly set.
+    pub fn foreign_keys(mut self, foreign_keys: bool) -> Self {
+        self.foreign_keys = Some(foreign_keys);
+        self
+    }
+
+    /// Specify the maximum number of prepared statements to keep in each
+    /// connection's statement cache, as used by
+    /// [`prepare_cached`](rusqlite::Connection::prepare_cached).
+    ///
+    /// Setting a capacity of `0` disables caching. By default, rusqlite's
+    /// capacity of 16 is used.
+    pub fn statement_cache_capacity(mut self, capacity: usize) -> Self {
+        self.statement_cache_capacity = Some(capacity);
+        self
+    }
+
+    /// Specify the [`soft_heap_limit`](https://www.sqlite.org/pragma.html#pragma_soft_heap_limit)
+    /// in bytes to set when opening a new connection.
+    ///
+    /// Note that this limit is global to the process, so it affects all
+    /// sqlite connections rather than only this client's. A limit of `0`
+    /// disables it. By default, no `soft_heap_limit` is explicitly set.
+    pub fn soft_heap_limit(mut self, limit: i64) -> Self {
+        self.soft_heap_limit = Some(limit);
+        self
+    }
+
+    /// Specify an arbitrary [pragma](https://www.sqlite.org/pragma.html) to
+    /// set when opening a new connection.
+    ///
+    /// Can be called multiple times, and each pragma is applied as
+    /// `PRAGMA name = value` in the order it was added, after any other
+    /// configured pragmas. If sqlite returns the resulting value, it is
+    /// verified against `value`.
+    pub fn pragma(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.pragmas.push((name.into(), value.into()));
+        self
+    }
+
+    /// Specify a [database connection configuration](https://www.sqlite.org/c3ref/c_dbconfig_defensive.html)
+    /// option to enable or disable when opening a new connection.
+    ///
+    /// Options are applied in order using
+    /// [`set_db_config`](rusqlite::Connection::set_db_config) before any
+    /// pragmas are set. Some security-relevant settings are only available
+    /// this way, such as [`DbConfig::SQLITE_DBCONFIG_DEFENSIVE`], which
+    /// prevents SQL from deliberately corrupting the database file and should
+    /// be enabled when running untrusted SQL.
+    pub fn db_config(mut self, config: DbConfig, enabled: bool) -> Self {
+        self.db_configs.push((config, enabled));
+        self
+    }
+
+    /// Specify whether to verify the resulting values of the configured
+    /// pragmas, including the [`JournalMode`], [`Synchronous`] setting, and
+    /// `cache_size`.
+    ///
+    /// When enabled, a mismatching value results in a
+    /// [`PragmaUpdate`](Error::PragmaUpdate) error. Disabling verification can
+    /// avoid false failures for VFSes or in-memory databases that
+    /// legitimately report a different value. By default, pragmas are
+    /// verified.
+    pub fn verify_pragmas(mut self, verify: bool) -> Self {
+        self.skip_pragma_verification = !verify;
+        self
+    }
+
     /// Specify the name of the [vfs](https://www.sqlite.org/vfs.html) to use.
     pub fn vfs(mut self, vfs: &str) -> Self {
         self.vfs = Some(vfs.to_owned());
         self
     }
 
+    /// Specify a database file at `path` to
+    /// [attach](https://www.sqlite.org/lang_attach.html) as `alias` when
+    /// opening a new connection.
+    ///
+    /// Can be called multiple times to attach several databases, which are
+    /// attached in the order they were added. Attaching the same alias twice
+    /// returns an [`Error::Rusqlite`] when opening the connection.
+    pub fn attach<P: AsRef<Path>>(mut self, path: P, alias: &str) -> Self {
+        self.attachments
+            .push((path.as_ref().into(), alias.to_owned()));
+        self
+    }
+
+    /// Specify a [runtime extension](https://www.sqlite.org/loadext.html) to
+    /// load when opening a new connection.
+    ///
+    /// Can be called multiple times to load several extensions, which are
+    /// loaded in the order they were added. If `entry_point` is `None`,
+    /// sqlite derives the entry point from the file name.
+    #[cfg(feature = "load_extension")]
+    pub fn extension<P: AsRef<Path>>(mut self, path: P, entry_point: Option<&str>) -> Self {
+        self.extensions
+            .push((path.as_ref().into(), entry_point.map(str::to_owned)));
+        self
+    }
+
+    /// Specify a callback to run on every newly opened connection.
+    ///
+    /// The callback runs on the connection's worker thread after all other
+    /// configuration has been applied, and can be used to register
+    /// functions, load extensions, or set connection-specific pragmas. If the
+    /// callback returns an error, opening the connection fails.
+    #[allow(clippy::type_complexity)]
+    pub fn on_open(
+        mut self,
+        on_open: Arc<dyn Fn(&mut Connection) -> Result<(), rusqlite::Error> + Send + Sync>,
+    ) -> Self {
+        self.on_open = Some(on_open);
+        self
+    }
+
+    /// Specify a callback to run on the connection right before it is closed.
+    ///
+    /// The callback runs on the connection's worker thread once all
+    /// previously queued calls have completed, whether the client is closed
+    /// explicitly or by dropping it, and can be used for cleanup such as
+    /// running `PRAGMA optimize` or a final checkpoint. It runs at most once
+    /// per connection, and a panic in the callback doesn't prevent the
+    /// connection from closing.
+    pub fn on_close(mut self, on_close: Arc<dyn Fn(&mut Connection) + Send + Sync>) -> Self {
+        self.on_close = Some(on_close);
+        self
+    }
+
+    /// Specify a [busy handler](https://www.sqlite.org/c3ref/busy_handler.html)
+    /// to invoke when the database is locked by another connection.
+    ///
+    /// The handler is called on the worker thread with the number of times
+    /// it was previously invoked for the same locking event. Returning `true`
+    /// retries the operation, while returning `false` gives up so that it
+    /// fails with `SQLITE_BUSY`. This allows adaptive backoff, such as
+    /// sleeping for longer after each attempt. The handler replaces the
+    /// default busy timeout of 5 seconds, and is installed before any other
+    /// configuration is applied.
+    pub fn busy_handler(mut self, busy_handler: Arc<dyn Fn(i32) -> bool + Send + Sync>) -> Self {
+        self.busy_handler = Some(busy_handler);
+        self
+    }
+
+    /// Specify a callback to invoke with the SQL text and execution time of
+    /// every statement run on the connection.
+    ///
+    /// The callback is installed before any other configuration is applied.
+    /// As it runs on the worker thread, it should return quickly, such as by
+    /// only logging statements slower than a threshold.
+    #[cfg(feature = "trace")]
+    #[allow(clippy::type_complexity)]
+    pub fn profile(mut self, profile: Arc<dyn Fn(&str, Duration) + Send + Sync>) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Specify a callback to invoke with the expanded SQL text of every
+    /// statement run on the connection, with parameters replaced by their
+    /// bound values.
+    ///
+    /// The callback is installed before any other configuration is applied.
+    /// As it runs on the worker thread, it should return quickly.
+    #[cfg(feature = "trace")]
+    pub fn trace(mut self, trace: Arc<dyn Fn(&str) + Send + Sync>) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    /// Specify how many attempts to make when opening a connection fails
+    /// because the database is busy, such as when another connection is
+    /// concurrently changing the journal mode.
+    ///
+    /// The first retry waits for `delay`, doubling after every subsequent
+    /// attempt. By default, opening a connection is only attempted once.
+    pub fn open_retry(mut self, attempts: usize, delay: Duration) -> Self {
+        self.open_retry = Some((attempts, delay));
+        self
+    }
+
+    /// Specify the maximum time to wait for a connection to be opened.
+    ///
+    /// This includes applying all configuration and any
+    /// [`open_retry`](ClientBuilder::open_retry) attempts. If the connection
+    /// isn't established in time, an [`Error::Timeout`] error is returned. By
+    /// default, opening a connection waits indefinitely.
+    pub fn open_timeout(mut self, timeout: Duration) -> Self {
+        self.open_timeout = Some(timeout);
+        self
+    }
+
+    /// Specify the maximum number of calls that can be queued for the
+    /// connection's worker thread.
+    ///
+    /// Once the queue is full, async calls such as [`Client::conn`] wait for
+    /// space to become available, and blocking calls such as
+    /// [`Client::conn_blocking`] block the current thread. Closing the client
+    /// doesn't wait for space, and runs once the queued calls have completed.
+    /// A capacity of zero is treated as one. By default, the queue is
+    /// unbounded.
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = Some(capacity);
+        self
+    }
+
+    /// Specify a time budget for each call made on the connection, after
+    /// which its running statement is aborted.
+    ///
+    /// The budget is checked by a
+    /// [progress handler](https://www.sqlite.org/c3ref/progress_handler.html),
+    /// and covers every statement run by a single call, such as one
+    /// [`Client::conn`] closure or [`Client::execute`], rather than each
+    /// statement on its own. Streaming queries, such as
+    /// [`Client::query_stream`] and [`Client::query_iter`], restart the
+    /// budget for each row they deliver, so a slow consumer doesn't count
+    /// against it. This is a safety net against runaway queries, and an
+    /// aborted statement fails with
+    /// [`rusqlite::ErrorCode::OperationInterrupted`]. Registering another
+    /// handler with [`Client::set_progress_handler`] replaces it.
+    #[cfg(feature = "hooks")]
+    pub fn statement_timeout(mut self, timeout: Duration) -> Self {
+        self.statement_timeout = Some(timeout);
+        self
+    }
+
+    /// Specify the name of the connection's worker thread, which makes it
+    /// easier to identify in thread dumps and profilers.
+    ///
+    /// The name must not contain null bytes. By default, the thread is
+    /// unnamed.
+    pub fn thread_name(mut self, name: impl Into<String>) -> Self {
+        self.thread_name = Some(name.into());
+        self
+    }
+
+    /// Specify the stack size, in bytes, of the connection's worker thread.
+    ///
+    /// This can be increased for deeply recursive queries or functions. By
+    /// default, the platform's default stack size for spawned threads is
+    /// used.
+    pub fn thread_stack_size(mut self, size: usize) -> Self {
+        self.thread_stack_size = Some(size);
+        self
+    }
+
+    /// Specify whether to transparently reopen the connection if its worker
+    /// thread has stopped unexpectedly.
+    ///
+    /// When enabled, a call that can't be sent to the worker thread reopens
+    /// the connection with this configuration, including all pragmas and the
+    /// [`on_open`](ClientBuilder::on_open) callback, and is then retried
+    /// once. Reopening blocks the calling thread until the new connection is
+    /// ready. A client that was explicitly closed is never reopened.
+    ///
+    /// Note that reopening an in-memory database results in a fresh, empty
+    /// database. By default, connections are not reopened.
+    pub fn auto_reconnect(mut self, auto_reconnect: bool) -> Self {
+        self.auto_reconnect = auto_reconnect;
+        self
+    }
+
     /// Returns a new [`Client`] that uses the `ClientBuilder` configuration.
     ///
     /// # Examples
@@ -111,36 +652,272 @@ enum Command {
 
 /// Client represents a single sqlite connection that can be used from async
 /// contexts.
+///
+/// Cloning a `Client` is cheap, as all clones share the same background
+/// connection. When the last clone is dropped, the connection is closed on
+/// its worker thread once any queued work has completed.
 #[derive(Clone)]
 pub struct Client {
+    state: Arc<State>,
+}
+
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+
+struct State {
+    worker: RwLock<Worker>,
+    path: Option<PathBuf>,
+    // The configuration to reopen the connection with, if
+    // `auto_reconnect` is enabled.
+    reconnect: Option<ClientBuilder>,
+    shutdown: AtomicBool,
+}
+
+impl State {
+    fn new(worker: Worker, path: Option<PathBuf>, reconnect: Option<ClientBuilder>) -> Self {
+        Self {
+            worker: RwLock::new(worker),
+            path,
+            reconnect,
+            shutdown: AtomicBool::new(false),
+        }
+    }
+
+    fn worker(&self) -> Worker {
+        self.worker
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    // Returns `Error::Reentrant` if called from this client's own worker
+    // thread, where blocking on a reply would never complete.
+    fn check_reentrant(&self) -> Result<(), Error> {
+        let id = Arc::as_ptr(&self.worker().closed) as usize;
+        if CURRENT_WORKER.with(Cell::get) == id {
+            return Err(Error::Reentrant);
+        }
+        Ok(())
+    }
+
+    // Sends a command that the caller will block on.
+    fn send_blocking(&self, cmd: Command) -> Result<(), Error> {
+        self.check_reentrant()?;
+        self.send(cmd)
+    }
+
+    fn send(&self, cmd: Command) -> Result<(), Error> {
+        let worker = self.worker();
+        match worker.send(cmd) {
+            Ok(()) => Ok(()),
+            Err(SendError(cmd)) => self.resend(&worker, cmd),
+        }
+    }
+
+    // Sends a command, waiting without blocking the current thread if the
+    // worker's queue is full.
+    async fn send_async(&self, cmd: Command) -> Result<(), Error> {
+        let worker = self.worker();
+        let mut cmd = Some(cmd);
+        let res = poll_fn(|cx| {
+            let c = cmd.take().expect("send polled after completion");
+            let c = match worker.try_send(c) {
+                Ok(()) => return Poll::Ready(Ok(())),
+                Err(TrySendError::Disconnected(c)) => return Poll::Ready(Err(c)),
+                Err(TrySendError::Full(c)) => c,
+            };
+            // Retry after registering, in case the worker made space in
+            // between.
+            worker.senders.register(cx.waker());
+            match worker.try_send(c) {
+                Ok(()) => Poll::Ready(Ok(())),
+                Err(TrySendError::Disconnected(c)) => Poll::Ready(Err(c)),
+                Err(TrySendError::Full(c)) => {
+                    cmd = Some(c);
+                    Poll::Pending
+                }
+            }
+        })
+        .await;
+        match res {
+            Ok(()) => Ok(()),
+            Err(cmd) => self.resend(&worker, cmd),
+        }
+    }
+
+    // Handles a command that couldn't be sent to `worker` because it exited,
+    // reconnecting if enabled.
+    fn resend(&self, worker: &Worker, cmd: Command) -> Result<(), Error> {
+        let Some(builder) = &self.reconnect else {
+            return Err(Error::Closed);
+        };
+        if self.shutdown.load(Acquire) || matches!(cmd, Command::Shutdown(_)) {
+            return Err(Error::Closed);
+        }
+
+        let mut current = self.worker.write().unwrap_or_else(PoisonError::into_inner);
+        // Another caller may have already reconnected while we were waiting
+        // for the lock.
+        if current.conn_tx.same_channel(&worker.conn_tx) {
+            *current = Worker::open_blocking(builder.clone())?;
+        }
+        Ok(current.send(cmd)?)
+    }
+}
+
+impl Drop for State {
+    fn drop(&mut self) {
+        // Best-effort shutdown so that `Connection::close` runs on the worker
+        // thread; any error is ignored as there's no caller to report it to.
+        let worker = self
+            .worker
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        _ = worker.conn_tx.send(Command::Shutdown(Box::new(|_| {})));
+    }
+}
+
+// A handle to the background thread that owns a single connection.
+#[derive(Clone)]
+struct Worker {
     conn_tx: Sender<Command>,
+    // Bounds the number of queued functions if `queue_capacity` is set, as
+    // crossbeam's bounded channels don't discard queued commands when the
+    // worker exits. Shutdown commands don't take a permit, so that closing
+    // or dropping a client never waits on a full queue.
+    permits: Option<Sender<()>>,
+    closed: Arc<AtomicBool>,
+    // The handle to interrupt the connection with, which is `None` for a
+    // pool's shared queue as it isn't tied to a single connection.
+    interrupt: Option<Arc<InterruptHandle>>,
+    senders: Arc<Senders>,
 }
 
-impl Client {
+// The receiving end of a queue shared by the workers of a pool with
+// `PoolBuilder::shared_queue`, from which whichever worker is free first runs
+// the next call.
+#[derive(Clone)]
+pub(crate) struct SharedQueue(Receiver<Command>);
+
+// Async senders waiting for space in a worker's bounded queue.
+#[derive(Default)]
+struct Senders(Mutex<Vec<Waker>>);
+
+impl Senders {
+    fn register(&self, waker: &Waker) {
+        let mut wakers = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+
+    fn wake_all(&self) {
+        let wakers = mem::take(&mut *self.0.lock().unwrap_or_else(PoisonError::into_inner));
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+// Marks the worker as closed when its thread exits, including by panicking,
+// and wakes any waiting senders so that they observe the disconnect.
+struct ClosedGuard(Arc<AtomicBool>, Arc<Senders>);
+
+impl Drop for ClosedGuard {
+    fn drop(&mut self) {
+        self.0.store(true, Release);
+        self.1.wake_all();
+    }
+}
+
+impl Worker {
+    // Sends a command, blocking the current thread while the queue is full.
+    fn send(&self, cmd: Command) -> Result<(), SendError<Command>> {
+        if let (Some(permits), Command::Func(_)) = (&self.permits, &cmd) {
+            if permits.send(()).is_err() {
+                return Err(SendError(cmd));
+            }
+        }
+        self.conn_tx.send(cmd)
+    }
+
+    fn try_send(&self, cmd: Command) -> Result<(), TrySendError<Command>> {
+        if let (Some(permits), Command::Func(_)) = (&self.permits, &cmd) {
+            match permits.try_send(()) {
+                Ok(()) => {}
+                Err(TrySendError::Full(())) => return Err(TrySendError::Full(cmd)),
+                Err(TrySendError::Disconnected(())) => {
+                    return Err(TrySendError::Disconnected(cmd));
+                }
+            }
+        }
+        self.conn_tx.try_send(cmd)
+    }
+
     async fn open_async(builder: ClientBuilder) -> Result<Self, Error> {
+        let timeout = builder.open_timeout;
         let (open_tx, open_rx) = oneshot::channel();
-        Self::open(builder, |res| {
+        Self::spawn(builder, |res| {
             _ = open_tx.send(res);
         });
-        open_rx.await?
+        let Some(timeout) = timeout else {
+            return open_rx.await?;
+        };
+        // If the timeout fires first, the worker is dropped once the
+        // connection is eventually opened, which closes it.
+        match select(open_rx, Box::pin(sleep(timeout))).await {
+            Either::Left((res, _)) => res?,
+            Either::Right(_) => Err(Error::Timeout),
+        }
     }
 
     fn open_blocking(builder: ClientBuilder) -> Result<Self, Error> {
+        let timeout = builder.open_timeout;
         let (conn_tx, conn_rx) = bounded(1);
-        Self::open(builder, move |res| {
+        Self::spawn(builder, move |res| {
             _ = conn_tx.send(res);
         });
-        conn_rx.recv()?
+        let Some(timeout) = timeout else {
+            return conn_rx.recv()?;
+        };
+        match conn_rx.recv_timeout(timeout) {
+            Ok(res) => res,
+            Err(RecvTimeoutError::Timeout) => Err(Error::Timeout),
+            Err(RecvTimeoutError::Disconnected) => Err(Error::Closed),
+        }
     }
 
-    fn open<F>(builder: ClientBuilder, func: F)
+    fn spawn<F>(builder: ClientBuilder, func: F)
     where
         F: FnOnce(Result<Self, Error>) + Send + 'static,
     {
-        thread::spawn(move || {
+        let mut thread = thread::Builder::new();
+        if let Some(name) = builder.thread_name.clone() {
+            thread = thread.name(name);
+        }
+        if let Some(size) = builder.thread_stack_size {
+            thread = thread.stack_size(size);
+        }
+        // Like `thread::spawn`, this only fails if the OS can't create a
+        // thread.
+        let spawned = thread.spawn(move || {
+            // The guard is dropped after the receiver, so the worker only
+            // reports itself as closed once sending to it fails.
+            let closed = Arc::new(AtomicBool::new(false));
+            let senders = Arc::new(Senders::default());
+            let _guard = ClosedGuard(closed.clone(), senders.clone());
             let (conn_tx, conn_rx) = unbounded();
+            let (permits, permits_rx) = builder
+                .queue_capacity
+                .map(|capacity| bounded(capacity.max(1)))
+                .unzip();
+            let on_close = builder.on_close.clone();
+            let shared = builder.shared_queue.clone();
 
-            let mut conn = match Client::create_conn(builder) {
+            let conn = match Client::create_conn_with_retry(builder) {
                 Ok(conn) => conn,
                 Err(err) => {
                     func(Err(err));
@@ -148,14 +925,95 @@ impl Client {
                 }
             };
 
-            let client = Self { conn_tx };
-            func(Ok(client));
+            func(Ok(Self {
+                conn_tx,
+                permits,
+                closed: closed.clone(),
+                interrupt: Some(Arc::new(conn.get_interrupt_handle())),
+                senders: senders.clone(),
+            }));
+            Self::run(
+                conn, conn_rx, shared, permits_rx, on_close, &closed, &senders,
+            );
+        });
+        spawned.expect("failed to spawn worker thread");
+    }
+
+    fn spawn_with_conn(conn: Connection) -> Self {
+        let closed = Arc::new(AtomicBool::new(false));
+        let senders = Arc::new(Senders::default());
+        let (conn_tx, conn_rx) = unbounded();
+        let worker = Self {
+            conn_tx,
+            permits: None,
+            closed: closed.clone(),
+            interrupt: Some(Arc::new(conn.get_interrupt_handle())),
+            senders: senders.clone(),
+        };
+        thread::spawn(move || {
+            let _guard = ClosedGuard(closed.clone(), senders.clone());
+            Self::run(conn, conn_rx, None, None, None, &closed, &senders);
+        });
+        worker
+    }
 
-            while let Ok(cmd) = conn_rx.recv() {
-                match cmd {
-                    Command::Func(func) => func(&mut conn),
-                    Command::Shutdown(func) => match conn.close() {
+    fn run(
+        mut conn: Connection,
+        conn_rx: Receiver<Command>,
+        shared: Option<SharedQueue>,
+        permits: Option<Receiver<()>>,
+        mut on_close: Option<OnClose>,
+        closed: &AtomicBool,
+        senders: &Senders,
+    ) {
+        CURRENT_WORKER.with(|id| id.set(closed as *const AtomicBool as usize));
+        let mut select = Select::new();
+        select.recv(&conn_rx);
+        if let Some(SharedQueue(shared_rx)) = &shared {
+            select.recv(shared_rx);
+        }
+        loop {
+            let op = select.select();
+            let index = op.index();
+            let cmd = match (index, &shared) {
+                (0, _) => match op.recv(&conn_rx) {
+                    Ok(cmd) => cmd,
+                    Err(_) => return,
+                },
+                (_, Some(SharedQueue(shared_rx))) => {
+                    match op.recv(shared_rx) {
+                        // The pool closes each worker through its own queue,
+                        // so a shutdown sent through the shared queue, as when
+                        // it's dropped, is ignored.
+                        Ok(Command::Func(func)) => Self::call(&mut conn, func),
+                        Ok(Command::Shutdown(_)) => {}
+                        Err(_) => select.remove(index),
+                    }
+                    continue;
+                }
+                (_, None) => unreachable!("selected a queue that wasn't registered"),
+            };
+            if let (Some(permits), Command::Func(_)) = (&permits, &cmd) {
+                // Receiving the function makes space in the queue.
+                _ = permits.try_recv();
+                senders.wake_all();
+            }
+            match cmd {
+                Command::Func(func) => Self::call(&mut conn, func),
+                Command::Shutdown(func) => {
+                    // Calls already in the shared queue are run first, as the
+                    // other workers may have shut down already.
+                    if let Some(SharedQueue(shared_rx)) = &shared {
+                        while let Ok(Command::Func(func)) = shared_rx.try_recv() {
+                            Self::call(&mut conn, func);
+                        }
+                    }
+                    if let Some(on_close) = on_close.take() {
+                        _ = panic::catch_unwind(AssertUnwindSafe(|| on_close(&mut conn)));
+                    }
+                    match conn.close() {
                         Ok(()) => {
+                            closed.store(true, Release);
                             func(Ok(()));
                             return;
                         }
@@ -163,141 +1021,1525 @@ impl Client {
                             conn = c;
                             func(Err(e.into()));
                         }
-                    },
+                    }
                 }
             }
-        });
+        }
+    }
+
+    // Runs a function received from either queue on the connection.
+    fn call(conn: &mut Connection, func: Box<dyn FnOnce(&mut Connection) + Send>) {
+        #[cfg(feature = "hooks")]
+        CALL_START.with(|start| start.set(Some(Instant::now())));
+        // Panics in provided functions are reported to callers as
+        // `Error::Panic`; this keeps the worker alive for any other panic.
+        _ = panic::catch_unwind(AssertUnwindSafe(|| func(conn)));
+        #[cfg(feature = "hooks")]
+        CALL_START.with(|start| start.set(None));
+    }
+}
+
+impl Client {
+    async fn open_async(builder: ClientBuilder) -> Result<Self, Error> {
+        let path = builder.path.clone();
+        let reconnect = reconnect_builder(&builder);
+        let worker = Worker::open_async(builder).await?;
+        Ok(Self {
+            state: Arc::new(State::new(worker, path, reconnect)),
+        })
+    }
+
+    fn open_blocking(builder: ClientBuilder) -> Result<Self, Error> {
+        let path = builder.path.clone();
+        let reconnect = reconnect_builder(&builder);
+        let worker = Worker::open_blocking(builder)?;
+        Ok(Self {
+            state: Arc::new(State::new(worker, path, reconnect)),
+        })
+    }
+
+    // Returns a client that sends calls to a queue shared by several workers,
+    // along with the queue for the workers to receive them from. Sending
+    // fails with `Error::Closed` once every receiving end has been dropped.
+    pub(crate) fn shared_queue() -> (Self, SharedQueue) {
+        let (conn_tx, conn_rx) = unbounded();
+        let worker = Worker {
+            conn_tx,
+            permits: None,
+            closed: Arc::default(),
+            interrupt: None,
+            senders: Arc::default(),
+        };
+        let client = Self {
+            state: Arc::new(State::new(worker, None, None)),
+        };
+        (client, SharedQueue(conn_rx))
+    }
+
+    fn create_conn_with_retry(builder: ClientBuilder) -> Result<Connection, Error> {
+        let Some((attempts, mut delay)) = builder.open_retry else {
+            return Client::create_conn(builder);
+        };
+        let mut attempt = 1;
+        loop {
+            match Client::create_conn(builder.clone()) {
+                Err(err) if err.is_busy() && attempt < attempts => {
+                    thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
     }
 
     fn create_conn(mut builder: ClientBuilder) -> Result<Connection, Error> {
         let path = builder.path.take().unwrap_or_else(|| ":memory:".into());
-        let conn = if let Some(vfs) = builder.vfs.take() {
+        // The connection never leaves its worker thread, so sqlite's mutexes
+        // aren't needed unless explicitly requested.
+        if !builder.flags.contains(OpenFlags::SQLITE_OPEN_FULL_MUTEX) {
+            builder.flags.insert(OpenFlags::SQLITE_OPEN_NO_MUTEX);
+        }
+        let mut conn = if let Some(vfs) = builder.vfs.take() {
             Connection::open_with_flags_and_vfs(path, builder.flags, &vfs)?
         } else {
             Connection::open_with_flags(path, builder.flags)?
         };
 
+        if let Some(busy_handler) = builder.busy_handler.take() {
+            BUSY_HANDLER.with(|cell| *cell.borrow_mut() = Some(busy_handler));
+            conn.busy_handler(Some(busy_callback))?;
+        }
+        #[cfg(feature = "hooks")]
+        if let Some(timeout) = builder.statement_timeout {
+            // Statements run while opening the connection aren't budgeted.
+            conn.progress_handler(
+                STATEMENT_TIMEOUT_OPS,
+                Some(move || {
+                    CALL_START
+                        .with(Cell::get)
+                        .is_some_and(|start| start.elapsed() > timeout)
+                }),
+            );
+        }
+        #[cfg(feature = "trace")]
+        if let Some(profile) = builder.profile.take() {
+            PROFILE.with(|cell| *cell.borrow_mut() = Some(profile));
+            conn.profile(Some(profile_callback));
+        }
+        #[cfg(feature = "trace")]
+        if let Some(trace) = builder.trace.take() {
+            TRACE.with(|cell| *cell.borrow_mut() = Some(trace));
+            conn.trace(Some(trace_callback));
+        }
+
+        for (config, enabled) in builder.db_configs.drain(..) {
+            conn.set_db_config(config, enabled)?;
+        }
+
+        for (path, alias) in builder.attachments.drain(..) {
+            let path = path.to_string_lossy().into_owned();
+            conn.execute("ATTACH DATABASE ?1 AS ?2", (path, alias))?;
+        }
+
+        let verify = !builder.skip_pragma_verification;
+        if let Some(page_size) = builder.page_size {
+            conn.pragma_update(None, "page_size", page_size)?;
+            let out: u32 = conn.pragma_query_value(None, "page_size", |row| row.get(0))?;
+            if verify && out != page_size {
+                return Err(Error::PragmaUpdate {
+                    name: "page_size".into(),
+                    exp: page_size.to_string().into(),
+                    got: out.to_string(),
+                });
+            }
+        }
+
         if let Some(journal_mode) = builder.journal_mode.take() {
             let val = journal_mode.as_str();
             let out: String =
                 conn.pragma_update_and_check(None, "journal_mode", val, |row| row.get(0))?;
-            if !out.eq_ignore_ascii_case(val) {
+            if verify && !out.eq_ignore_ascii_case(val) {
                 return Err(Error::PragmaUpdate {
-                    name: "journal_mode",
-                    exp: val,
+                    name: "journal_mode".into(),
+                    exp: val.into(),
                     got: out,
                 });
             }
         }
 
-        Ok(conn)
+        if let Some(synchronous) = builder.synchronous.take() {
+            conn.pragma_update(None, "synchronous", synchronous.as_str())?;
+            let out: i64 = conn.pragma_query_value(None, "synchronous", |row| row.get(0))?;
+            if verify && out != synchronous as i64 {
+                return Err(Error::PragmaUpdate {
+                    name: "synchronous".into(),
+                    exp: synchronous.as_str().into(),
+                    got: out.to_string(),
+                });
+            }
+        }
+
+        if let Some(temp_store) = builder.temp_store {
+            conn.pragma_update(None, "temp_store", temp_store.as_str())?;
+            let out: i64 = conn.pragma_query_value(None, "temp_store", |row| row.get(0))?;
+            if verify && out != temp_store as i64 {
+                return Err(Error::PragmaUpdate {
+                    name: "temp_store".into(),
+                    exp: temp_store.as_str().into(),
+                    got: out.to_string(),
+                });
+            }
+        }
+
+        if let Some(cache_size) = builder.cache_size {
+            conn.pragma_update(None, "cache_size", cache_size)?;
+            let out: i64 = conn.pragma_query_value(None, "cache_size", |row| row.get(0))?;
+            if verify && out != cache_size {
+                return Err(Error::PragmaUpdate {
+                    name: "cache_size".into(),
+                    exp: cache_size.to_string().into(),
+                    got: out.to_string(),
+                });
+            }
+        }
+
+        if let Some(mmap_size) = builder.mmap_size {
+            let out: i64 =
+                conn.pragma_update_and_check(None, "mmap_size", mmap_size, |row| row.get(0))?;
+            if verify && mmap_size == 0 && out != 0 {
+                return Err(Error::PragmaUpdate {
+                    name: "mmap_size".into(),
+                    exp: mmap_size.to_string().into(),
+                    got: out.to_string(),
+                });
+            }
+        }
+
+        if let Some(foreign_keys) = builder.foreign_keys {
+            conn.pragma_update(None, "foreign_keys", foreign_keys)?;
+            let out: bool = conn.pragma_query_value(None, "foreign_keys", |row| row.get(0))?;
+            if verify && out != foreign_keys {
+                return Err(Error::PragmaUpdate {
+                    name: "foreign_keys".into(),
+                    exp: if foreign_keys { "true" } else { "false" }.into(),
+                    got: out.to_string(),
+                });
+            }
+        }
+
+        if let Some(limit) = builder.soft_heap_limit {
+            let out: i64 =
+                conn.pragma_update_and_check(None, "soft_heap_limit", limit, |row| row.get(0))?;
+            if verify && out != limit {
+                return Err(Error::PragmaUpdate {
+                    name: "soft_heap_limit".into(),
+                    exp: limit.to_string().into(),
+                    got: out.to_string(),
+                });
+            }
+        }
+
+        if let Some(capacity) = builder.statement_cache_capacity {
+            conn.set_prepared_statement_cache_capacity(capacity);
+        }
+
+        for (name, value) in builder.pragmas.drain(..) {
+            let out = conn
+                .prepare(&format!("PRAGMA {name} = {value}"))?
+                .query_row((), |row| pragma_value_to_string(row.get_ref(0)?))
+                .optional()?;
+            if let Some(out) = out {
+                if verify && !pragma_value_matches(&value, &out) {
+                    return Err(Error::PragmaUpdate {
+                        name: name.into(),
+                        exp: value.into(),
+                        got: out,
+                    });
+                }
+            }
+        }
+
+        #[cfg(feature = "load_extension")]
+        if !builder.extensions.is_empty() {
+            // SAFETY: extension loading is only enabled for the duration of
+            // the guard, and the extensions are explicitly configured by the
+            // user.
+            unsafe {
+                let _guard = rusqlite::LoadExtensionGuard::new(&conn)?;
+                for (path, entry_point) in builder.extensions.drain(..) {
+                    conn.load_extension(path, entry_point.as_deref())?;
+                }
+            }
+        }
+
+        if let Some(on_open) = builder.on_open.take() {
+            on_open(&mut conn)?;
+        }
+
+        Ok(conn)
+    }
+
+    /// Returns a new [`Client`] that wraps an already open
+    /// [`rusqlite::Connection`].
+    ///
+    /// The connection is moved onto a new worker thread, so no configuration
+    /// from a [`ClientBuilder`] is applied to it. This can be used to set up
+    /// a connection with options the builder doesn't expose. The connection
+    /// must not be used elsewhere afterward, e.g. through raw handles.
+    ///
+    /// The [`path`](Client::path) is the file name of the connection's main
+    /// database, if any.
+    pub fn from_conn(conn: Connection) -> Self {
+        let path = conn
+            .path()
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from);
+        Self {
+            state: Arc::new(State::new(Worker::spawn_with_conn(conn), path, None)),
+        }
+    }
+
+    /// Returns the configured path of the database, or `None` if an
+    /// in-memory database was opened by default.
+    pub fn path(&self) -> Option<&Path> {
+        self.state.path.as_deref()
+    }
+
+    /// Invokes the provided function with a [`rusqlite::Connection`].
+    ///
+    /// If the returned future is dropped before the function has completed,
+    /// such as when it loses a race in a `select`, the function is skipped if
+    /// it hasn't started yet, or otherwise the connection is
+    /// [interrupted](rusqlite::InterruptHandle::interrupt) so that any running
+    /// statement stops promptly. Work that was already committed isn't undone.
+    pub async fn conn<F, T>(&self, func: F) -> Result<T, Error>
+    where
+        F: FnOnce(&Connection) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let func = traced(move |conn: &mut Connection| func(conn));
+        let (tx, rx) = oneshot::channel();
+        let _call = self.send_interruptible(func, tx).await?;
+        Ok(rx.await???)
+    }
+
+    /// Invokes the provided function with a mutable [`rusqlite::Connection`].
+    pub async fn conn_mut<F, T>(&self, func: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Connection) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let func = traced(func);
+        let (tx, rx) = oneshot::channel();
+        let _call = self.send_interruptible(func, tx).await?;
+        Ok(rx.await???)
+    }
+
+    /// Invokes the provided function with a [`rusqlite::Connection`],
+    /// retrying up to `retries` times if it fails because the database is busy
+    /// or locked.
+    ///
+    /// Retries use an exponential backoff starting at 10ms. The backoff waits
+    /// without occupying the worker thread, so other calls on this client can
+    /// run in between attempts.
+    pub async fn conn_retry<F, T>(&self, retries: usize, func: F) -> Result<T, Error>
+    where
+        F: Fn(&Connection) -> Result<T, rusqlite::Error> + Send + Sync + 'static,
+        T: Send + 'static,
+    {
+        let func = Arc::new(func);
+        let mut delay = Duration::from_millis(10);
+        let mut attempt = 0;
+        loop {
+            let func = func.clone();
+            match self.conn(move |conn| func(conn)).await {
+                Err(err) if (err.is_busy() || err.is_locked()) && attempt < retries => {
+                    sleep(delay).await;
+                    delay = delay.saturating_mul(2);
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// Invokes the provided function within a transaction.
+    ///
+    /// The transaction is committed if the function returns `Ok`, and rolled
+    /// back if it returns `Err`. It is started with `BEGIN IMMEDIATE`, so that
+    /// concurrent writers wait on the busy timeout instead of failing when
+    /// upgrading from a read to a write lock.
+    pub async fn transaction<F, T>(&self, func: F) -> Result<T, Error>
+    where
+        F: FnOnce(&Transaction<'_>) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.conn_mut(move |conn| transaction(conn, func)).await
+    }
+
+    /// Invokes the provided function within a savepoint with the provided
+    /// `name`.
+    ///
+    /// The savepoint is released if the function returns `Ok`, and rolled
+    /// back if it returns `Err`. Savepoints can be nested, and can be used
+    /// within an outer transaction, in which case rolling back the savepoint
+    /// leaves the rest of the outer transaction intact.
+    pub async fn savepoint<F, T>(&self, name: &str, func: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Savepoint<'_>) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let name = name.to_owned();
+        self.conn_mut(move |conn| {
+            let mut sp = conn.savepoint_with_name(name)?;
+            // Dropping the savepoint on error rolls it back.
+            let res = func(&mut sp)?;
+            sp.commit()?;
+            Ok(res)
+        })
+        .await
+    }
+
+    /// Applies every migration in `migrations` newer than the database's
+    /// [`user_version`](https://www.sqlite.org/pragma.html#pragma_user_version),
+    /// returning the resulting version.
+    ///
+    /// All pending migrations are applied within a single transaction, along
+    /// with the `user_version` update. If any migration fails, the transaction
+    /// is rolled back and `user_version` is left unchanged.
+    pub async fn migrate(&self, migrations: &Migrations) -> Result<u32, Error> {
+        let migrations = migrations.clone();
+        self.transaction(move |tx| migrations.apply(tx)).await
+    }
+
+    /// Invokes the provided function with a [`rusqlite::Connection`].
+    ///
+    /// Maps the result error type to a custom error; designed to be
+    /// used in conjunction with [`query_and_then`](https://docs.rs/rusqlite/latest/rusqlite/struct.CachedStatement.html#method.query_and_then).
+    pub async fn conn_and_then<F, T, E>(&self, func: F) -> Result<T, E>
+    where
+        F: FnOnce(&Connection) -> Result<T, E> + Send + 'static,
+        T: Send + 'static,
+        E: From<rusqlite::Error> + From<Error> + Send + 'static,
+    {
+        let func = traced(move |conn: &mut Connection| func(conn));
+        let (tx, rx) = oneshot::channel();
+        let _call = self.send_interruptible(func, tx).await?;
+        rx.await.map_err(Error::from)??
+    }
+
+    /// Invokes the provided function with a mutable [`rusqlite::Connection`].
+    ///
+    /// Maps the result error type to a custom error; designed to be
+    /// used in conjunction with [`query_and_then`](https://docs.rs/rusqlite/latest/rusqlite/struct.CachedStatement.html#method.query_and_then).
+    pub async fn conn_mut_and_then<F, T, E>(&self, func: F) -> Result<T, E>
+    where
+        F: FnOnce(&mut Connection) -> Result<T, E> + Send + 'static,
+        T: Send + 'static,
+        E: From<rusqlite::Error> + From<Error> + Send + 'static,
+    {
+        let func = traced(func);
+        let (tx, rx) = oneshot::channel();
+        let _call = self.send_interruptible(func, tx).await?;
+        rx.await.map_err(Error::from)??
+    }
+
+    /// Registers a callback that is invoked on the worker thread whenever a
+    /// row is inserted, updated, or deleted in a rowid table.
+    ///
+    /// Passing `None` removes any previously registered hook. See the
+    /// [rusqlite docs](rusqlite::Connection::update_hook()) for details.
+    #[cfg(feature = "hooks")]
+    pub async fn update_hook<F>(&self, hook: Option<F>) -> Result<(), Error>
+    where
+        F: FnMut(rusqlite::hooks::Action, &str, &str, i64) + Send + 'static,
+    {
+        self.conn(move |conn| {
+            conn.update_hook(hook);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Registers a callback that is invoked on the worker thread whenever a
+    /// transaction is committed. Returning `true` from the callback turns the
+    /// commit into a rollback.
+    ///
+    /// Passing `None` removes any previously registered hook.
+    #[cfg(feature = "hooks")]
+    pub async fn commit_hook<F>(&self, hook: Option<F>) -> Result<(), Error>
+    where
+        F: FnMut() -> bool + Send + 'static,
+    {
+        self.conn(move |conn| {
+            conn.commit_hook(hook);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Registers a callback that is invoked on the worker thread whenever a
+    /// transaction is rolled back.
+    ///
+    /// Passing `None` removes any previously registered hook.
+    #[cfg(feature = "hooks")]
+    pub async fn rollback_hook<F>(&self, hook: Option<F>) -> Result<(), Error>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.conn(move |conn| {
+            conn.rollback_hook(hook);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Registers a callback that is invoked on the worker thread before each
+    /// row is inserted, updated, or deleted, with access to the old and new
+    /// column values through the [`PreUpdateCase`](rusqlite::hooks::PreUpdateCase).
+    ///
+    /// This requires the `preupdate_hook` feature, which generates the sqlite
+    /// bindings at build time and so needs `libclang` to be installed. A
+    /// system sqlite must also be compiled with `SQLITE_ENABLE_PREUPDATE_HOOK`.
+    /// Passing `None` removes any previously registered hook.
+    #[cfg(feature = "preupdate_hook")]
+    pub async fn preupdate_hook<F>(&self, hook: Option<F>) -> Result<(), Error>
+    where
+        F: FnMut(rusqlite::hooks::Action, &str, &str, &rusqlite::hooks::PreUpdateCase)
+            + Send
+            + 'static,
+    {
+        self.conn(move |conn| {
+            conn.preupdate_hook(hook);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Registers an [authorizer](https://www.sqlite.org/c3ref/set_authorizer.html)
+    /// that is invoked on the worker thread as each statement is prepared.
+    ///
+    /// The callback can allow, ignore, or deny each action, such as attaching
+    /// a database or writing to a table. Passing `None` removes any
+    /// previously registered authorizer.
+    #[cfg(feature = "hooks")]
+    pub async fn set_authorizer<F>(&self, authorizer: Option<F>) -> Result<(), Error>
+    where
+        F: for<'r> FnMut(rusqlite::hooks::AuthContext<'r>) -> rusqlite::hooks::Authorization
+            + Send
+            + 'static,
+    {
+        self.conn(move |conn| {
+            conn.authorizer(authorizer);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Registers a callback that is invoked on the worker thread roughly every
+    /// `num_ops` virtual machine instructions of a running statement.
+    /// Returning `true` from the callback interrupts the operation.
+    ///
+    /// The callback must not call back into this client, as the worker thread
+    /// is blocked until the callback returns and doing so would deadlock.
+    /// Passing `None`, or a `num_ops` less than 1, removes any previously
+    /// registered handler.
+    #[cfg(feature = "hooks")]
+    pub async fn set_progress_handler<F>(
+        &self,
+        num_ops: std::os::raw::c_int,
+        handler: Option<F>,
+    ) -> Result<(), Error>
+    where
+        F: FnMut() -> bool + Send + 'static,
+    {
+        self.conn(move |conn| {
+            conn.progress_handler(num_ops, handler);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Registers a custom aggregate function named `name` that takes `n_arg`
+    /// arguments, or any number of arguments if `n_arg` is -1.
+    ///
+    /// The aggregate runs on the worker thread. See the
+    /// [rusqlite docs](rusqlite::Connection::create_aggregate_function()) for
+    /// details.
+    #[cfg(feature = "functions")]
+    pub async fn create_aggregate_function<A, D, T>(
+        &self,
+        name: &str,
+        n_arg: std::os::raw::c_int,
+        flags: rusqlite::functions::FunctionFlags,
+        aggr: D,
+    ) -> Result<(), Error>
+    where
+        A: std::panic::RefUnwindSafe + std::panic::UnwindSafe,
+        D: rusqlite::functions::Aggregate<A, T> + Send + 'static,
+        T: rusqlite::functions::SqlFnOutput,
+    {
+        let name = name.to_owned();
+        self.conn(move |conn| conn.create_aggregate_function(&name, n_arg, flags, aggr))
+            .await
+    }
+
+    /// Registers a custom aggregate window function named `name` that takes
+    /// `n_arg` arguments, or any number of arguments if `n_arg` is -1.
+    ///
+    /// The aggregate runs on the worker thread. See the
+    /// [rusqlite docs](rusqlite::Connection::create_window_function()) for
+    /// details.
+    #[cfg(feature = "window")]
+    pub async fn create_window_function<A, W, T>(
+        &self,
+        name: &str,
+        n_arg: std::os::raw::c_int,
+        flags: rusqlite::functions::FunctionFlags,
+        aggr: W,
+    ) -> Result<(), Error>
+    where
+        A: std::panic::RefUnwindSafe + std::panic::UnwindSafe,
+        W: rusqlite::functions::WindowAggregate<A, T> + Send + 'static,
+        T: rusqlite::functions::SqlFnOutput,
+    {
+        let name = name.to_owned();
+        self.conn(move |conn| conn.create_window_function(&name, n_arg, flags, aggr))
+            .await
+    }
+
+    /// Performs an online backup of the main database to the file at `dest`.
+    ///
+    /// The backup runs on the worker thread, so other calls on this client
+    /// will wait until it has completed.
+    #[cfg(feature = "backup")]
+    pub async fn backup_to<P: AsRef<Path>>(&self, dest: P) -> Result<(), Error> {
+        let dest = dest.as_ref().to_owned();
+        self.conn(move |conn| conn.backup(rusqlite::DatabaseName::Main, dest, None))
+            .await
+    }
+
+    /// Performs an online backup of the main database to the file at `dest`,
+    /// copying `pages_per_step` pages at a time and sleeping for `pause`
+    /// between each step.
+    ///
+    /// The `progress` callback is invoked on the worker thread after every
+    /// step with the current [`Progress`](rusqlite::backup::Progress).
+    #[cfg(feature = "backup")]
+    pub async fn backup_to_with_progress<P, F>(
+        &self,
+        dest: P,
+        pages_per_step: std::os::raw::c_int,
+        pause: Duration,
+        mut progress: F,
+    ) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+        F: FnMut(rusqlite::backup::Progress) + Send + 'static,
+    {
+        use rusqlite::backup::{Backup, StepResult};
+
+        let dest = dest.as_ref().to_owned();
+        self.conn(move |conn| {
+            let mut dst = Connection::open(dest)?;
+            let backup = Backup::new(conn, &mut dst)?;
+            loop {
+                let res = backup.step(pages_per_step)?;
+                progress(backup.progress());
+                match res {
+                    StepResult::Done => return Ok(()),
+                    _ => thread::sleep(pause),
+                }
+            }
+        })
+        .await
+    }
+
+    /// Overwrites the main database with the contents of the database file at
+    /// `src` using the online backup API.
+    ///
+    /// If `src` does not exist, an [`Error::Rusqlite`] is returned and the
+    /// current database is left untouched. When the database is in WAL mode,
+    /// the WAL is checkpointed and truncated once the restore completes.
+    #[cfg(feature = "backup")]
+    pub async fn restore_from<P: AsRef<Path>>(&self, src: P) -> Result<(), Error> {
+        use rusqlite::backup::Backup;
+
+        let src = src.as_ref().to_owned();
+        self.conn_mut(move |conn| {
+            let src = Connection::open_with_flags(src, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+            Backup::new(&src, conn)?.run_to_completion(100, Duration::from_millis(10), None)?;
+            conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", (), |_| Ok(()))
+        })
+        .await
+    }
+
+    /// Serializes the database named `schema`, or the main database if
+    /// `None`, into a byte buffer using
+    /// [`sqlite3_serialize`](https://www.sqlite.org/c3ref/serialize.html).
+    ///
+    /// The buffer contains the same bytes as the database file would on disk.
+    #[cfg(feature = "serialize")]
+    pub async fn serialize(&self, schema: Option<&str>) -> Result<Vec<u8>, Error> {
+        let schema = schema.map(str::to_owned);
+        self.conn(move |conn| {
+            let name = schema.as_deref().map_or(
+                rusqlite::DatabaseName::Main,
+                rusqlite::DatabaseName::Attached,
+            );
+            Ok(conn.serialize(name)?.to_vec())
+        })
+        .await
+    }
+
+    /// Replaces the database named `schema`, or the main database if `None`,
+    /// with the serialized database in `bytes` using
+    /// [`sqlite3_deserialize`](https://www.sqlite.org/c3ref/deserialize.html).
+    ///
+    /// The connection then operates on an in-memory copy of the data that can
+    /// be written to and grown. Deserializing fails with an
+    /// [`Error::Rusqlite`] if the connection has any statements in progress,
+    /// such as an open [`Client::query_stream`].
+    #[cfg(feature = "serialize")]
+    pub async fn deserialize(&self, schema: Option<&str>, bytes: Vec<u8>) -> Result<(), Error> {
+        use rusqlite::{ffi, serialize::OwnedData};
+        use std::ptr::NonNull;
+
+        let schema = schema.map(str::to_owned);
+        self.conn_mut(move |conn| {
+            let name = schema.as_deref().map_or(
+                rusqlite::DatabaseName::Main,
+                rusqlite::DatabaseName::Attached,
+            );
+            // sqlite takes ownership of the buffer, so it must be allocated by
+            // sqlite.
+            let len = bytes.len();
+            // SAFETY: the allocation is at least `len` bytes, and is only
+            // handed to `OwnedData` after it has been fully initialized.
+            let data = unsafe {
+                let ptr = NonNull::new(ffi::sqlite3_malloc64(len.max(1) as u64).cast::<u8>())
+                    .ok_or_else(|| {
+                        rusqlite::Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_NOMEM), None)
+                    })?;
+                ptr.as_ptr().copy_from_nonoverlapping(bytes.as_ptr(), len);
+                OwnedData::from_raw_nonnull(ptr, len)
+            };
+            conn.deserialize(name, data, false)
+        })
+        .await
+    }
+
+    /// Opens the blob at `rowid` in `db.table.column` for incremental reading,
+    /// returning a stream of chunks of up to `chunk_size` bytes.
+    ///
+    /// The blob is opened read-only if `read_only` is true, and read-write
+    /// otherwise, as with [`Connection::blob_open`]. Chunks are read on the
+    /// worker thread one at a time as the stream is polled. While the stream is alive, the connection is dedicated to it and
+    /// all other calls on this client wait until it is exhausted or dropped.
+    #[cfg(feature = "blob")]
+    pub async fn blob_read(
+        &self,
+        db: &str,
+        table: &str,
+        column: &str,
+        rowid: i64,
+        read_only: bool,
+        chunk_size: usize,
+    ) -> Result<BoxStream<'static, Result<Vec<u8>, Error>>, Error> {
+        type Reply = oneshot::Sender<Result<Option<Vec<u8>>, rusqlite::Error>>;
+
+        let (db, table, column) = (db.to_owned(), table.to_owned(), column.to_owned());
+        let chunk_size = chunk_size.max(1);
+        let (open_tx, open_rx) = oneshot::channel();
+        let (req_tx, req_rx) = unbounded::<Reply>();
+        self.state
+            .send_async(Command::Func(Box::new(move |conn| {
+                let name = rusqlite::DatabaseName::Attached(&db);
+                let blob = match conn.blob_open(name, &table, &column, rowid, read_only) {
+                    Ok(blob) => blob,
+                    Err(err) => {
+                        _ = open_tx.send(Err(err));
+                        return;
+                    }
+                };
+                _ = open_tx.send(Ok(()));
+                let mut offset = 0;
+                while let Ok(reply) = req_rx.recv() {
+                    let res = if offset >= blob.len() {
+                        Ok(None)
+                    } else {
+                        let mut buf = vec![0; chunk_size.min(blob.len() - offset)];
+                        blob.read_at_exact(&mut buf, offset).map(|()| {
+                            offset += buf.len();
+                            Some(buf)
+                        })
+                    };
+                    let done = !matches!(res, Ok(Some(_)));
+                    _ = reply.send(res);
+                    if done {
+                        return;
+                    }
+                }
+            })))
+            .await?;
+        open_rx.await??;
+
+        Ok(stream::unfold(Some(req_tx), |req_tx| async move {
+            let req_tx = req_tx?;
+            let (tx, rx) = oneshot::channel();
+            if req_tx.send(tx).is_err() {
+                return Some((Err(Error::Closed), None));
+            }
+            match rx.await {
+                Ok(Ok(Some(chunk))) => Some((Ok(chunk), Some(req_tx))),
+                Ok(Ok(None)) => None,
+                Ok(Err(err)) => Some((Err(err.into()), None)),
+                Err(err) => Some((Err(err.into()), None)),
+            }
+        })
+        .boxed())
+    }
+
+    /// Opens the blob at `rowid` in `db.table.column` for incremental
+    /// writing, and writes each chunk produced by `chunks` in order starting
+    /// at the beginning of the blob.
+    ///
+    /// The iterator is consumed on the worker thread. Incremental I/O can't
+    /// change the size of a blob, so the row must already contain a blob large
+    /// enough to hold every chunk, e.g. one created with `zeroblob(N)`.
+    #[cfg(feature = "blob")]
+    pub async fn blob_write<I>(
+        &self,
+        db: &str,
+        table: &str,
+        column: &str,
+        rowid: i64,
+        chunks: I,
+    ) -> Result<(), Error>
+    where
+        I: IntoIterator + Send + 'static,
+        I::Item: AsRef<[u8]>,
+    {
+        let (db, table, column) = (db.to_owned(), table.to_owned(), column.to_owned());
+        self.conn(move |conn| {
+            let name = rusqlite::DatabaseName::Attached(&db);
+            let mut blob = conn.blob_open(name, &table, &column, rowid, false)?;
+            let mut offset = 0;
+            for chunk in chunks {
+                let chunk = chunk.as_ref();
+                blob.write_at(chunk, offset)?;
+                offset += chunk.len();
+            }
+            blob.close()
+        })
+        .await
+    }
+
+    /// Finalizes all prepared statements in the connection's statement cache.
+    ///
+    /// This is useful after schema changes, such as running migrations, to
+    /// release statements that were prepared against the old schema.
+    pub async fn clear_cache(&self) -> Result<(), Error> {
+        self.conn(|conn| {
+            conn.flush_prepared_statement_cache();
+            Ok(())
+        })
+        .await
+    }
+
+    /// Runs a [WAL checkpoint](https://www.sqlite.org/pragma.html#pragma_wal_checkpoint)
+    /// using the provided [`CheckpointMode`].
+    ///
+    /// If the database isn't in WAL mode, this is a no-op and both frame counts
+    /// in the returned [`CheckpointResult`] are `-1`.
+    pub async fn checkpoint(&self, mode: CheckpointMode) -> Result<CheckpointResult, Error> {
+        self.conn(move |conn| checkpoint(conn, mode)).await
+    }
+
+    /// Rebuilds the database file with [`VACUUM`](https://www.sqlite.org/lang_vacuum.html),
+    /// repacking it into a minimal amount of disk space.
+    ///
+    /// `VACUUM` fails if a transaction is active on the connection. In WAL
+    /// mode, the rebuilt content is written to the WAL, so the main database
+    /// file only shrinks once a [`checkpoint`](Client::checkpoint) has run.
+    pub async fn vacuum(&self) -> Result<(), Error> {
+        self.conn(|conn| conn.execute_batch("VACUUM")).await
+    }
+
+    /// Writes a vacuumed copy of the main database to a new file at `dest`
+    /// using [`VACUUM INTO`](https://www.sqlite.org/lang_vacuum.html#vacuuminto).
+    ///
+    /// The current database is left untouched, and `dest` must not already
+    /// exist as a non-empty database.
+    pub async fn vacuum_into<P: AsRef<Path>>(&self, dest: P) -> Result<(), Error> {
+        let dest = dest.as_ref().to_string_lossy().into_owned();
+        self.conn(move |conn| conn.execute("VACUUM INTO ?1", [dest]).map(|_| ()))
+            .await
+    }
+
+    /// Executes the provided statement, returning the number of rows that
+    /// were changed.
+    ///
+    /// This is a shorthand for calling [`rusqlite::Connection::execute`] from
+    /// [`Client::conn`]. As `params` are sent to the worker thread, they must
+    /// be `Send + 'static`, e.g. `[]`, an owned tuple, or a `'static` slice
+    /// of named parameters. For borrowed named parameters, such as the output
+    /// of [`rusqlite::named_params!`], use [`Client::execute_named`] or
+    /// [`Client::query_row_named`] instead.
+    pub async fn execute<P>(&self, sql: &str, params: P) -> Result<usize, Error>
+    where
+        P: Params + Send + 'static,
+    {
+        let sql = sql.to_owned();
+        self.conn(move |conn| conn.execute(&sql, params)).await
+    }
+
+    /// Executes the provided statement with named parameters, returning the
+    /// number of rows that were changed.
+    ///
+    /// Unlike [`Client::execute`], this accepts borrowed parameters such as
+    /// the output of [`rusqlite::named_params!`]. They are converted into
+    /// owned values when this method is called, so the returned future
+    /// doesn't borrow them and is `Send`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use async_sqlite::{rusqlite::named_params, Client};
+    /// # async fn run(client: Client) -> Result<(), async_sqlite::Error> {
+    /// client
+    ///     .execute_named(
+    ///         "INSERT INTO users (id, name) VALUES (:id, :name)",
+    ///         named_params! { ":id": 1, ":name": "one" },
+    ///     )
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn execute_named(
+        &self,
+        sql: &str,
+        params: &[(&str, &dyn ToSql)],
+    ) -> impl Future<Output = Result<usize, Error>> + Send + '_ {
+        let sql = sql.to_owned();
+        let params = NamedParams::new(params);
+        async move {
+            let params = params?;
+            self.conn(move |conn| conn.execute(&sql, params.as_params().as_slice()))
+                .await
+        }
+    }
+
+    /// Executes the provided statement with positional parameters built at
+    /// runtime, returning the number of rows that were changed.
+    ///
+    /// This is useful for query builders, where the number and types of the
+    /// parameters aren't known at compile time.
+    pub async fn execute_dyn(&self, sql: &str, params: Vec<Value>) -> Result<usize, Error> {
+        self.execute(sql, params_from_iter(params)).await
+    }
+
+    /// Executes the provided statement, returning the rowid of the most
+    /// recently inserted row.
+    ///
+    /// The statement and the call to
+    /// [`last_insert_rowid`](rusqlite::Connection::last_insert_rowid) run
+    /// within the same call on the worker thread, so no other request can
+    /// change the rowid in between.
+    pub async fn execute_returning_rowid<P>(&self, sql: &str, params: P) -> Result<i64, Error>
+    where
+        P: Params + Send + 'static,
+    {
+        let sql = sql.to_owned();
+        self.conn(move |conn| {
+            conn.execute(&sql, params)?;
+            Ok(conn.last_insert_rowid())
+        })
+        .await
+    }
+
+    /// Executes the provided SQL script, which may contain multiple
+    /// semicolon-separated statements.
+    ///
+    /// This is a shorthand for calling [`rusqlite::Connection::execute_batch`]
+    /// from [`Client::conn`], so the whole script runs on this client's
+    /// connection. Execution stops at the first failing statement.
+    pub async fn execute_batch(&self, sql: &str) -> Result<(), Error> {
+        let sql = sql.to_owned();
+        self.conn(move |conn| conn.execute_batch(&sql)).await
+    }
+
+    /// Executes the provided SQL script, returning the total number of rows
+    /// that were changed by all of its statements.
+    ///
+    /// The changes are counted using
+    /// [`total_changes`](rusqlite::Connection::total_changes) within the same
+    /// call on the worker thread, so no other request can interleave.
+    pub async fn execute_batch_returning_changes(&self, sql: &str) -> Result<u64, Error> {
+        let sql = sql.to_owned();
+        self.conn(move |conn| {
+            let before = conn.total_changes();
+            conn.execute_batch(&sql)?;
+            Ok(conn.total_changes() - before)
+        })
+        .await
+    }
+
+    /// Executes the provided statement once for each set of parameters in
+    /// `rows`, returning the total number of rows that were changed.
+    ///
+    /// The statement is prepared once using
+    /// [`prepare_cached`](rusqlite::Connection::prepare_cached), and all
+    /// executions happen within a single transaction. If any execution fails,
+    /// the transaction is rolled back and the error is returned.
+    pub async fn execute_batch_params<P, I>(&self, sql: &str, rows: I) -> Result<usize, Error>
+    where
+        P: Params,
+        I: IntoIterator<Item = P> + Send + 'static,
+    {
+        let sql = sql.to_owned();
+        self.conn_mut(move |conn| {
+            let tx = conn.transaction()?;
+            let mut changed = 0;
+            {
+                let mut stmt = tx.prepare_cached(&sql)?;
+                for params in rows {
+                    changed += stmt.execute(params)?;
+                }
+            }
+            tx.commit()?;
+            Ok(changed)
+        })
+        .await
+    }
+
+    /// Inserts `rows` into the provided `table` and `columns` using multi-row
+    /// `INSERT ... VALUES (..), (..)` statements of up to `chunk_size` rows
+    /// each, returning the total number of rows inserted.
+    ///
+    /// The table and column names are quoted as identifiers. The chunk size
+    /// is reduced if needed to respect sqlite's limit on the number of bound
+    /// variables in a statement. All chunks are inserted within a single
+    /// transaction, which is rolled back if any chunk fails or a row doesn't
+    /// contain exactly one value per column.
+    pub async fn bulk_insert<I>(
+        &self,
+        table: &str,
+        columns: &[&str],
+        rows: I,
+        chunk_size: usize,
+    ) -> Result<usize, Error>
+    where
+        I: IntoIterator<Item = Vec<Value>> + Send + 'static,
+    {
+        if columns.is_empty() {
+            return Err(Error::Rusqlite(rusqlite::Error::InvalidQuery));
+        }
+        let table = quote_identifier(table);
+        let columns: Vec<String> = columns.iter().map(|c| quote_identifier(c)).collect();
+        self.conn_mut(move |conn| {
+            let max_rows = (max_variables(conn) / columns.len()).max(1);
+            let chunk_size = chunk_size.clamp(1, max_rows);
+            let tx = conn.transaction()?;
+            let mut inserted = 0;
+            let mut rows = rows.into_iter();
+            let mut values = Vec::with_capacity(chunk_size * columns.len());
+            loop {
+                let mut num_rows = 0;
+                for row in rows.by_ref().take(chunk_size) {
+                    if row.len() != columns.len() {
+                        return Err(rusqlite::Error::InvalidParameterCount(
+                            row.len(),
+                            columns.len(),
+                        ));
+                    }
+                    values.extend(row);
+                    num_rows += 1;
+                }
+                if num_rows == 0 {
+                    break;
+                }
+                let sql = bulk_insert_sql(&table, &columns, num_rows);
+                let mut stmt = tx.prepare_cached(&sql)?;
+                inserted += stmt.execute(params_from_iter(values.drain(..)))?;
+                if num_rows < chunk_size {
+                    break;
+                }
+            }
+            tx.commit()?;
+            Ok(inserted)
+        })
+        .await
+    }
+
+    /// Executes the provided query and invokes `f` on the first returned row.
+    ///
+    /// This is a shorthand for calling
+    /// [`rusqlite::Connection::query_row`] from [`Client::conn`]. If the query
+    /// returns no rows, an [`Error::Rusqlite`] containing
+    /// [`rusqlite::Error::QueryReturnedNoRows`] is returned.
+    ///
+    /// See [`Client::execute`] for the requirements on `params`.
+    pub async fn query_row<T, P, F>(&self, sql: &str, params: P, f: F) -> Result<T, Error>
+    where
+        P: Params + Send + 'static,
+        F: FnOnce(&Row<'_>) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let sql = sql.to_owned();
+        self.conn(move |conn| conn.query_row(&sql, params, f)).await
+    }
+
+    /// Executes the provided query and invokes `f` on the first returned row,
+    /// returning `None` if the query returns no rows.
+    ///
+    /// This is a shorthand for calling [`rusqlite::OptionalExtension::optional`]
+    /// on the result of [`Client::query_row`].
+    pub async fn query_opt<T, P, F>(&self, sql: &str, params: P, f: F) -> Result<Option<T>, Error>
+    where
+        P: Params + Send + 'static,
+        F: FnOnce(&Row<'_>) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let sql = sql.to_owned();
+        self.conn(move |conn| conn.query_row(&sql, params, f).optional())
+            .await
+    }
+
+    /// Executes the provided query and maps each returned row using `f`,
+    /// collecting the results.
+    ///
+    /// This covers multi-row queries, including statements with a
+    /// [`RETURNING`](https://www.sqlite.org/lang_returning.html) clause such as
+    /// `INSERT ... RETURNING id`.
+    pub async fn query_map<T, P, F>(&self, sql: &str, params: P, f: F) -> Result<Vec<T>, Error>
+    where
+        P: Params + Send + 'static,
+        F: FnMut(&Row<'_>) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let sql = sql.to_owned();
+        self.conn(move |conn| conn.prepare(&sql)?.query_map(params, f)?.collect())
+            .await
+    }
+
+    /// Executes the provided query with positional parameters built at
+    /// runtime and maps each returned row using `f`, collecting the results.
+    ///
+    /// See [`Client::execute_dyn`] for more information.
+    pub async fn query_map_dyn<T, F>(
+        &self,
+        sql: &str,
+        params: Vec<Value>,
+        f: F,
+    ) -> Result<Vec<T>, Error>
+    where
+        F: FnMut(&Row<'_>) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.query_map(sql, params_from_iter(params), f).await
+    }
+
+    /// Prepares the provided statement without executing it, returning any
+    /// error, such as a syntax error or a reference to a missing table.
+    ///
+    /// This is useful to validate queries against the live schema, such as in
+    /// tests. Only the first statement in `sql` is prepared.
+    pub async fn validate_sql(&self, sql: &str) -> Result<(), Error> {
+        let sql = sql.to_owned();
+        self.conn(move |conn| conn.prepare(&sql).map(|_| ())).await
+    }
+
+    /// Executes the provided query with named parameters and invokes `f` on
+    /// the first returned row.
+    ///
+    /// See [`Client::execute_named`] for how the parameters are handled.
+    pub fn query_row_named<T, F>(
+        &self,
+        sql: &str,
+        params: &[(&str, &dyn ToSql)],
+        f: F,
+    ) -> impl Future<Output = Result<T, Error>> + Send + '_
+    where
+        F: FnOnce(&Row<'_>) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let sql = sql.to_owned();
+        let params = NamedParams::new(params);
+        async move {
+            let params = params?;
+            self.conn(move |conn| conn.query_row(&sql, params.as_params().as_slice(), f))
+                .await
+        }
+    }
+
+    /// Executes the provided query and deserializes each returned row into a
+    /// `T`, collecting the results.
+    ///
+    /// This requires the `serde` feature, and uses
+    /// [serde_rusqlite](https://docs.rs/serde_rusqlite). Columns are matched
+    /// to the fields of a struct deriving `Deserialize` by name, and to the
+    /// elements of a tuple by position. A row with a single column can also
+    /// be deserialized into a scalar such as an `i64` or `Option<String>`. A
+    /// missing column or an incompatible value results in an
+    /// [`Error::Deserialize`] error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use async_sqlite::Client;
+    /// # async fn run(client: Client) -> Result<(), async_sqlite::Error> {
+    /// let users: Vec<(i64, String)> = client
+    ///     .query_as("SELECT id, name FROM users WHERE id > ?", [10])
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub async fn query_as<T, P>(&self, sql: &str, params: P) -> Result<Vec<T>, Error>
+    where
+        T: serde::de::DeserializeOwned + Send + 'static,
+        P: Params + Send + 'static,
+    {
+        let sql = sql.to_owned();
+        self.conn_and_then(move |conn| {
+            let mut stmt = conn.prepare(&sql)?;
+            let rows = serde_rusqlite::from_rows(stmt.query(params)?);
+            Ok(rows.collect::<Result<_, _>>()?)
+        })
+        .await
+    }
+
+    /// Executes the provided query, returning a stream that lazily maps each
+    /// returned row using `f`.
+    ///
+    /// Rows are produced by the worker thread and sent to the stream over a
+    /// bounded channel holding up to 64 rows. Once it is full, the worker
+    /// waits for the stream to be polled, providing backpressure.
+    ///
+    /// While the stream is alive, the connection is dedicated to it and all
+    /// other calls on this client wait until it is exhausted or dropped.
+    /// Dropping the stream early stops the query.
+    pub async fn query_stream<T, P, F>(
+        &self,
+        sql: &str,
+        params: P,
+        mut f: F,
+    ) -> BoxStream<'static, Result<T, Error>>
+    where
+        P: Params + Send + 'static,
+        F: FnMut(&Row<'_>) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let sql = sql.to_owned();
+        let (mut tx, rx) = mpsc::channel(QUERY_ITER_CAPACITY);
+        let res = self
+            .state
+            .send_async(Command::Func(Box::new(move |conn| {
+                let res = catch_panic(|| -> Result<(), rusqlite::Error> {
+                    let mut stmt = conn.prepare(&sql)?;
+                    let mut rows = stmt.query(params)?;
+                    while let Some(row) = rows.next()? {
+                        if send_parked(&mut tx, Some(f(row).map_err(Error::from))).is_err() {
+                            // The stream was dropped.
+                            return Ok(());
+                        }
+                        restart_statement_timeout();
+                    }
+                    Ok(())
+                });
+                if let Err(err) = res.and_then(|res| res.map_err(Error::from)) {
+                    _ = send_parked(&mut tx, Some(Err(err)));
+                }
+                _ = send_parked(&mut tx, None);
+            })))
+            .await;
+        if let Err(err) = res {
+            return stream::once(async { Err(err) }).boxed();
+        }
+
+        stream::unfold(Some(rx), |rx| async move {
+            let mut rx = rx?;
+            match rx.next().await {
+                Some(Some(res)) => Some((res, Some(rx))),
+                Some(None) => None,
+                // The worker exited before finishing the query.
+                None => Some((Err(Error::Closed), None)),
+            }
+        })
+        .boxed()
+    }
+
+    // Sends `func` to the worker thread, returning a guard that cancels or
+    // interrupts it if dropped before the function has completed. The result
+    // is sent to `tx` once the call is marked as done, so that a caller that
+    // has received it never interrupts the connection.
+    async fn send_interruptible<F, T>(
+        &self,
+        func: F,
+        tx: oneshot::Sender<Result<T, Error>>,
+    ) -> Result<InterruptGuard, Error>
+    where
+        F: FnOnce(&mut Connection) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let guard = InterruptGuard {
+            state: Arc::new(Mutex::new(CallState::Queued)),
+        };
+        let state = guard.state.clone();
+        self.state
+            .send_async(Command::Func(Box::new(move |conn| {
+                {
+                    let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+                    if matches!(*state, CallState::Done) {
+                        // The caller stopped waiting before the function started.
+                        return;
+                    }
+                    *state = CallState::Running(conn.get_interrupt_handle());
+                }
+                let res = catch_panic(|| func(conn));
+                *state.lock().unwrap_or_else(PoisonError::into_inner) = CallState::Done;
+                _ = tx.send(res);
+            })))
+            .await?;
+        Ok(guard)
+    }
+
+    /// Closes the underlying sqlite connection.
+    ///
+    /// After this method returns, all calls to `self::conn()` or
+    /// `self::conn_mut()` will return an [`Error::Closed`] error.
+    pub async fn close(&self) -> Result<(), Error> {
+        let Some(rx) = self.send_shutdown().await else {
+            return Ok(());
+        };
+        // If receiving fails, the connection is already closed.
+        rx.await.unwrap_or(Ok(()))
+    }
+
+    /// Closes the underlying sqlite connection, interrupting the running
+    /// statement if the connection hasn't closed within `timeout`.
+    ///
+    /// The connection only closes once the calls queued before it have run,
+    /// so a long-running statement is interrupted each time another
+    /// `timeout` elapses until the connection has closed. Interrupting a
+    /// statement within a transaction leaves it uncommitted, in which case it
+    /// is rolled back when the connection closes.
+    pub async fn close_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        let interrupt = self.state.worker().interrupt;
+        let Some(mut rx) = self.send_shutdown().await else {
+            return Ok(());
+        };
+        loop {
+            match select(rx, Box::pin(sleep(timeout))).await {
+                // If receiving fails, the connection is already closed.
+                Either::Left((res, _)) => return res.unwrap_or(Ok(())),
+                Either::Right((_, pending)) => {
+                    if let Some(interrupt) = &interrupt {
+                        interrupt.interrupt();
+                    }
+                    rx = pending;
+                }
+            }
+        }
+    }
+
+    // Queues a shutdown of the worker, returning `None` if it has already
+    // shut down.
+    async fn send_shutdown(&self) -> Option<oneshot::Receiver<Result<(), Error>>> {
+        let (tx, rx) = oneshot::channel();
+        let func = Box::new(|res| _ = tx.send(res));
+        self.state.shutdown.store(true, Release);
+        self.state
+            .send_async(Command::Shutdown(func))
+            .await
+            .ok()
+            .map(|()| rx)
     }
 
-    /// Invokes the provided function with a [`rusqlite::Connection`].
-    pub async fn conn<F, T>(&self, func: F) -> Result<T, Error>
+    /// Returns `true` if the underlying connection has been closed.
+    ///
+    /// Once closed, all subsequent calls return [`Error::Closed`]. This does
+    /// not issue a command to the worker thread.
+    pub fn is_closed(&self) -> bool {
+        self.state.worker().closed.load(Acquire)
+    }
+
+    /// Returns the number of calls waiting in the queue for the worker thread,
+    /// not including a call that is currently running.
+    ///
+    /// See [`ClientBuilder::queue_capacity`] for limiting the queue size.
+    pub fn queue_len(&self) -> usize {
+        self.state.worker().conn_tx.len()
+    }
+
+    /// Invokes the provided function with a [`rusqlite::Connection`], blocking
+    /// the current thread until completion.
+    ///
+    /// Calling this, or any other blocking method, on the same client from
+    /// within a provided function returns an [`Error::Reentrant`] error rather
+    /// than deadlocking.
+    pub fn conn_blocking<F, T>(&self, func: F) -> Result<T, Error>
     where
         F: FnOnce(&Connection) -> Result<T, rusqlite::Error> + Send + 'static,
         T: Send + 'static,
     {
-        let (tx, rx) = oneshot::channel();
-        self.conn_tx.send(Command::Func(Box::new(move |conn| {
-            _ = tx.send(func(conn));
-        })))?;
-        Ok(rx.await??)
+        let func = traced(move |conn: &mut Connection| func(conn));
+        let (tx, rx) = bounded(1);
+        self.state
+            .send_blocking(Command::Func(Box::new(move |conn| {
+                _ = tx.send(catch_panic(|| func(conn)));
+            })))?;
+        Ok(rx.recv()???)
     }
 
-    /// Invokes the provided function with a mutable [`rusqlite::Connection`].
-    pub async fn conn_mut<F, T>(&self, func: F) -> Result<T, Error>
+    /// Invokes the provided function with a [`rusqlite::Connection`],
+    /// blocking the current thread until it has run.
+    ///
+    /// Unlike [`Client::conn_blocking`], the function and its result don't
+    /// need to be `'static`, so the function can borrow local data instead of
+    /// cloning it. This is sound because the current thread is blocked until
+    /// the worker thread has either run or discarded the function, so the
+    /// borrows outlive every use of them. As with the other blocking methods,
+    /// it shouldn't be called from an async context, where it would block the
+    /// executor.
+    pub fn conn_scoped<F, T>(&self, func: F) -> Result<T, Error>
+    where
+        F: FnOnce(&Connection) -> Result<T, rusqlite::Error> + Send,
+        T: Send,
+    {
+        let func = traced(move |conn: &mut Connection| func(conn));
+        let mut call = ScopedCall {
+            func: Some(func),
+            out: None,
+        };
+        let ptr = ScopedPtr(&mut call as *mut ScopedCall<_, T> as *mut ());
+        let run = call.runner();
+        // The caller is notified that the function has run, or was discarded
+        // without running, when the command drops the sender.
+        let (done_tx, done_rx) = bounded::<()>(0);
+        let sent = self
+            .state
+            .send_blocking(Command::Func(Box::new(move |conn| {
+                let (_done, ptr) = (done_tx, ptr);
+                // SAFETY: `call` outlives the command, as the caller waits
+                // for it to be dropped below.
+                unsafe { run(ptr.0, conn) };
+            })));
+        // Waiting even if sending failed guarantees that the command, which
+        // points to `call`, has been dropped before `call` goes out of scope.
+        _ = done_rx.recv();
+        sent?;
+        call.out.unwrap_or(Err(Error::Closed))
+    }
+
+    /// Invokes the provided function with a mutable [`rusqlite::Connection`],
+    /// blocking the current thread until completion.
+    pub fn conn_mut_blocking<F, T>(&self, func: F) -> Result<T, Error>
     where
         F: FnOnce(&mut Connection) -> Result<T, rusqlite::Error> + Send + 'static,
         T: Send + 'static,
     {
-        let (tx, rx) = oneshot::channel();
-        self.conn_tx.send(Command::Func(Box::new(move |conn| {
-            _ = tx.send(func(conn));
-        })))?;
-        Ok(rx.await??)
+        let func = traced(func);
+        let (tx, rx) = bounded(1);
+        self.state
+            .send_blocking(Command::Func(Box::new(move |conn| {
+                _ = tx.send(catch_panic(|| func(conn)));
+            })))?;
+        Ok(rx.recv()???)
     }
 
-    /// Invokes the provided function with a [`rusqlite::Connection`].
+    /// Invokes the provided function with a [`rusqlite::Connection`], blocking
+    /// the current thread until completion.
     ///
-    /// Maps the result error type to a custom error; designed to be
-    /// used in conjunction with [`query_and_then`](https://docs.rs/rusqlite/latest/rusqlite/struct.CachedStatement.html#method.query_and_then).
-    pub async fn conn_and_then<F, T, E>(&self, func: F) -> Result<T, E>
+    /// Maps the result error type to a custom error. See
+    /// [`Client::conn_and_then`] for more information.
+    pub fn conn_and_then_blocking<F, T, E>(&self, func: F) -> Result<T, E>
     where
         F: FnOnce(&Connection) -> Result<T, E> + Send + 'static,
         T: Send + 'static,
         E: From<rusqlite::Error> + From<Error> + Send + 'static,
     {
-        let (tx, rx) = oneshot::channel();
-        self.conn_tx
-            .send(Command::Func(Box::new(move |conn| {
-                _ = tx.send(func(conn));
-            })))
-            .map_err(Error::from)?;
-        rx.await.map_err(Error::from)?
+        let func = traced(move |conn: &mut Connection| func(conn));
+        let (tx, rx) = bounded(1);
+        self.state
+            .send_blocking(Command::Func(Box::new(move |conn| {
+                _ = tx.send(catch_panic(|| func(conn)));
+            })))?;
+        rx.recv().map_err(Error::from)??
     }
 
-    /// Invokes the provided function with a mutable [`rusqlite::Connection`].
+    /// Invokes the provided function with a mutable [`rusqlite::Connection`],
+    /// blocking the current thread until completion.
     ///
-    /// Maps the result error type to a custom error; designed to be
-    /// used in conjunction with [`query_and_then`](https://docs.rs/rusqlite/latest/rusqlite/struct.CachedStatement.html#method.query_and_then).
-    pub async fn conn_mut_and_then<F, T, E>(&self, func: F) -> Result<T, E>
+    /// Maps the result error type to a custom error. See
+    /// [`Client::conn_mut_and_then`] for more information.
+    pub fn conn_mut_and_then_blocking<F, T, E>(&self, func: F) -> Result<T, E>
     where
         F: FnOnce(&mut Connection) -> Result<T, E> + Send + 'static,
         T: Send + 'static,
         E: From<rusqlite::Error> + From<Error> + Send + 'static,
     {
-        let (tx, rx) = oneshot::channel();
-        self.conn_tx
-            .send(Command::Func(Box::new(move |conn| {
-                _ = tx.send(func(conn));
-            })))
-            .map_err(Error::from)?;
-        rx.await.map_err(Error::from)?
-    }
-
-    /// Closes the underlying sqlite connection.
-    ///
-    /// After this method returns, all calls to `self::conn()` or
-    /// `self::conn_mut()` will return an [`Error::Closed`] error.
-    pub async fn close(&self) -> Result<(), Error> {
-        let (tx, rx) = oneshot::channel();
-        let func = Box::new(|res| _ = tx.send(res));
-        if self.conn_tx.send(Command::Shutdown(func)).is_err() {
-            // If the worker thread has already shut down, return Ok here.
-            return Ok(());
-        }
-        // If receiving fails, the connection is already closed.
-        rx.await.unwrap_or(Ok(()))
+        let func = traced(func);
+        let (tx, rx) = bounded(1);
+        self.state
+            .send_blocking(Command::Func(Box::new(move |conn| {
+                _ = tx.send(catch_panic(|| func(conn)));
+            })))?;
+        rx.recv().map_err(Error::from)??
     }
 
-    /// Invokes the provided function with a [`rusqlite::Connection`], blocking
-    /// the current thread until completion.
-    pub fn conn_blocking<F, T>(&self, func: F) -> Result<T, Error>
+    /// Executes the provided query and maps each returned row using `f`,
+    /// blocking the current thread until all rows have been collected.
+    pub fn query_map_blocking<T, P, F>(&self, sql: &str, params: P, f: F) -> Result<Vec<T>, Error>
     where
-        F: FnOnce(&Connection) -> Result<T, rusqlite::Error> + Send + 'static,
+        P: Params + Send + 'static,
+        F: FnMut(&Row<'_>) -> Result<T, rusqlite::Error> + Send + 'static,
         T: Send + 'static,
     {
-        let (tx, rx) = bounded(1);
-        self.conn_tx.send(Command::Func(Box::new(move |conn| {
-            _ = tx.send(func(conn));
-        })))?;
-        Ok(rx.recv()??)
+        let sql = sql.to_owned();
+        self.conn_blocking(move |conn| conn.prepare(&sql)?.query_map(params, f)?.collect())
     }
 
-    /// Invokes the provided function with a mutable [`rusqlite::Connection`],
-    /// blocking the current thread until completion.
-    pub fn conn_mut_blocking<F, T>(&self, func: F) -> Result<T, Error>
+    /// Executes the provided query, returning an iterator that lazily maps
+    /// each returned row using `f`.
+    ///
+    /// Rows are produced by the worker thread and sent to the iterator over a
+    /// bounded channel, so at most a small number of rows are buffered at a
+    /// time. Calls to [`Iterator::next`] block the current thread until the
+    /// next row is available.
+    ///
+    /// While the iterator is alive, the connection is dedicated to it and all
+    /// other calls on this client wait until it is exhausted or dropped.
+    pub fn query_iter<T, P, F>(&self, sql: &str, params: P, mut f: F) -> Result<QueryIter<T>, Error>
     where
-        F: FnOnce(&mut Connection) -> Result<T, rusqlite::Error> + Send + 'static,
+        P: Params + Send + 'static,
+        F: FnMut(&Row<'_>) -> Result<T, rusqlite::Error> + Send + 'static,
         T: Send + 'static,
     {
-        let (tx, rx) = bounded(1);
-        self.conn_tx.send(Command::Func(Box::new(move |conn| {
-            _ = tx.send(func(conn));
-        })))?;
-        Ok(rx.recv()??)
+        let sql = sql.to_owned();
+        let (tx, rx) = bounded(QUERY_ITER_CAPACITY);
+        self.state
+            .send_blocking(Command::Func(Box::new(move |conn| {
+                let res = catch_panic(|| -> Result<(), rusqlite::Error> {
+                    let mut stmt = conn.prepare(&sql)?;
+                    let mut rows = stmt.query(params)?;
+                    while let Some(row) = rows.next()? {
+                        if tx.send(Some(f(row).map_err(Error::from))).is_err() {
+                            // The iterator was dropped.
+                            return Ok(());
+                        }
+                        restart_statement_timeout();
+                    }
+                    Ok(())
+                });
+                if let Err(err) = res.and_then(|res| res.map_err(Error::from)) {
+                    _ = tx.send(Some(Err(err)));
+                }
+                _ = tx.send(None);
+            })))?;
+        Ok(QueryIter { rx, done: false })
     }
 
     /// Closes the underlying sqlite connection, blocking the current thread
@@ -306,9 +2548,11 @@ impl Client {
     /// After this method returns, all calls to `self::conn_blocking()` or
     /// `self::conn_mut_blocking()` will return an [`Error::Closed`] error.
     pub fn close_blocking(&self) -> Result<(), Error> {
+        self.state.check_reentrant()?;
         let (tx, rx) = bounded(1);
         let func = Box::new(move |res| _ = tx.send(res));
-        if self.conn_tx.send(Command::Shutdown(func)).is_err() {
+        self.state.shutdown.store(true, Release);
+        if self.state.send(Command::Shutdown(func)).is_err() {
             return Ok(());
         }
         // If receiving fails, the connection is already closed.
@@ -316,6 +2560,528 @@ impl Client {
     }
 }
 
+// Sends `item` on the bounded channel from a worker thread, parking the thread
+// while the channel is full. Returns an error if the receiver was dropped.
+fn send_parked<T>(tx: &mut mpsc::Sender<T>, item: T) -> Result<(), mpsc::SendError> {
+    struct Unpark(thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match tx.poll_ready(&mut cx) {
+            Poll::Ready(Ok(())) => return tx.start_send(item),
+            Poll::Ready(Err(err)) => return Err(err),
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+enum CallState {
+    Queued,
+    // Holds the handle of the connection running the call, which isn't known
+    // until then for a pool's shared queue.
+    Running(InterruptHandle),
+    // The caller stopped waiting while the call was running, so the timer
+    // thread keeps interrupting the connection until the call completes.
+    Interrupted(InterruptHandle),
+    Done,
+}
+
+// Cancels a call if dropped while it's queued, or interrupts the connection if
+// dropped while it's running. The state lock is held while interrupting, so
+// the worker can't move on to the next call in the meantime.
+struct InterruptGuard {
+    state: Arc<Mutex<CallState>>,
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if let CallState::Running(interrupt) = mem::replace(&mut *state, CallState::Done) {
+            interrupt.interrupt();
+            // sqlite ignores an interrupt while no statement is running, such
+            // as just before the call starts its first one, so the timer
+            // thread interrupts the connection again until the call completes.
+            *state = CallState::Interrupted(interrupt);
+            drop(state);
+            if let Some(timer_tx) = timer() {
+                _ = timer_tx.send(Timer {
+                    deadline: Instant::now() + INTERRUPT_RETRY_INTERVAL,
+                    action: TimerAction::Interrupt {
+                        state: self.state.clone(),
+                        interval: INTERRUPT_RETRY_INTERVAL,
+                    },
+                });
+            }
+        }
+    }
+}
+
+// How soon a dropped call that is still running is first interrupted again.
+// The interval doubles with each retry, up to `MAX_INTERRUPT_RETRY_INTERVAL`.
+const INTERRUPT_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+const MAX_INTERRUPT_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+// A function borrowed by a `Client::conn_scoped` call, along with a slot for
+// its result, both of which live on the caller's stack.
+struct ScopedCall<F, T> {
+    func: Option<F>,
+    out: Option<Result<T, Error>>,
+}
+
+// A type-erased pointer to a `ScopedCall` that is sent to the worker thread.
+struct ScopedPtr(*mut ());
+
+// SAFETY: the pointer is only dereferenced on the worker thread while the
+// caller is blocked, and the function and result it points to are `Send`.
+unsafe impl Send for ScopedPtr {}
+
+impl<F, T> ScopedCall<F, T>
+where
+    F: FnOnce(&mut Connection) -> Result<T, rusqlite::Error>,
+{
+    // Returns the function that runs this call through a type-erased pointer.
+    fn runner(&self) -> unsafe fn(*mut (), &mut Connection) {
+        Self::run
+    }
+
+    // Runs the function of the `ScopedCall` that `ptr` points to, storing its
+    // result.
+    //
+    // SAFETY: `ptr` must point to a live `ScopedCall<F, T>` that isn't
+    // accessed elsewhere for the duration of the call.
+    unsafe fn run(ptr: *mut (), conn: &mut Connection) {
+        let call = &mut *(ptr as *mut Self);
+        if let Some(func) = call.func.take() {
+            call.out = Some(catch_panic(|| func(conn)).and_then(|res| res.map_err(Error::from)));
+        }
+    }
+}
+
+// Runs `func`, converting a panic into an `Error::Panic` so that the worker
+// thread can keep serving requests.
+fn catch_panic<T>(func: impl FnOnce() -> T) -> Result<T, Error> {
+    panic::catch_unwind(AssertUnwindSafe(func)).map_err(|payload| {
+        let msg = if let Some(msg) = payload.downcast_ref::<&str>() {
+            (*msg).to_owned()
+        } else if let Some(msg) = payload.downcast_ref::<String>() {
+            msg.clone()
+        } else {
+            "unknown panic".to_owned()
+        };
+        Error::Panic(msg)
+    })
+}
+
+// Returns the configuration to reopen a connection with, if `auto_reconnect`
+// is enabled. A reopened connection doesn't join a pool's shared queue, so
+// that the queue is disconnected once the pool's connections have closed.
+fn reconnect_builder(builder: &ClientBuilder) -> Option<ClientBuilder> {
+    builder.auto_reconnect.then(|| ClientBuilder {
+        shared_queue: None,
+        ..builder.clone()
+    })
+}
+
+// Runs `func` within an immediate transaction, committing it on success.
+pub(crate) fn transaction<F, T>(conn: &mut Connection, func: F) -> Result<T, rusqlite::Error>
+where
+    F: FnOnce(&Transaction<'_>) -> Result<T, rusqlite::Error>,
+{
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    // Dropping the transaction on error rolls it back.
+    let res = func(&tx)?;
+    tx.commit()?;
+    Ok(res)
+}
+
+// Returns the maximum number of variables that can be bound in a single
+// statement.
+#[cfg(feature = "limits")]
+fn max_variables(conn: &Connection) -> usize {
+    conn.limit(rusqlite::limits::Limit::SQLITE_LIMIT_VARIABLE_NUMBER)
+        .max(1) as usize
+}
+
+// Returns the maximum number of variables that can be bound in a single
+// statement, using sqlite's default limit for its version.
+#[cfg(not(feature = "limits"))]
+fn max_variables(_conn: &Connection) -> usize {
+    if rusqlite::version_number() >= 3_032_000 {
+        32766
+    } else {
+        999
+    }
+}
+
+// Builds a parameterized statement inserting `num_rows` rows into `table`.
+fn bulk_insert_sql(table: &str, columns: &[String], num_rows: usize) -> String {
+    let row = format!("({})", vec!["?"; columns.len()].join(", "));
+    format!(
+        "INSERT INTO {table} ({}) VALUES {}",
+        columns.join(", "),
+        vec![row; num_rows].join(", ")
+    )
+}
+
+// Quotes `name` as an sqlite identifier.
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+thread_local! {
+    // Identifies the worker owning the current thread, if any, by the address
+    // of its `closed` flag.
+    static CURRENT_WORKER: Cell<usize> = const { Cell::new(0) };
+}
+
+#[cfg(feature = "hooks")]
+thread_local! {
+    // When the call currently running on the worker thread started, if any,
+    // for `ClientBuilder::statement_timeout`.
+    static CALL_START: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+// The number of virtual machine instructions between checks of the
+// statement timeout.
+#[cfg(feature = "hooks")]
+const STATEMENT_TIMEOUT_OPS: std::os::raw::c_int = 1000;
+
+// Restarts the statement timeout of the running call, so that the time a
+// streaming query spends waiting for its consumer doesn't count against it.
+fn restart_statement_timeout() {
+    #[cfg(feature = "hooks")]
+    CALL_START.with(|start| {
+        if start.get().is_some() {
+            start.set(Some(Instant::now()));
+        }
+    });
+}
+
+thread_local! {
+    // The busy handler of the connection owned by the current worker thread,
+    // as rusqlite only accepts function pointers.
+    static BUSY_HANDLER: std::cell::RefCell<Option<BusyHandler>> = const { std::cell::RefCell::new(None) };
+}
+
+fn busy_callback(count: i32) -> bool {
+    match BUSY_HANDLER.with(|cell| cell.borrow().clone()) {
+        Some(busy_handler) => busy_handler(count),
+        None => false,
+    }
+}
+
+#[cfg(feature = "trace")]
+thread_local! {
+    // The profile and trace callbacks of the connection owned by the current
+    // worker thread, as rusqlite only accepts function pointers.
+    static PROFILE: std::cell::RefCell<Option<ProfileFn>> = const { std::cell::RefCell::new(None) };
+    static TRACE: std::cell::RefCell<Option<TraceFn>> = const { std::cell::RefCell::new(None) };
+}
+
+#[cfg(feature = "trace")]
+fn profile_callback(sql: &str, duration: Duration) {
+    if let Some(profile) = PROFILE.with(|cell| cell.borrow().clone()) {
+        profile(sql, duration);
+    }
+}
+
+#[cfg(feature = "trace")]
+fn trace_callback(sql: &str) {
+    if let Some(trace) = TRACE.with(|cell| cell.borrow().clone()) {
+        trace(sql);
+    }
+}
+
+// Waits for the provided duration without depending on a specific async
+// runtime.
+//
+// All sleeps share a single timer thread, started on first use and kept for
+// the lifetime of the process, which completes each sleep once its deadline
+// passes. A sleep whose future is dropped early stays queued until its
+// deadline, so the cost is one heap entry per outstanding sleep.
+pub(crate) async fn sleep(duration: Duration) {
+    let Some(deadline) = Instant::now().checked_add(duration) else {
+        // A deadline this far away is never reached.
+        return std::future::pending().await;
+    };
+    let timer_tx = timer().expect("spawning timer thread");
+    let (tx, rx) = oneshot::channel();
+    let timer = Timer {
+        deadline,
+        action: TimerAction::Wake(tx),
+    };
+    if timer_tx.send(timer).is_ok() {
+        _ = rx.await;
+    }
+}
+
+// Returns the sender for the shared timer thread, starting it on first use,
+// or `None` if the thread couldn't be spawned.
+fn timer() -> Option<&'static Sender<Timer>> {
+    static TIMER: OnceLock<Option<Sender<Timer>>> = OnceLock::new();
+    TIMER
+        .get_or_init(|| {
+            let (tx, rx) = unbounded();
+            thread::Builder::new()
+                .name("async-sqlite-timer".to_owned())
+                .spawn(move || run_timer(rx))
+                .ok()?;
+            Some(tx)
+        })
+        .as_ref()
+}
+
+// Runs the shared timer thread, firing timers in deadline order.
+fn run_timer(rx: Receiver<Timer>) {
+    let mut timers = BinaryHeap::<Reverse<Timer>>::new();
+    loop {
+        let now = Instant::now();
+        while let Some(Reverse(timer)) = timers.peek() {
+            if timer.deadline > now && !timer.is_canceled() {
+                break;
+            }
+            let Some(Reverse(timer)) = timers.pop() else {
+                break;
+            };
+            match timer.action {
+                TimerAction::Wake(tx) => _ = tx.send(()),
+                TimerAction::Interrupt { state, interval } => {
+                    let guard = state.lock().unwrap_or_else(PoisonError::into_inner);
+                    let CallState::Interrupted(interrupt) = &*guard else {
+                        continue;
+                    };
+                    interrupt.interrupt();
+                    drop(guard);
+                    let interval = (interval * 2).min(MAX_INTERRUPT_RETRY_INTERVAL);
+                    timers.push(Reverse(Timer {
+                        deadline: now + interval,
+                        action: TimerAction::Interrupt { state, interval },
+                    }));
+                }
+            }
+        }
+        let res = match timers.peek() {
+            Some(Reverse(timer)) => rx.recv_deadline(timer.deadline),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match res {
+            Ok(timer) => timers.push(Reverse(timer)),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+struct Timer {
+    deadline: Instant,
+    action: TimerAction,
+}
+
+enum TimerAction {
+    // Completes a sleep.
+    Wake(oneshot::Sender<()>),
+    // Interrupts a dropped call again if it's still running.
+    Interrupt {
+        state: Arc<Mutex<CallState>>,
+        interval: Duration,
+    },
+}
+
+impl Timer {
+    // Whether the timer can be dropped without waiting for its deadline.
+    fn is_canceled(&self) -> bool {
+        match &self.action {
+            TimerAction::Wake(tx) => tx.is_canceled(),
+            TimerAction::Interrupt { .. } => false,
+        }
+    }
+}
+
+impl PartialEq for Timer {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for Timer {}
+
+impl PartialOrd for Timer {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timer {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.deadline.cmp(&other.deadline)
+    }
+}
+
+pub(crate) fn checkpoint(
+    conn: &Connection,
+    mode: CheckpointMode,
+) -> Result<CheckpointResult, rusqlite::Error> {
+    let sql = format!("PRAGMA wal_checkpoint({})", mode.as_str());
+    conn.query_row(&sql, (), |row| {
+        Ok(CheckpointResult {
+            busy: row.get(0)?,
+            log_frames: row.get(1)?,
+            checkpointed_frames: row.get(2)?,
+        })
+    })
+}
+
+const QUERY_ITER_CAPACITY: usize = 64;
+
+/// An iterator over the mapped rows of a query, as returned by
+/// [`Client::query_iter`].
+pub struct QueryIter<T> {
+    rx: Receiver<Option<Result<T, Error>>>,
+    done: bool,
+}
+
+impl<T> Iterator for QueryIter<T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.rx.recv() {
+            Ok(Some(res)) => Some(res),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                // The worker exited before finishing the query.
+                self.done = true;
+                Some(Err(err.into()))
+            }
+        }
+    }
+}
+
+// Wraps a function sent to the worker thread so that it runs within an
+// `async_sqlite.conn` span, created as a child of the caller's current span.
+// The caller's dispatcher is captured too, since the worker thread doesn't
+// share the caller's thread-local subscriber.
+#[cfg(feature = "tracing")]
+fn traced<T, E: 'static>(
+    func: impl FnOnce(&mut Connection) -> Result<T, E>,
+) -> impl FnOnce(&mut Connection) -> Result<T, E> {
+    let span = tracing::info_span!(
+        "async_sqlite.conn",
+        queue_us = tracing::field::Empty,
+        exec_us = tracing::field::Empty,
+    );
+    let dispatch = tracing::dispatcher::get_default(tracing::Dispatch::clone);
+    let queued = Instant::now();
+    move |conn: &mut Connection| {
+        tracing::dispatcher::with_default(&dispatch, || {
+            let _entered = span.enter();
+            span.record("queue_us", queued.elapsed().as_micros() as u64);
+            let start = Instant::now();
+            let res = func(conn);
+            span.record("exec_us", start.elapsed().as_micros() as u64);
+            if let Err(err) = &res {
+                warn_failed(err);
+            }
+            res
+        })
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+fn traced<F>(func: F) -> F {
+    func
+}
+
+// Logs a failed call at the warn level, calling out busy databases
+// separately since they usually point to lock contention.
+#[cfg(feature = "tracing")]
+fn warn_failed(err: &dyn std::any::Any) {
+    let sqlite_err = match err.downcast_ref::<Error>() {
+        Some(Error::Rusqlite(err)) => Some(err),
+        Some(err) => {
+            tracing::warn!(error = %err, "call failed");
+            return;
+        }
+        None => err.downcast_ref::<rusqlite::Error>(),
+    };
+    match sqlite_err {
+        Some(err) if err.sqlite_error_code() == Some(rusqlite::ErrorCode::DatabaseBusy) => {
+            tracing::warn!(error = %err, "database is busy");
+        }
+        Some(err) => tracing::warn!(error = %err, "call failed"),
+        None => tracing::warn!("call failed"),
+    }
+}
+
+// Named parameters converted into owned values, so that they can be sent to
+// the worker thread.
+pub(crate) struct NamedParams(Vec<(String, Value)>);
+
+impl NamedParams {
+    pub(crate) fn new(params: &[(&str, &dyn ToSql)]) -> Result<Self, Error> {
+        let params = params
+            .iter()
+            .map(|&(name, value)| {
+                let value = match value.to_sql()? {
+                    ToSqlOutput::Borrowed(value) => value.into(),
+                    ToSqlOutput::Owned(value) => value,
+                    _ => {
+                        return Err(rusqlite::Error::ToSqlConversionFailure(
+                            format!("unsupported value for parameter {name}").into(),
+                        ))
+                    }
+                };
+                Ok((name.to_owned(), value))
+            })
+            .collect::<Result<_, rusqlite::Error>>()?;
+        Ok(Self(params))
+    }
+
+    pub(crate) fn as_params(&self) -> Vec<(&str, &dyn ToSql)> {
+        self.0
+            .iter()
+            .map(|(name, value)| (name.as_str(), value as &dyn ToSql))
+            .collect()
+    }
+}
+
+fn pragma_value_to_string(value: ValueRef<'_>) -> Result<String, rusqlite::Error> {
+    Ok(match value {
+        ValueRef::Null => String::new(),
+        ValueRef::Integer(v) => v.to_string(),
+        ValueRef::Real(v) => v.to_string(),
+        ValueRef::Text(_) | ValueRef::Blob(_) => value.as_str()?.to_owned(),
+    })
+}
+
+// Compares a pragma value with the value sqlite reports, treating sqlite's
+// boolean keywords as equivalent to their integer values.
+fn pragma_value_matches(exp: &str, got: &str) -> bool {
+    fn normalize(value: &str) -> String {
+        let value = value
+            .trim_matches(|c| c == '\'' || c == '"')
+            .to_ascii_lowercase();
+        match value.as_str() {
+            "on" | "true" | "yes" => "1".to_owned(),
+            "off" | "false" | "no" => "0".to_owned(),
+            _ => value,
+        }
+    }
+    normalize(exp) == normalize(got)
+}
+
 /// The possible sqlite journal modes.
 ///
 /// For more information, please see the [sqlite docs](https://www.sqlite.org/pragma.html#pragma_journal_mode).
@@ -342,3 +3108,84 @@ impl JournalMode {
         }
     }
 }
+
+/// The possible sqlite synchronous settings.
+///
+/// For more information, please see the [sqlite docs](https://www.sqlite.org/pragma.html#pragma_synchronous).
+#[derive(Clone, Copy, Debug)]
+pub enum Synchronous {
+    Off = 0,
+    Normal = 1,
+    Full = 2,
+    Extra = 3,
+}
+
+impl Synchronous {
+    /// Returns the appropriate string representation of the synchronous
+    /// setting.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Off => "OFF",
+            Self::Normal => "NORMAL",
+            Self::Full => "FULL",
+            Self::Extra => "EXTRA",
+        }
+    }
+}
+
+/// The possible sqlite temp_store settings.
+///
+/// For more information, please see the [sqlite docs](https://www.sqlite.org/pragma.html#pragma_temp_store).
+#[derive(Clone, Copy, Debug)]
+pub enum TempStore {
+    Default = 0,
+    File = 1,
+    Memory = 2,
+}
+
+impl TempStore {
+    /// Returns the appropriate string representation of the temp_store
+    /// setting.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Default => "DEFAULT",
+            Self::File => "FILE",
+            Self::Memory => "MEMORY",
+        }
+    }
+}
+
+/// The possible sqlite WAL checkpoint modes.
+///
+/// For more information, please see the [sqlite docs](https://www.sqlite.org/c3ref/wal_checkpoint_v2.html).
+#[derive(Clone, Copy, Debug)]
+pub enum CheckpointMode {
+    Passive,
+    Full,
+    Restart,
+    Truncate,
+}
+
+impl CheckpointMode {
+    /// Returns the appropriate string representation of the checkpoint mode.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Passive => "PASSIVE",
+            Self::Full => "FULL",
+            Self::Restart => "RESTART",
+            Self::Truncate => "TRUNCATE",
+        }
+    }
+}
+
+/// The result of a WAL checkpoint, as returned by [`Client::checkpoint`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CheckpointResult {
+    /// Whether the checkpoint was blocked from completing by another
+    /// connection.
+    pub busy: bool,
+    /// The number of frames in the WAL file.
+    pub log_frames: i64,
+    /// The number of frames in the WAL file that were checkpointed.
+    pub checkpointed_frames: i64,
+}
@@ -1,17 +1,86 @@
+use std::borrow::Cow;
+
 /// Enum of all possible errors.
+///
+/// Only the [`Rusqlite`](Error::Rusqlite) variant wraps another error, which
+/// is returned from [`source`](std::error::Error::source). The other
+/// variants carry all of their context in their fields and message.
 #[derive(Debug)]
 #[non_exhaustive]
 pub enum Error {
     /// Indicates that the connection to the sqlite database is closed.
     Closed,
     /// Error updating PRAGMA.
+    ///
+    /// The name and expected value are borrowed for built-in settings, and
+    /// owned for values only known at runtime, such as a numeric setting or
+    /// one set with [`ClientBuilder::pragma`](crate::ClientBuilder::pragma).
     PragmaUpdate {
-        name: &'static str,
-        exp: &'static str,
+        name: Cow<'static, str>,
+        exp: Cow<'static, str>,
         got: String,
     },
     /// Represents a [`rusqlite::Error`].
     Rusqlite(rusqlite::Error),
+    /// Indicates that a row couldn't be deserialized, such as when a column
+    /// is missing or has an unexpected type, containing the error message.
+    #[cfg(feature = "serde")]
+    Deserialize(String),
+    /// Indicates that an operation didn't complete in time.
+    Timeout,
+    /// Indicates that a provided function panicked on the worker thread,
+    /// containing the panic message.
+    Panic(String),
+    /// Indicates that a pool connection index is out of range.
+    IndexOutOfRange { index: usize, len: usize },
+    /// Indicates that a blocking call was made from within a function running
+    /// on the same client's worker thread, which would otherwise deadlock.
+    Reentrant,
+}
+
+impl Error {
+    /// Returns `true` if the error is caused by the database being busy
+    /// (`SQLITE_BUSY`).
+    pub fn is_busy(&self) -> bool {
+        self.sqlite_error_code() == Some(rusqlite::ErrorCode::DatabaseBusy)
+    }
+
+    /// Returns `true` if the error is caused by a table in the database being
+    /// locked (`SQLITE_LOCKED`).
+    pub fn is_locked(&self) -> bool {
+        self.sqlite_error_code() == Some(rusqlite::ErrorCode::DatabaseLocked)
+    }
+
+    /// Returns `true` if the error is caused by the connection being closed.
+    pub fn is_closed(&self) -> bool {
+        matches!(self, Error::Closed)
+    }
+
+    /// Returns the sqlite error code of the underlying
+    /// [`rusqlite::Error`], if any.
+    pub fn sqlite_error_code(&self) -> Option<rusqlite::ErrorCode> {
+        match self {
+            Error::Rusqlite(err) => err.sqlite_error_code(),
+            _ => None,
+        }
+    }
+
+    /// Returns a reference to the underlying [`rusqlite::Error`], if any.
+    pub fn as_rusqlite(&self) -> Option<&rusqlite::Error> {
+        match self {
+            Error::Rusqlite(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    /// Converts the error into the underlying [`rusqlite::Error`], returning
+    /// the original error if it doesn't wrap one.
+    pub fn into_rusqlite(self) -> Result<rusqlite::Error, Error> {
+        match self {
+            Error::Rusqlite(err) => Ok(err),
+            err => Err(err),
+        }
+    }
 }
 
 impl std::error::Error for Error {
@@ -31,6 +100,17 @@ impl std::fmt::Display for Error {
                 write!(f, "updating pragma {name}: expected '{exp}', got '{got}'")
             }
             Error::Rusqlite(err) => err.fmt(f),
+            #[cfg(feature = "serde")]
+            Error::Deserialize(msg) => write!(f, "deserializing row: {msg}"),
+            Error::Timeout => write!(f, "timed out"),
+            Error::Panic(msg) => write!(f, "worker thread panicked: {msg}"),
+            Error::IndexOutOfRange { index, len } => {
+                write!(f, "connection index {index} out of range for pool of {len}")
+            }
+            Error::Reentrant => write!(
+                f,
+                "blocking call made from the connection's own worker thread"
+            ),
         }
     }
 }
@@ -41,6 +121,27 @@ impl From<rusqlite::Error> for Error {
     }
 }
 
+#[cfg(feature = "serde")]
+impl From<serde_rusqlite::Error> for Error {
+    fn from(value: serde_rusqlite::Error) -> Self {
+        match value {
+            serde_rusqlite::Error::Rusqlite(err) => Error::Rusqlite(err),
+            err => Error::Deserialize(err.to_string()),
+        }
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(value: Error) -> Self {
+        let kind = match &value {
+            Error::Rusqlite(rusqlite::Error::QueryReturnedNoRows) => std::io::ErrorKind::NotFound,
+            Error::Timeout => std::io::ErrorKind::TimedOut,
+            _ => std::io::ErrorKind::Other,
+        };
+        std::io::Error::new(kind, value)
+    }
+}
+
 impl<T> From<crossbeam_channel::SendError<T>> for Error {
     fn from(_value: crossbeam_channel::SendError<T>) -> Self {
         Error::Closed
@@ -67,13 +67,23 @@
 //! ```toml
 //! async-sqlite = { version = "*", default-features = false }
 //! ```
+//!
+//! Additionally, the `tracing` feature runs each call made on a connection in an
+//! `async_sqlite.conn` [tracing](https://docs.rs/tracing) span, and logs failed
+//! calls at the warn level. The `serde` feature enables deserializing rows with
+//! [serde](https://serde.rs) using `Client::query_as` and `Pool::query_as`.
 
 pub use rusqlite;
 
 mod client;
 mod error;
+mod migrate;
 mod pool;
 
-pub use client::{Client, ClientBuilder, JournalMode};
+pub use client::{
+    CheckpointMode, CheckpointResult, Client, ClientBuilder, JournalMode, QueryIter, Synchronous,
+    TempStore,
+};
 pub use error::Error;
-pub use pool::{Pool, PoolBuilder};
+pub use migrate::{Migration, Migrations};
+pub use pool::{Pool, PoolBuilder, PoolStats, PooledConn, WeakPool};
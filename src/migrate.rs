@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use rusqlite::Transaction;
+
+/// A single schema migration, identified by the `user_version` it upgrades
+/// the database to.
+#[derive(Clone, Copy, Debug)]
+pub struct Migration {
+    /// The version of the schema after this migration is applied. Versions
+    /// must be greater than zero.
+    pub version: u32,
+    /// The SQL script that applies the migration.
+    pub up_sql: &'static str,
+}
+
+/// An ordered list of [`Migration`]s that can be applied with
+/// [`Client::migrate`](crate::Client::migrate).
+///
+/// The database's [`user_version`](https://www.sqlite.org/pragma.html#pragma_user_version)
+/// is used to track which migrations have already been applied.
+#[derive(Clone, Debug)]
+pub struct Migrations {
+    migrations: Arc<[Migration]>,
+}
+
+impl Migrations {
+    /// Returns a new list of migrations, sorted by version.
+    ///
+    /// # Panics
+    ///
+    /// Panics if two migrations have the same version.
+    pub fn new(migrations: &[Migration]) -> Self {
+        let mut migrations = migrations.to_vec();
+        migrations.sort_by_key(|m| m.version);
+        if let Some(pair) = migrations.windows(2).find(|w| w[0].version == w[1].version) {
+            panic!("duplicate migration version {}", pair[0].version);
+        }
+        Self {
+            migrations: migrations.into(),
+        }
+    }
+
+    /// Returns the version of the most recent migration, or zero if there
+    /// are none.
+    pub fn latest_version(&self) -> u32 {
+        self.migrations.last().map_or(0, |m| m.version)
+    }
+
+    // Applies every migration newer than the current `user_version` within
+    // the provided transaction, returning the resulting version.
+    pub(crate) fn apply(&self, tx: &Transaction<'_>) -> Result<u32, rusqlite::Error> {
+        let mut version: u32 = tx.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        for migration in self.migrations.iter() {
+            if migration.version <= version {
+                continue;
+            }
+            tx.execute_batch(migration.up_sql)?;
+            version = migration.version;
+        }
+        tx.pragma_update(None, "user_version", version)?;
+        Ok(version)
+    }
+}
@@ -1,17 +1,35 @@
 use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    future::Future,
+    hash::{Hash, Hasher},
     num::NonZeroUsize,
-    path::{Path, PathBuf},
+    path::Path,
     sync::{
-        atomic::{AtomicU64, Ordering::Relaxed},
-        Arc,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering::Relaxed},
+        Arc, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak,
     },
-    thread::available_parallelism,
+    thread::{self, available_parallelism},
+    time::Duration,
 };
 
-use crate::{Client, ClientBuilder, Error, JournalMode};
+use crate::{
+    client::{checkpoint, transaction, NamedParams, SharedQueue},
+    CheckpointMode, CheckpointResult, Client, ClientBuilder, Error, JournalMode, Synchronous,
+    TempStore,
+};
 
-use futures_util::future::join_all;
-use rusqlite::{Connection, OpenFlags};
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender};
+use futures_util::{
+    future::join_all,
+    stream::{self, BoxStream, StreamExt},
+};
+use rusqlite::{
+    config::DbConfig,
+    params_from_iter,
+    types::{ToSql, Value},
+    Connection, OpenFlags, OptionalExtension, Params, Row, Transaction,
+};
 
 /// A `PoolBuilder` can be used to create a [`Pool`] with custom
 /// configuration.
@@ -33,10 +51,19 @@ use rusqlite::{Connection, OpenFlags};
 /// ```
 #[derive(Clone, Debug, Default)]
 pub struct PoolBuilder {
-    path: Option<PathBuf>,
-    flags: OpenFlags,
-    journal_mode: Option<JournalMode>,
-    vfs: Option<String>,
+    // The configuration shared by every connection in the pool.
+    client: ClientBuilder,
+    // Whether the path is the URI of a named in-memory database.
+    shared_memory: bool,
+    auto_checkpoint: Option<Duration>,
+    auto_optimize: bool,
+    auto_optimize_interval: Option<Duration>,
+    // Whether the first connection is the only one opened read-write.
+    wal_reader_pool: bool,
+    // Whether connections after the first are only opened when needed.
+    lazy: bool,
+    // Whether connections take calls from a single queue.
+    shared_queue: bool,
     num_conns: Option<usize>,
 }
 
@@ -50,7 +77,26 @@ impl PoolBuilder {
     ///
     /// By default, an in-memory database is used.
     pub fn path<P: AsRef<Path>>(mut self, path: P) -> Self {
-        self.path = Some(path.as_ref().into());
+        self.client = self.client.path(path);
+        self.shared_memory = false;
+        self
+    }
+
+    /// Specify a named in-memory database that is shared by every connection
+    /// in the pool.
+    ///
+    /// This opens `file:{name}?mode=memory&cache=shared` with
+    /// [`OpenFlags::SQLITE_OPEN_URI`] set, replacing any configured path. The
+    /// database only exists while at least one connection to it is open, so
+    /// its data is lost once the pool is closed. As connections use sqlite's
+    /// [shared cache](https://www.sqlite.org/sharedcache.html), concurrent
+    /// requests may fail with a locked error, see [`Error::is_locked`].
+    pub fn shared_memory(mut self, name: &str) -> Self {
+        self.client = self
+            .client
+            .path(format!("file:{name}?mode=memory&cache=shared"))
+            .uri(true);
+        self.shared_memory = true;
         self
     }
 
@@ -58,7 +104,93 @@ impl PoolBuilder {
     ///
     /// By default, [`OpenFlags::default()`] is used.
     pub fn flags(mut self, flags: OpenFlags) -> Self {
-        self.flags = flags;
+        self.client = self.client.flags(flags);
+        self
+    }
+
+    /// Specify whether to open connections in read-only mode.
+    ///
+    /// See [`ClientBuilder::read_only`] for more information.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.client = self.client.read_only(read_only);
+        self
+    }
+
+    /// Specify whether to open connections in sqlite's
+    /// [serialized](https://www.sqlite.org/threadsafe.html) threading mode.
+    ///
+    /// See [`ClientBuilder::full_mutex`] for more information.
+    pub fn full_mutex(mut self, full_mutex: bool) -> Self {
+        self.client = self.client.full_mutex(full_mutex);
+        self
+    }
+
+    /// Specify whether the path is interpreted as a
+    /// [URI filename](https://www.sqlite.org/uri.html).
+    ///
+    /// See [`ClientBuilder::uri`] for more information.
+    pub fn uri(mut self, uri: bool) -> Self {
+        self.client = self.client.uri(uri);
+        self
+    }
+
+    /// Specify whether the database file must already exist.
+    ///
+    /// See [`ClientBuilder::must_exist`] for more information.
+    pub fn must_exist(mut self, must_exist: bool) -> Self {
+        self.client = self.client.must_exist(must_exist);
+        self
+    }
+
+    /// Specify whether to create the database file if it doesn't exist.
+    ///
+    /// See [`ClientBuilder::create`] for more information.
+    pub fn create(mut self, create: bool) -> Self {
+        self.client = self.client.create(create);
+        self
+    }
+
+    /// Specify whether to open connections for reading and writing.
+    ///
+    /// See [`ClientBuilder::read_write`] for more information.
+    pub fn read_write(mut self, read_write: bool) -> Self {
+        self.client = self.client.read_write(read_write);
+        self
+    }
+
+    /// Specify whether to open connections in sqlite's
+    /// [multi-thread](https://www.sqlite.org/threadsafe.html) threading mode.
+    ///
+    /// See [`ClientBuilder::no_mutex`] for more information.
+    pub fn no_mutex(mut self, no_mutex: bool) -> Self {
+        self.client = self.client.no_mutex(no_mutex);
+        self
+    }
+
+    /// Specify whether to open connections with sqlite's
+    /// [shared cache](https://www.sqlite.org/sharedcache.html) enabled.
+    ///
+    /// See [`ClientBuilder::shared_cache`] for more information.
+    pub fn shared_cache(mut self, shared_cache: bool) -> Self {
+        self.client = self.client.shared_cache(shared_cache);
+        self
+    }
+
+    /// Specify whether to open connections with a private cache, even if
+    /// shared cache mode is enabled process-wide.
+    ///
+    /// See [`ClientBuilder::private_cache`] for more information.
+    pub fn private_cache(mut self, private_cache: bool) -> Self {
+        self.client = self.client.private_cache(private_cache);
+        self
+    }
+
+    /// Specify the [`page_size`](https://www.sqlite.org/pragma.html#pragma_page_size)
+    /// in bytes to set when opening a new connection.
+    ///
+    /// See [`ClientBuilder::page_size`] for more information.
+    pub fn page_size(mut self, page_size: u32) -> Self {
+        self.client = self.client.page_size(page_size);
         self
     }
 
@@ -66,13 +198,344 @@ impl PoolBuilder {
     ///
     /// By default, no `journal_mode` is explicity set.
     pub fn journal_mode(mut self, journal_mode: JournalMode) -> Self {
-        self.journal_mode = Some(journal_mode);
+        self.client = self.client.journal_mode(journal_mode);
+        self
+    }
+
+    /// Specify the [`Synchronous`] setting to set when opening a new
+    /// connection.
+    ///
+    /// By default, no `synchronous` setting is explicitly set.
+    pub fn synchronous(mut self, synchronous: Synchronous) -> Self {
+        self.client = self.client.synchronous(synchronous);
+        self
+    }
+
+    /// Specify the [`TempStore`] setting to set when opening a new
+    /// connection.
+    ///
+    /// See [`ClientBuilder::temp_store`] for more information.
+    pub fn temp_store(mut self, temp_store: TempStore) -> Self {
+        self.client = self.client.temp_store(temp_store);
+        self
+    }
+
+    /// Specify the [`cache_size`](https://www.sqlite.org/pragma.html#pragma_cache_size)
+    /// to set when opening a new connection.
+    ///
+    /// See [`ClientBuilder::cache_size`] for more information.
+    pub fn cache_size(mut self, cache_size: i64) -> Self {
+        self.client = self.client.cache_size(cache_size);
+        self
+    }
+
+    /// Specify the [`mmap_size`](https://www.sqlite.org/pragma.html#pragma_mmap_size)
+    /// in bytes to set when opening a new connection.
+    ///
+    /// See [`ClientBuilder::mmap_size`] for more information.
+    pub fn mmap_size(mut self, mmap_size: i64) -> Self {
+        self.client = self.client.mmap_size(mmap_size);
+        self
+    }
+
+    /// Specify whether to enforce foreign key constraints on every connection
+    /// in the pool.
+    ///
+    /// See [`ClientBuilder::foreign_keys`] for more information.
+    pub fn foreign_keys(mut self, foreign_keys: bool) -> Self {
+        self.client = self.client.foreign_keys(foreign_keys);
+        self
+    }
+
+    /// Specify the maximum number of prepared statements to keep in each
+    /// connection's statement cache.
+    ///
+    /// See [`ClientBuilder::statement_cache_capacity`] for more information.
+    pub fn statement_cache_capacity(mut self, capacity: usize) -> Self {
+        self.client = self.client.statement_cache_capacity(capacity);
+        self
+    }
+
+    /// Specify the [`soft_heap_limit`](https://www.sqlite.org/pragma.html#pragma_soft_heap_limit)
+    /// in bytes to set when opening the pool.
+    ///
+    /// As this limit is global to the process, it's only set by the first
+    /// connection. See [`ClientBuilder::soft_heap_limit`] for more
+    /// information.
+    pub fn soft_heap_limit(mut self, limit: i64) -> Self {
+        self.client = self.client.soft_heap_limit(limit);
+        self
+    }
+
+    /// Specify an arbitrary [pragma](https://www.sqlite.org/pragma.html) to
+    /// set when opening a new connection.
+    ///
+    /// See [`ClientBuilder::pragma`] for more information.
+    pub fn pragma(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.client = self.client.pragma(name, value);
+        self
+    }
+
+    /// Specify a database connection configuration option to enable or
+    /// disable on every connection in the pool.
+    ///
+    /// See [`ClientBuilder::db_config`] for more information.
+    pub fn db_config(mut self, config: DbConfig, enabled: bool) -> Self {
+        self.client = self.client.db_config(config, enabled);
+        self
+    }
+
+    /// Specify whether to verify the resulting values of the configured
+    /// pragmas.
+    ///
+    /// See [`ClientBuilder::verify_pragmas`] for more information.
+    pub fn verify_pragmas(mut self, verify: bool) -> Self {
+        self.client = self.client.verify_pragmas(verify);
         self
     }
 
     /// Specify the name of the [vfs](https://www.sqlite.org/vfs.html) to use.
     pub fn vfs(mut self, vfs: &str) -> Self {
-        self.vfs = Some(vfs.to_owned());
+        self.client = self.client.vfs(vfs);
+        self
+    }
+
+    /// Specify a database file at `path` to
+    /// [attach](https://www.sqlite.org/lang_attach.html) as `alias` on every
+    /// connection in the pool.
+    ///
+    /// See [`ClientBuilder::attach`] for more information.
+    pub fn attach<P: AsRef<Path>>(mut self, path: P, alias: &str) -> Self {
+        self.client = self.client.attach(path, alias);
+        self
+    }
+
+    /// Specify a [runtime extension](https://www.sqlite.org/loadext.html) to
+    /// load on every connection in the pool.
+    ///
+    /// See [`ClientBuilder::extension`] for more information.
+    #[cfg(feature = "load_extension")]
+    pub fn extension<P: AsRef<Path>>(mut self, path: P, entry_point: Option<&str>) -> Self {
+        self.client = self.client.extension(path, entry_point);
+        self
+    }
+
+    /// Specify a callback to run on every connection in the pool when it is
+    /// opened.
+    ///
+    /// See [`ClientBuilder::on_open`] for more information.
+    #[allow(clippy::type_complexity)]
+    pub fn on_open(
+        mut self,
+        on_open: Arc<dyn Fn(&mut Connection) -> Result<(), rusqlite::Error> + Send + Sync>,
+    ) -> Self {
+        self.client = self.client.on_open(on_open);
+        self
+    }
+
+    /// Specify a callback to run on every connection right before it is
+    /// closed.
+    ///
+    /// See [`ClientBuilder::on_close`] for more information.
+    pub fn on_close(mut self, on_close: Arc<dyn Fn(&mut Connection) + Send + Sync>) -> Self {
+        self.client = self.client.on_close(on_close);
+        self
+    }
+
+    /// Specify a busy handler to invoke when the database is locked by
+    /// another connection.
+    ///
+    /// See [`ClientBuilder::busy_handler`] for more information.
+    pub fn busy_handler(mut self, busy_handler: Arc<dyn Fn(i32) -> bool + Send + Sync>) -> Self {
+        self.client = self.client.busy_handler(busy_handler);
+        self
+    }
+
+    /// Specify a time budget for each call made on a connection in the pool,
+    /// after which its running statement is aborted.
+    ///
+    /// See [`ClientBuilder::statement_timeout`] for more information.
+    #[cfg(feature = "hooks")]
+    pub fn statement_timeout(mut self, timeout: Duration) -> Self {
+        self.client = self.client.statement_timeout(timeout);
+        self
+    }
+
+    /// Specify a callback to invoke with the SQL text and execution time of
+    /// every statement run on any connection in the pool.
+    ///
+    /// See [`ClientBuilder::profile`] for more information.
+    #[cfg(feature = "trace")]
+    #[allow(clippy::type_complexity)]
+    pub fn profile(mut self, profile: Arc<dyn Fn(&str, Duration) + Send + Sync>) -> Self {
+        self.client = self.client.profile(profile);
+        self
+    }
+
+    /// Specify a callback to invoke with the expanded SQL text of every
+    /// statement run on any connection in the pool.
+    ///
+    /// See [`ClientBuilder::trace`] for more information.
+    #[cfg(feature = "trace")]
+    pub fn trace(mut self, trace: Arc<dyn Fn(&str) + Send + Sync>) -> Self {
+        self.client = self.client.trace(trace);
+        self
+    }
+
+    /// Specify how many attempts to make when opening a connection fails
+    /// because the database is busy.
+    ///
+    /// This commonly happens when several connections concurrently enable
+    /// [`JournalMode::Wal`] on a new database. See
+    /// [`ClientBuilder::open_retry`] for more information.
+    pub fn open_retry(mut self, attempts: usize, delay: Duration) -> Self {
+        self.client = self.client.open_retry(attempts, delay);
+        self
+    }
+
+    /// Specify the maximum time to wait for each connection in the pool to be
+    /// opened.
+    ///
+    /// See [`ClientBuilder::open_timeout`] for more information.
+    pub fn open_timeout(mut self, timeout: Duration) -> Self {
+        self.client = self.client.open_timeout(timeout);
+        self
+    }
+
+    /// Specify the maximum number of calls that can be queued for each
+    /// connection's worker thread.
+    ///
+    /// See [`ClientBuilder::queue_capacity`] for more information.
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.client = self.client.queue_capacity(capacity);
+        self
+    }
+
+    /// Specify the base name of the worker threads of the connections in the
+    /// pool.
+    ///
+    /// Each thread is named with the index of its connection appended, such
+    /// as `name-3`. Defaults to `async-sqlite`.
+    pub fn thread_name(mut self, name: impl Into<String>) -> Self {
+        self.client = self.client.thread_name(name);
+        self
+    }
+
+    /// Specify the stack size, in bytes, of the worker threads of the
+    /// connections in the pool.
+    ///
+    /// See [`ClientBuilder::thread_stack_size`] for more information.
+    pub fn thread_stack_size(mut self, size: usize) -> Self {
+        self.client = self.client.thread_stack_size(size);
+        self
+    }
+
+    /// Specify whether to transparently reopen a connection in the pool if
+    /// its worker thread has stopped unexpectedly.
+    ///
+    /// See [`ClientBuilder::auto_reconnect`] for more information.
+    pub fn auto_reconnect(mut self, auto_reconnect: bool) -> Self {
+        self.client = self.client.auto_reconnect(auto_reconnect);
+        self
+    }
+
+    /// Specify an interval at which to run a passive
+    /// [WAL checkpoint](https://www.sqlite.org/pragma.html#pragma_wal_checkpoint)
+    /// in the background.
+    ///
+    /// Every `interval`, a background thread checks whether the database has
+    /// been written to since the last checkpoint and, if so, runs a
+    /// [`CheckpointMode::Passive`] checkpoint on the first connection in the
+    /// pool. This keeps the WAL bounded for long-running pools, and is
+    /// typically combined with disabling sqlite's own
+    /// [`wal_autocheckpoint`](https://www.sqlite.org/pragma.html#pragma_wal_autocheckpoint)
+    /// so that checkpoints don't run as part of a commit. As a passive
+    /// checkpoint never waits for writers, the WAL can only be reset once a
+    /// checkpoint has caught up with them, so it may still grow under
+    /// sustained writes. The thread stops once the pool is closed or dropped.
+    pub fn auto_checkpoint(mut self, interval: Duration) -> Self {
+        self.auto_checkpoint = Some(interval);
+        self
+    }
+
+    /// Specify whether to run
+    /// [`PRAGMA optimize`](https://www.sqlite.org/pragma.html#pragma_optimize)
+    /// on each connection right before it is closed, as recommended by
+    /// sqlite, so that the query planner has up-to-date statistics.
+    ///
+    /// The pragma runs after any [`on_close`](PoolBuilder::on_close)
+    /// callback, and any error is ignored. Disabled by default.
+    pub fn auto_optimize(mut self, auto_optimize: bool) -> Self {
+        self.auto_optimize = auto_optimize;
+        self
+    }
+
+    /// Specify an interval at which to run
+    /// [`PRAGMA optimize`](https://www.sqlite.org/pragma.html#pragma_optimize)
+    /// on every connection in the background.
+    ///
+    /// This is useful for long-running pools whose connections are rarely
+    /// closed, and can be combined with
+    /// [`auto_optimize`](PoolBuilder::auto_optimize). Errors, such as the
+    /// database being busy, are ignored until the next tick. The thread stops
+    /// once the pool is closed or dropped.
+    pub fn auto_optimize_interval(mut self, interval: Duration) -> Self {
+        self.auto_optimize_interval = Some(interval);
+        self
+    }
+
+    /// Specify whether to open the pool as a single writer with read-only
+    /// readers, for use with an existing database in WAL mode.
+    ///
+    /// When enabled, the first connection in the pool is opened read-write
+    /// and is the only one that calls like [`Pool::conn_mut`],
+    /// [`Pool::transaction`], [`Pool::execute`], and [`Pool::acquire`] are
+    /// dispatched to. Every other connection is opened with
+    /// [`OpenFlags::SQLITE_OPEN_READ_ONLY`] and serves calls like
+    /// [`Pool::conn`] and [`Pool::query_row`], so reads never take a write
+    /// lock and can't fail with `SQLITE_BUSY` while a write is in progress.
+    /// A pool with a single connection dispatches every call to the writer.
+    ///
+    /// As readers can't create tables, the schema must already exist when
+    /// the pool is opened, and the database should already be in WAL mode.
+    pub fn wal_reader_pool(mut self, wal_reader_pool: bool) -> Self {
+        self.wal_reader_pool = wal_reader_pool;
+        self
+    }
+
+    /// Specify whether to open connections lazily, as they are needed.
+    ///
+    /// When enabled, only the first connection is opened by
+    /// [`open`](PoolBuilder::open). Additional connections, up to
+    /// [`num_conns`](PoolBuilder::num_conns), are opened when a call is made
+    /// while every open connection is busy, which speeds up startup for
+    /// applications that rarely use the full pool. If opening a connection
+    /// fails, the error is returned from the call that triggered it.
+    ///
+    /// Calls that run on every connection, such as [`Pool::conn_for_each`],
+    /// only run on the connections opened so far.
+    pub fn lazy(mut self, lazy: bool) -> Self {
+        self.lazy = lazy;
+        self
+    }
+
+    /// Specify whether connections take calls from a single shared queue.
+    ///
+    /// By default, each call is dispatched up front to the connection with
+    /// the fewest calls in flight, where it waits behind that connection's
+    /// earlier calls even if another connection frees up first. With a
+    /// shared queue, calls like [`Pool::conn`] are instead run by whichever
+    /// connection is free first, which improves tail latency when call
+    /// durations vary widely.
+    ///
+    /// Calls made on a specific connection, such as with [`Pool::conn_on`],
+    /// [`Pool::conn_affinity`] or [`Pool::acquire`], and writes with
+    /// [`wal_reader_pool`](PoolBuilder::wal_reader_pool), are still sent to
+    /// that connection. The shared queue is unbounded regardless of
+    /// [`queue_capacity`](PoolBuilder::queue_capacity), and calls run from it
+    /// aren't counted in [`PoolStats::per_conn_requests`].
+    pub fn shared_queue(mut self, shared_queue: bool) -> Self {
+        self.shared_queue = shared_queue;
         self
     }
 
@@ -96,26 +559,23 @@ impl PoolBuilder {
     /// # }
     /// ```
     pub async fn open(self) -> Result<Pool, Error> {
-        let num_conns = self.get_num_conns();
-        let opens = (0..num_conns).map(|_| {
-            ClientBuilder {
-                path: self.path.clone(),
-                flags: self.flags,
-                journal_mode: self.journal_mode,
-                vfs: self.vfs.clone(),
-            }
-            .open()
-        });
-        let clients = join_all(opens)
-            .await
+        let num_conns = self.get_initial_conns();
+        let queue = self.shared_queue.then(Client::shared_queue);
+        let shared = queue.as_ref().map(|(_, shared)| shared);
+        // The writer is opened before the readers, so that any pragmas that
+        // require write access have been applied by the time they open.
+        let first = if self.wal_reader_pool {
+            vec![self.client_builder(0, shared).open().await?]
+        } else {
+            Vec::new()
+        };
+        let opens = (first.len()..num_conns).map(|i| self.client_builder(i, shared).open());
+        let clients = first
             .into_iter()
+            .map(Ok)
+            .chain(join_all(opens).await)
             .collect::<Result<Vec<Client>, Error>>()?;
-        Ok(Pool {
-            state: Arc::new(State {
-                clients,
-                counter: AtomicU64::new(0),
-            }),
-        })
+        Ok(Pool::new(self, clients, queue))
     }
 
     /// Returns a new [`Pool`] that uses the `PoolBuilder` configuration,
@@ -131,24 +591,45 @@ impl PoolBuilder {
     /// # }
     /// ```
     pub fn open_blocking(self) -> Result<Pool, Error> {
-        let num_conns = self.get_num_conns();
-        let clients = (0..num_conns)
-            .map(|_| {
-                ClientBuilder {
-                    path: self.path.clone(),
-                    flags: self.flags,
-                    journal_mode: self.journal_mode,
-                    vfs: self.vfs.clone(),
-                }
-                .open_blocking()
-            })
+        let queue = self.shared_queue.then(Client::shared_queue);
+        let shared = queue.as_ref().map(|(_, shared)| shared);
+        let clients = (0..self.get_initial_conns())
+            .map(|i| self.client_builder(i, shared).open_blocking())
             .collect::<Result<Vec<Client>, Error>>()?;
-        Ok(Pool {
-            state: Arc::new(State {
-                clients,
-                counter: AtomicU64::new(0),
-            }),
-        })
+        Ok(Pool::new(self, clients, queue))
+    }
+
+    // Returns the configuration for the connection at `index` in the pool,
+    // which takes calls from `shared` if the pool has a shared queue.
+    fn client_builder(&self, index: usize, shared: Option<&SharedQueue>) -> ClientBuilder {
+        let mut builder = self.client.clone();
+        builder.shared_queue = shared.cloned();
+        let name = builder.thread_name.as_deref().unwrap_or("async-sqlite");
+        builder.thread_name = Some(format!("{name}-{index}"));
+        // The limit is global to the process, so it's only set once.
+        if index > 0 {
+            builder.soft_heap_limit = None;
+            if self.wal_reader_pool {
+                builder = builder.read_only(true);
+            }
+        }
+        if self.auto_optimize {
+            let on_close = builder.on_close.take();
+            builder.on_close = Some(Arc::new(move |conn| {
+                if let Some(on_close) = &on_close {
+                    on_close(conn);
+                }
+                _ = conn.execute_batch("PRAGMA optimize");
+            }));
+        }
+        builder
+    }
+
+    fn get_initial_conns(&self) -> usize {
+        if self.lazy {
+            return 1;
+        }
+        self.get_num_conns()
     }
 
     fn get_num_conns(&self) -> usize {
@@ -163,79 +644,1284 @@ impl PoolBuilder {
 /// A simple Pool of sqlite connections.
 ///
 /// A Pool has the same API as an individual [`Client`].
+///
+/// Cloning a `Pool` is cheap, as all clones share the same connections. When
+/// the last clone is dropped, each connection is closed on its worker thread
+/// once any queued work has completed. Use [`Pool::downgrade`] to hold a
+/// reference that doesn't keep the connections open.
 #[derive(Clone)]
 pub struct Pool {
     state: Arc<State>,
 }
 
+impl fmt::Debug for Pool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let num_conns = self.state.conns().len();
+        f.debug_struct("Pool")
+            .field("num_conns", &num_conns)
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+
+/// A non-owning reference to a [`Pool`], as returned by [`Pool::downgrade`].
+///
+/// A `WeakPool` doesn't keep the pool's connections open, so it can be held
+/// by background tasks without preventing the pool from being dropped.
+#[derive(Clone)]
+pub struct WeakPool {
+    state: Weak<State>,
+}
+
+impl WeakPool {
+    /// Returns the [`Pool`] if any of its clones are still alive.
+    pub fn upgrade(&self) -> Option<Pool> {
+        self.state.upgrade().map(|state| Pool { state })
+    }
+}
+
+impl fmt::Debug for WeakPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WeakPool")
+            .field("alive", &(self.state.strong_count() > 0))
+            .finish()
+    }
+}
+
 struct State {
-    clients: Vec<Client>,
+    builder: PoolBuilder,
+    conns: RwLock<Vec<PoolConn>>,
     counter: AtomicU64,
+    shutdown: AtomicBool,
+    // The number of connections the pool may grow to with
+    // `PoolBuilder::lazy`, and the number currently being opened.
+    max_conns: AtomicUsize,
+    opening: AtomicUsize,
+    // Sends calls to the queue shared by every connection with
+    // `PoolBuilder::shared_queue`.
+    queue: Option<PoolConn>,
+    // The receiving end of the shared queue for connections opened later,
+    // which is released when the pool is closed so that calls fail once
+    // every connection has closed.
+    shared: Mutex<Option<SharedQueue>>,
+    // Stops the background threads, if any, when dropped.
+    _stop: Option<Sender<()>>,
 }
 
-impl Pool {
-    /// Invokes the provided function with a [`rusqlite::Connection`].
-    pub async fn conn<F, T>(&self, func: F) -> Result<T, Error>
-    where
-        F: FnOnce(&Connection) -> Result<T, rusqlite::Error> + Send + 'static,
-        T: Send + 'static,
-    {
-        self.get().conn(func).await
+impl State {
+    fn conns(&self) -> RwLockReadGuard<'_, Vec<PoolConn>> {
+        self.conns.read().unwrap_or_else(PoisonError::into_inner)
     }
 
-    /// Invokes the provided function with a mutable [`rusqlite::Connection`].
-    pub async fn conn_mut<F, T>(&self, func: F) -> Result<T, Error>
-    where
-        F: FnOnce(&mut Connection) -> Result<T, rusqlite::Error> + Send + 'static,
-        T: Send + 'static,
-    {
-        self.get().conn_mut(func).await
+    fn conns_mut(&self) -> RwLockWriteGuard<'_, Vec<PoolConn>> {
+        self.conns.write().unwrap_or_else(PoisonError::into_inner)
     }
 
-    /// Closes the underlying sqlite connections.
-    ///
-    /// After this method returns, all calls to `self::conn()` or
-    /// `self::conn_mut()` will return an [`Error::Closed`] error.
-    pub async fn close(&self) -> Result<(), Error> {
-        for client in self.state.clients.iter() {
-            client.close().await?;
+    // Returns the configuration for the connection at `index` in the pool.
+    fn client_builder(&self, index: usize) -> ClientBuilder {
+        let shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+        self.builder.client_builder(index, shared.as_ref())
+    }
+
+    fn release_shared(&self) {
+        self.shared
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+    }
+}
+
+struct PoolConn {
+    client: Client,
+    requests: AtomicU64,
+    in_flight: Arc<AtomicUsize>,
+    leases: Arc<AtomicUsize>,
+}
+
+impl PoolConn {
+    fn new(client: Client) -> Self {
+        Self {
+            client,
+            requests: AtomicU64::new(0),
+            in_flight: Arc::default(),
+            leases: Arc::default(),
         }
-        Ok(())
     }
 
-    /// Invokes the provided function with a [`rusqlite::Connection`], blocking
-    /// the current thread.
-    pub fn conn_blocking<F, T>(&self, func: F) -> Result<T, Error>
+    // Records a request as dispatched to this connection.
+    fn dispatch(&self) -> (Client, InFlight) {
+        self.requests.fetch_add(1, Relaxed);
+        self.in_flight.fetch_add(1, Relaxed);
+        (self.client.clone(), InFlight(self.in_flight.clone()))
+    }
+
+    fn lease(&self) -> PooledConn {
+        self.leases.fetch_add(1, Relaxed);
+        PooledConn {
+            client: self.client.clone(),
+            _lease: Lease(self.leases.clone()),
+        }
+    }
+}
+
+// Tracks a request as in flight on a connection until it is dropped, either
+// after the request has run on the worker thread or when it is discarded.
+struct InFlight(Arc<AtomicUsize>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Relaxed);
+    }
+}
+
+/// A connection leased from a [`Pool`], as returned by [`Pool::acquire`].
+///
+/// All calls made through a `PooledConn` run on the same connection. The
+/// connection is returned to rotation when the `PooledConn` is dropped.
+pub struct PooledConn {
+    client: Client,
+    _lease: Lease,
+}
+
+impl PooledConn {
+    /// Invokes the provided function with the leased
+    /// [`rusqlite::Connection`].
+    pub async fn conn<F, T>(&self, func: F) -> Result<T, Error>
     where
         F: FnOnce(&Connection) -> Result<T, rusqlite::Error> + Send + 'static,
         T: Send + 'static,
     {
-        self.get().conn_blocking(func)
+        self.client.conn(func).await
     }
 
-    /// Invokes the provided function with a mutable [`rusqlite::Connection`],
-    /// blocking the current thread.
-    pub fn conn_mut_blocking<F, T>(&self, func: F) -> Result<T, Error>
+    /// Invokes the provided function with the leased mutable
+    /// [`rusqlite::Connection`].
+    pub async fn conn_mut<F, T>(&self, func: F) -> Result<T, Error>
     where
         F: FnOnce(&mut Connection) -> Result<T, rusqlite::Error> + Send + 'static,
         T: Send + 'static,
     {
-        self.get().conn_mut_blocking(func)
+        self.client.conn_mut(func).await
     }
+}
 
-    /// Closes the underlying sqlite connections, blocking the current thread.
-    ///
-    /// After this method returns, all calls to `self::conn_blocking()` or
-    /// `self::conn_mut_blocking()` will return an [`Error::Closed`] error.
-    pub fn close_blocking(&self) -> Result<(), Error> {
-        self.state
-            .clients
-            .iter()
-            .try_for_each(|client| client.close_blocking())
+impl fmt::Debug for PooledConn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledConn")
+            .field("client", &self.client)
+            .finish()
+    }
+}
+
+// Marks a connection as leased until it is dropped.
+struct Lease(Arc<AtomicUsize>);
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Relaxed);
+    }
+}
+
+/// A snapshot of [`Pool`] utilization, as returned by [`Pool::stats`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PoolStats {
+    /// The number of connections in the pool.
+    pub num_conns: usize,
+    /// The total number of requests dispatched by the pool.
+    pub total_requests: u64,
+    /// The number of requests dispatched to each connection, indexed by
+    /// connection.
+    pub per_conn_requests: Vec<u64>,
+}
+
+impl Pool {
+    fn new(
+        builder: PoolBuilder,
+        clients: Vec<Client>,
+        queue: Option<(Client, SharedQueue)>,
+    ) -> Self {
+        let interval = builder.auto_checkpoint;
+        let optimize_interval = builder.auto_optimize_interval;
+        let max_conns = builder.get_num_conns();
+        let (stop_tx, stop_rx) = bounded(0);
+        let state = Arc::new(State {
+            builder,
+            conns: RwLock::new(clients.into_iter().map(PoolConn::new).collect()),
+            counter: AtomicU64::new(0),
+            shutdown: AtomicBool::new(false),
+            max_conns: AtomicUsize::new(max_conns),
+            opening: AtomicUsize::new(0),
+            shared: Mutex::new(queue.as_ref().map(|(_, shared)| shared.clone())),
+            queue: queue.map(|(client, _)| PoolConn::new(client)),
+            _stop: (interval.is_some() || optimize_interval.is_some()).then_some(stop_tx),
+        });
+        let pool = Pool { state };
+        if let Some(interval) = interval {
+            spawn_auto_checkpoint(pool.downgrade(), interval, stop_rx.clone());
+        }
+        if let Some(interval) = optimize_interval {
+            spawn_auto_optimize(pool.downgrade(), interval, stop_rx);
+        }
+        pool
+    }
+
+    /// Returns a [`WeakPool`] that refers to this pool without keeping its
+    /// connections open.
+    pub fn downgrade(&self) -> WeakPool {
+        WeakPool {
+            state: Arc::downgrade(&self.state),
+        }
+    }
+
+    /// Invokes the provided function with a [`rusqlite::Connection`].
+    pub async fn conn<F, T>(&self, func: F) -> Result<T, Error>
+    where
+        F: FnOnce(&Connection) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (client, in_flight) = self.get(false).await?;
+        client
+            .conn(move |conn| {
+                let _in_flight = in_flight;
+                func(conn)
+            })
+            .await
+    }
+
+    /// Invokes the provided function with a mutable [`rusqlite::Connection`].
+    pub async fn conn_mut<F, T>(&self, func: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Connection) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (client, in_flight) = self.get(true).await?;
+        client
+            .conn_mut(move |conn| {
+                let _in_flight = in_flight;
+                func(conn)
+            })
+            .await
+    }
+
+    /// Invokes the provided function with a [`rusqlite::Connection`].
+    ///
+    /// Maps the result error type to a custom error. See
+    /// [`Client::conn_and_then`] for more information.
+    pub async fn conn_and_then<F, T, E>(&self, func: F) -> Result<T, E>
+    where
+        F: FnOnce(&Connection) -> Result<T, E> + Send + 'static,
+        T: Send + 'static,
+        E: From<rusqlite::Error> + From<Error> + Send + 'static,
+    {
+        let (client, in_flight) = self.get(false).await?;
+        client
+            .conn_and_then(move |conn| {
+                let _in_flight = in_flight;
+                func(conn)
+            })
+            .await
+    }
+
+    /// Invokes the provided function with a mutable [`rusqlite::Connection`].
+    ///
+    /// Maps the result error type to a custom error. See
+    /// [`Client::conn_mut_and_then`] for more information.
+    pub async fn conn_mut_and_then<F, T, E>(&self, func: F) -> Result<T, E>
+    where
+        F: FnOnce(&mut Connection) -> Result<T, E> + Send + 'static,
+        T: Send + 'static,
+        E: From<rusqlite::Error> + From<Error> + Send + 'static,
+    {
+        let (client, in_flight) = self.get(true).await?;
+        client
+            .conn_mut_and_then(move |conn| {
+                let _in_flight = in_flight;
+                func(conn)
+            })
+            .await
+    }
+
+    /// Invokes the provided function within a transaction on a single
+    /// connection.
+    ///
+    /// See [`Client::transaction`] for more information.
+    pub async fn transaction<F, T>(&self, func: F) -> Result<T, Error>
+    where
+        F: FnOnce(&Transaction<'_>) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.conn_mut(move |conn| transaction(conn, func)).await
+    }
+
+    /// Invokes the provided function with the [`rusqlite::Connection`] at
+    /// `index` in the pool.
+    ///
+    /// This is useful to deterministically route calls to a connection with
+    /// its own state, such as one set up using [`Pool::conn_for_each`]. If
+    /// `index` is not less than the number of connections, an
+    /// [`Error::IndexOutOfRange`] error is returned.
+    pub async fn conn_on<F, T>(&self, index: usize, func: F) -> Result<T, Error>
+    where
+        F: FnOnce(&Connection) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (client, in_flight) = self.get_on(index)?;
+        client
+            .conn(move |conn| {
+                let _in_flight = in_flight;
+                func(conn)
+            })
+            .await
+    }
+
+    /// Invokes the provided function with a [`rusqlite::Connection`] chosen
+    /// by hashing `key`, so that calls with the same key run on the same
+    /// connection while the number of connections doesn't change.
+    ///
+    /// This is useful for per-tenant or per-shard workloads, where related
+    /// queries benefit from a warm page cache and cached statements. Calls
+    /// are routed by key regardless of how busy each connection is, so skewed
+    /// keys can unbalance the pool.
+    pub async fn conn_affinity<K, F, T>(&self, key: K, func: F) -> Result<T, Error>
+    where
+        K: Hash,
+        F: FnOnce(&Connection) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let (client, in_flight) = self.get_hashed(hasher.finish())?;
+        client
+            .conn(move |conn| {
+                let _in_flight = in_flight;
+                func(conn)
+            })
+            .await
+    }
+
+    /// Invokes the provided function with each item, distributing the items
+    /// round-robin across the connections in the pool and returning the
+    /// results in item order.
+    ///
+    /// Each item runs as its own call, so the items assigned to different
+    /// connections run concurrently. This is useful for parallel read
+    /// workloads, such as computing aggregates over disjoint id ranges. With
+    /// [`PoolBuilder::wal_reader_pool`], the items are only distributed
+    /// across the readers.
+    pub async fn map_spread<I, F, T>(&self, items: I, func: F) -> Vec<Result<T, Error>>
+    where
+        I: IntoIterator,
+        I::Item: Send + 'static,
+        F: Fn(&Connection, I::Item) -> Result<T, rusqlite::Error> + Send + Sync + 'static,
+        T: Send + 'static,
+    {
+        let func = Arc::new(func);
+        let futures = {
+            let shutdown = self.state.shutdown.load(Relaxed);
+            let conns = self.state.conns();
+            let first = usize::from(self.state.builder.wal_reader_pool && conns.len() > 1);
+            let conns = &conns[first..];
+            items
+                .into_iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    let func = func.clone();
+                    let dispatched = if shutdown {
+                        Err(Error::Closed)
+                    } else {
+                        self.state.counter.fetch_add(1, Relaxed);
+                        Ok(conns[i % conns.len()].dispatch())
+                    };
+                    async move {
+                        let (client, in_flight) = dispatched?;
+                        client
+                            .conn(move |conn| {
+                                let _in_flight = in_flight;
+                                func(conn, item)
+                            })
+                            .await
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        join_all(futures).await
+    }
+
+    /// Invokes the provided function with each [`rusqlite::Connection`] in the
+    /// pool, returning the results in connection order.
+    ///
+    /// This is useful for per-connection setup, such as attaching a database.
+    pub async fn conn_for_each<F, T>(&self, func: F) -> Vec<Result<T, Error>>
+    where
+        F: Fn(&Connection) -> Result<T, rusqlite::Error> + Send + Sync + 'static,
+        T: Send + 'static,
+    {
+        let func = Arc::new(func);
+        let futures = self.fan_out().into_iter().map(|client| {
+            let func = func.clone();
+            async move { client?.conn(move |conn| func(conn)).await }
+        });
+        join_all(futures).await
+    }
+
+    /// Invokes the provided function with each [`rusqlite::Connection`] in the
+    /// pool, running on at most `max_concurrent` connections at a time and
+    /// returning the results in connection order.
+    ///
+    /// This is useful for expensive per-connection setup, such as loading an
+    /// extension, where fanning out to every connection at once could spike
+    /// CPU or IO. A `max_concurrent` of zero is treated as one.
+    pub async fn conn_for_each_limited<F, T>(
+        &self,
+        max_concurrent: usize,
+        func: F,
+    ) -> Vec<Result<T, Error>>
+    where
+        F: Fn(&Connection) -> Result<T, rusqlite::Error> + Send + Sync + 'static,
+        T: Send + 'static,
+    {
+        let func = Arc::new(func);
+        stream::iter(self.fan_out())
+            .map(|client| {
+                let func = func.clone();
+                async move { client?.conn(move |conn| func(conn)).await }
+            })
+            .buffered(max_concurrent.max(1))
+            .collect()
+            .await
+    }
+
+    /// Invokes the provided function with each [`rusqlite::Connection`] in the
+    /// pool, returning the values in connection order or the first error.
+    ///
+    /// The function still runs on every connection if one fails. This is
+    /// useful for all-or-nothing setup steps, where a partial success would
+    /// leave the pool in an inconsistent state.
+    pub async fn try_conn_for_each<F, T>(&self, func: F) -> Result<Vec<T>, Error>
+    where
+        F: Fn(&Connection) -> Result<T, rusqlite::Error> + Send + Sync + 'static,
+        T: Send + 'static,
+    {
+        self.conn_for_each(func).await.into_iter().collect()
+    }
+
+    /// Invokes the provided function with each [`rusqlite::Connection`] in the
+    /// pool, combining the values in connection order using `combine`,
+    /// starting from `init`.
+    ///
+    /// Like [`try_conn_for_each`](Pool::try_conn_for_each), the function runs
+    /// on every connection, and the first error is returned if any fails.
+    pub async fn conn_fold<F, G, T, A>(&self, init: A, func: F, combine: G) -> Result<A, Error>
+    where
+        F: Fn(&Connection) -> Result<T, rusqlite::Error> + Send + Sync + 'static,
+        G: FnMut(A, T) -> A,
+        T: Send + 'static,
+    {
+        Ok(self
+            .try_conn_for_each(func)
+            .await?
+            .into_iter()
+            .fold(init, combine))
+    }
+
+    /// Registers a custom aggregate function on every connection in the pool,
+    /// giving each connection its own clone of `aggr`.
+    ///
+    /// Connections opened later, such as by [`resize`](Pool::resize), won't
+    /// have the function, so prefer [`PoolBuilder::on_open`] if the pool may
+    /// grow. See [`Client::create_aggregate_function`] for details.
+    #[cfg(feature = "functions")]
+    pub async fn create_aggregate_function<A, D, T>(
+        &self,
+        name: &str,
+        n_arg: std::os::raw::c_int,
+        flags: rusqlite::functions::FunctionFlags,
+        aggr: D,
+    ) -> Result<(), Error>
+    where
+        A: std::panic::RefUnwindSafe + std::panic::UnwindSafe,
+        D: rusqlite::functions::Aggregate<A, T> + Clone + Send + Sync + 'static,
+        T: rusqlite::functions::SqlFnOutput,
+    {
+        let name = name.to_owned();
+        self.try_conn_for_each(move |conn| {
+            conn.create_aggregate_function(&name, n_arg, flags, aggr.clone())
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Registers a custom aggregate window function on every connection in
+    /// the pool, giving each connection its own clone of `aggr`.
+    ///
+    /// Connections opened later, such as by [`resize`](Pool::resize), won't
+    /// have the function, so prefer [`PoolBuilder::on_open`] if the pool may
+    /// grow. See [`Client::create_window_function`] for details.
+    #[cfg(feature = "window")]
+    pub async fn create_window_function<A, W, T>(
+        &self,
+        name: &str,
+        n_arg: std::os::raw::c_int,
+        flags: rusqlite::functions::FunctionFlags,
+        aggr: W,
+    ) -> Result<(), Error>
+    where
+        A: std::panic::RefUnwindSafe + std::panic::UnwindSafe,
+        W: rusqlite::functions::WindowAggregate<A, T> + Clone + Send + Sync + 'static,
+        T: rusqlite::functions::SqlFnOutput,
+    {
+        let name = name.to_owned();
+        self.try_conn_for_each(move |conn| {
+            conn.create_window_function(&name, n_arg, flags, aggr.clone())
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Runs `SELECT 1` on every connection in the pool, returning the results
+    /// in connection order.
+    ///
+    /// This is useful for readiness probes that need to report the health of
+    /// each connection. See [`Pool::is_healthy`] for a summary.
+    pub async fn health_check(&self) -> Vec<Result<(), Error>> {
+        self.conn_for_each(|conn| conn.query_row("SELECT 1", [], |_| Ok(())))
+            .await
+    }
+
+    /// Returns `true` if every connection in the pool passes a
+    /// [`health_check`](Pool::health_check).
+    pub async fn is_healthy(&self) -> bool {
+        self.health_check().await.iter().all(Result::is_ok)
+    }
+
+    /// Invokes the provided function with each mutable
+    /// [`rusqlite::Connection`] in the pool, returning the results in
+    /// connection order.
+    ///
+    /// This is intended for per-connection setup that requires a mutable
+    /// connection, such as registering functions. It should not be used to
+    /// mutate data or change the schema, as the statement would run once per
+    /// connection against the same database.
+    pub async fn conn_mut_for_each<F, T>(&self, func: F) -> Vec<Result<T, Error>>
+    where
+        F: Fn(&mut Connection) -> Result<T, rusqlite::Error> + Send + Sync + 'static,
+        T: Send + 'static,
+    {
+        let func = Arc::new(func);
+        let futures = self.fan_out().into_iter().map(|client| {
+            let func = func.clone();
+            async move { client?.conn_mut(move |conn| func(conn)).await }
+        });
+        join_all(futures).await
+    }
+
+    /// Leases a single connection from the pool, so that several dependent
+    /// calls, such as the statements of a transaction spanning multiple
+    /// awaits, all run on the same connection.
+    ///
+    /// While the returned [`PooledConn`] is held, the pool doesn't dispatch
+    /// other requests to its connection, reducing the available capacity of
+    /// the pool. If every connection is leased, requests are dispatched to
+    /// leased connections as a fallback, so a pool should have more
+    /// connections than the number of leases held at once.
+    ///
+    /// With [`PoolBuilder::wal_reader_pool`], the writer is always leased, so
+    /// that the calls made through the lease may write.
+    pub fn acquire(&self) -> Result<PooledConn, Error> {
+        if self.state.builder.wal_reader_pool {
+            return self.select_writer(PoolConn::lease);
+        }
+        let n = self.state.counter.load(Relaxed);
+        self.select(n, PoolConn::lease)
+    }
+
+    /// Finalizes all prepared statements in the statement cache of every
+    /// connection in the pool.
+    ///
+    /// See [`Client::clear_cache`] for more information.
+    pub async fn clear_cache(&self) -> Result<(), Error> {
+        self.conn_for_each(|conn| {
+            conn.flush_prepared_statement_cache();
+            Ok(())
+        })
+        .await
+        .into_iter()
+        .collect()
+    }
+
+    /// Runs a WAL checkpoint on a single connection.
+    ///
+    /// See [`Client::checkpoint`] for more information.
+    pub async fn checkpoint(&self, mode: CheckpointMode) -> Result<CheckpointResult, Error> {
+        self.conn_mut(move |conn| checkpoint(conn, mode)).await
+    }
+
+    /// Rebuilds the database file with `VACUUM` on a single connection.
+    ///
+    /// See [`Client::vacuum`] for more information.
+    pub async fn vacuum(&self) -> Result<(), Error> {
+        self.conn_mut(|conn| conn.execute_batch("VACUUM")).await
+    }
+
+    /// Writes a vacuumed copy of the main database to a new file at `dest` on
+    /// a single connection.
+    ///
+    /// See [`Client::vacuum_into`] for more information.
+    pub async fn vacuum_into<P: AsRef<Path>>(&self, dest: P) -> Result<(), Error> {
+        let dest = dest.as_ref().to_string_lossy().into_owned();
+        self.conn(move |conn| conn.execute("VACUUM INTO ?1", [dest]).map(|_| ()))
+            .await
+    }
+
+    /// Executes the provided statement, returning the number of rows that
+    /// were changed.
+    ///
+    /// See [`Client::execute`] for more information.
+    pub async fn execute<P>(&self, sql: &str, params: P) -> Result<usize, Error>
+    where
+        P: Params + Send + 'static,
+    {
+        let sql = sql.to_owned();
+        self.conn_mut(move |conn| conn.execute(&sql, params)).await
+    }
+
+    /// Executes the provided statement with named parameters, returning the
+    /// number of rows that were changed.
+    ///
+    /// See [`Client::execute_named`] for more information.
+    pub fn execute_named(
+        &self,
+        sql: &str,
+        params: &[(&str, &dyn ToSql)],
+    ) -> impl Future<Output = Result<usize, Error>> + Send + '_ {
+        let sql = sql.to_owned();
+        let params = NamedParams::new(params);
+        async move {
+            let params = params?;
+            self.conn_mut(move |conn| conn.execute(&sql, params.as_params().as_slice()))
+                .await
+        }
     }
 
-    fn get(&self) -> &Client {
+    /// Executes the provided statement with positional parameters built at
+    /// runtime, returning the number of rows that were changed.
+    ///
+    /// See [`Client::execute_dyn`] for more information.
+    pub async fn execute_dyn(&self, sql: &str, params: Vec<Value>) -> Result<usize, Error> {
+        self.execute(sql, params_from_iter(params)).await
+    }
+
+    /// Executes the provided statement, returning the rowid of the most
+    /// recently inserted row.
+    ///
+    /// See [`Client::execute_returning_rowid`] for more information.
+    pub async fn execute_returning_rowid<P>(&self, sql: &str, params: P) -> Result<i64, Error>
+    where
+        P: Params + Send + 'static,
+    {
+        let sql = sql.to_owned();
+        self.conn_mut(move |conn| {
+            conn.execute(&sql, params)?;
+            Ok(conn.last_insert_rowid())
+        })
+        .await
+    }
+
+    /// Executes the provided SQL script on a single connection.
+    ///
+    /// See [`Client::execute_batch`] for more information.
+    pub async fn execute_batch(&self, sql: &str) -> Result<(), Error> {
+        let sql = sql.to_owned();
+        self.conn_mut(move |conn| conn.execute_batch(&sql)).await
+    }
+
+    /// Executes the provided schema change, such as `CREATE TABLE`, once on a
+    /// single connection, then refreshes the schema of every connection in
+    /// the pool.
+    ///
+    /// As the connections share the same database file, the change is
+    /// visible to all of them once it commits. However, each connection only
+    /// reloads its copy of the schema when it next reads from the database,
+    /// and statements prepared against the old schema are only re-prepared
+    /// when they next run. Until then, a statement prepared on another
+    /// connection may report stale metadata, such as the columns of a
+    /// `SELECT *`. This method clears the statement cache of every
+    /// connection and reads the schema on each, so that later statements are
+    /// prepared against the new schema. Pools whose connections each open a
+    /// separate in-memory database don't share their schema.
+    pub async fn execute_ddl(&self, sql: &str) -> Result<(), Error> {
+        self.execute_batch(sql).await?;
+        self.conn_for_each(|conn| {
+            conn.flush_prepared_statement_cache();
+            conn.query_row("SELECT COUNT(*) FROM sqlite_schema", [], |_| Ok(()))
+        })
+        .await
+        .into_iter()
+        .collect()
+    }
+
+    /// Executes the provided SQL script on a single connection, returning
+    /// the total number of rows that were changed.
+    ///
+    /// See [`Client::execute_batch_returning_changes`] for more information.
+    pub async fn execute_batch_returning_changes(&self, sql: &str) -> Result<u64, Error> {
+        let sql = sql.to_owned();
+        self.conn_mut(move |conn| {
+            let before = conn.total_changes();
+            conn.execute_batch(&sql)?;
+            Ok(conn.total_changes() - before)
+        })
+        .await
+    }
+
+    /// Executes the provided query and invokes `f` on the first returned row.
+    ///
+    /// See [`Client::query_row`] for more information.
+    pub async fn query_row<T, P, F>(&self, sql: &str, params: P, f: F) -> Result<T, Error>
+    where
+        P: Params + Send + 'static,
+        F: FnOnce(&Row<'_>) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let sql = sql.to_owned();
+        self.conn(move |conn| conn.query_row(&sql, params, f)).await
+    }
+
+    /// Executes the provided query and invokes `f` on the first returned row,
+    /// returning `None` if the query returns no rows.
+    ///
+    /// See [`Client::query_opt`] for more information.
+    pub async fn query_opt<T, P, F>(&self, sql: &str, params: P, f: F) -> Result<Option<T>, Error>
+    where
+        P: Params + Send + 'static,
+        F: FnOnce(&Row<'_>) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let sql = sql.to_owned();
+        self.conn(move |conn| conn.query_row(&sql, params, f).optional())
+            .await
+    }
+
+    /// Executes the provided query and maps each returned row using `f`,
+    /// collecting the results.
+    ///
+    /// With [`PoolBuilder::wal_reader_pool`], the query runs on a read-only
+    /// connection, so use [`Pool::conn_mut`] for statements that write, such
+    /// as `INSERT ... RETURNING`. See [`Client::query_map`] for more
+    /// information.
+    pub async fn query_map<T, P, F>(&self, sql: &str, params: P, f: F) -> Result<Vec<T>, Error>
+    where
+        P: Params + Send + 'static,
+        F: FnMut(&Row<'_>) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let sql = sql.to_owned();
+        self.conn(move |conn| conn.prepare(&sql)?.query_map(params, f)?.collect())
+            .await
+    }
+
+    /// Executes the provided query with positional parameters built at
+    /// runtime and maps each returned row using `f`, collecting the results.
+    ///
+    /// See [`Client::execute_dyn`] for more information.
+    pub async fn query_map_dyn<T, F>(
+        &self,
+        sql: &str,
+        params: Vec<Value>,
+        f: F,
+    ) -> Result<Vec<T>, Error>
+    where
+        F: FnMut(&Row<'_>) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.query_map(sql, params_from_iter(params), f).await
+    }
+
+    /// Prepares the provided statement on a single connection without
+    /// executing it, returning any error.
+    ///
+    /// See [`Client::validate_sql`] for more information.
+    pub async fn validate_sql(&self, sql: &str) -> Result<(), Error> {
+        let sql = sql.to_owned();
+        self.conn(move |conn| conn.prepare(&sql).map(|_| ())).await
+    }
+
+    /// Executes the provided query with named parameters and invokes `f` on
+    /// the first returned row.
+    ///
+    /// See [`Client::query_row_named`] for more information.
+    pub fn query_row_named<T, F>(
+        &self,
+        sql: &str,
+        params: &[(&str, &dyn ToSql)],
+        f: F,
+    ) -> impl Future<Output = Result<T, Error>> + Send + '_
+    where
+        F: FnOnce(&Row<'_>) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let sql = sql.to_owned();
+        let params = NamedParams::new(params);
+        async move {
+            let params = params?;
+            self.conn(move |conn| conn.query_row(&sql, params.as_params().as_slice(), f))
+                .await
+        }
+    }
+
+    /// Executes the provided query and deserializes each returned row into a
+    /// `T`, collecting the results.
+    ///
+    /// See [`Client::query_as`] for more information.
+    #[cfg(feature = "serde")]
+    pub async fn query_as<T, P>(&self, sql: &str, params: P) -> Result<Vec<T>, Error>
+    where
+        T: serde::de::DeserializeOwned + Send + 'static,
+        P: Params + Send + 'static,
+    {
+        let (client, _in_flight) = self.get(false).await?;
+        client.query_as(sql, params).await
+    }
+
+    /// Executes the provided query on a leased connection, returning a stream
+    /// that lazily maps each returned row using `f`.
+    ///
+    /// The connection is leased for the lifetime of the stream, as with
+    /// [`Pool::acquire`], and returned to rotation once the stream is
+    /// exhausted or dropped. See [`Client::query_stream`] for more
+    /// information.
+    pub async fn query_stream<T, P, F>(
+        &self,
+        sql: &str,
+        params: P,
+        f: F,
+    ) -> BoxStream<'static, Result<T, Error>>
+    where
+        P: Params + Send + 'static,
+        F: FnMut(&Row<'_>) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let n = self.state.counter.load(Relaxed);
+        let conn = match self.select(n, PoolConn::lease) {
+            Ok(conn) => conn,
+            Err(err) => return stream::once(async { Err(err) }).boxed(),
+        };
+        let rows = conn.client.query_stream(sql, params, f).await;
+        // The lease is dropped along with the rows once they're exhausted.
+        stream::unfold(Some((rows, conn)), |state| async move {
+            let (mut rows, conn) = state?;
+            let res = rows.next().await?;
+            Some((res, Some((rows, conn))))
+        })
+        .boxed()
+    }
+
+    /// Closes the underlying sqlite connections.
+    ///
+    /// After this method returns, all calls to `self::conn()` or
+    /// `self::conn_mut()` will return an [`Error::Closed`] error. The pool is
+    /// not consumed, so it can be closed through a shared reference, including
+    /// from any of its clones.
+    pub async fn close(&self) -> Result<(), Error> {
+        self.state.release_shared();
+        for client in self.clients() {
+            client.close().await?;
+        }
+        Ok(())
+    }
+
+    /// Gracefully shuts down the pool, waiting for outstanding work to
+    /// complete.
+    ///
+    /// Unlike [`Pool::close`], which closes each connection in turn while
+    /// requests may still be dispatched to the others, `shutdown` first stops
+    /// the pool from accepting new work so that all subsequent calls return
+    /// an [`Error::Closed`] error. Each connection then runs every request that
+    /// was already dispatched to it before closing.
+    pub async fn shutdown(&self) -> Result<(), Error> {
+        self.state.shutdown.store(true, Relaxed);
+        self.state.release_shared();
+        let clients = self.clients();
+        join_all(clients.iter().map(Client::close))
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    /// Resizes the pool to contain `num_conns` connections, which is clamped
+    /// to a minimum of one.
+    ///
+    /// When growing, new connections are opened using the configuration the
+    /// pool was built with. When shrinking, excess connections are removed
+    /// from rotation immediately, then closed once the requests already
+    /// dispatched to them have completed.
+    ///
+    /// The pool remains usable from other threads while resizing, but
+    /// concurrent calls to `resize` may race with each other.
+    pub async fn resize(&self, num_conns: usize) -> Result<(), Error> {
+        if self.state.shutdown.load(Relaxed) {
+            return Err(Error::Closed);
+        }
+        let num_conns = num_conns.max(1);
+        self.state.max_conns.store(num_conns, Relaxed);
+        let current = self.state.conns().len();
+        if num_conns > current {
+            let opens = (current..num_conns).map(|i| self.state.client_builder(i).open());
+            let clients = join_all(opens)
+                .await
+                .into_iter()
+                .collect::<Result<Vec<Client>, Error>>()?;
+            self.state
+                .conns_mut()
+                .extend(clients.into_iter().map(PoolConn::new));
+        } else {
+            let removed = self.state.conns_mut().split_off(num_conns);
+            for conn in removed {
+                conn.client.close().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Invokes the provided function with a [`rusqlite::Connection`], blocking
+    /// the current thread.
+    pub fn conn_blocking<F, T>(&self, func: F) -> Result<T, Error>
+    where
+        F: FnOnce(&Connection) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (client, in_flight) = self.get_blocking(false)?;
+        client.conn_blocking(move |conn| {
+            let _in_flight = in_flight;
+            func(conn)
+        })
+    }
+
+    /// Invokes the provided function with a mutable [`rusqlite::Connection`],
+    /// blocking the current thread.
+    pub fn conn_mut_blocking<F, T>(&self, func: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Connection) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (client, in_flight) = self.get_blocking(true)?;
+        client.conn_mut_blocking(move |conn| {
+            let _in_flight = in_flight;
+            func(conn)
+        })
+    }
+
+    /// Closes the underlying sqlite connections, blocking the current thread.
+    ///
+    /// After this method returns, all calls to `self::conn_blocking()` or
+    /// `self::conn_mut_blocking()` will return an [`Error::Closed`] error.
+    pub fn close_blocking(&self) -> Result<(), Error> {
+        self.state.release_shared();
+        self.clients()
+            .iter()
+            .try_for_each(|client| client.close_blocking())
+    }
+
+    /// Returns the configured path of the database, or `None` if in-memory
+    /// databases were opened, either by default or with
+    /// [`PoolBuilder::shared_memory`].
+    pub fn path(&self) -> Option<&Path> {
+        if self.state.builder.shared_memory {
+            return None;
+        }
+        self.state.builder.client.path.as_deref()
+    }
+
+    /// Returns the number of connections currently in the pool.
+    pub fn num_conns(&self) -> usize {
+        self.state.conns().len()
+    }
+
+    /// Returns the number of connections in the pool that haven't been
+    /// closed.
+    pub fn size(&self) -> usize {
+        self.state
+            .conns()
+            .iter()
+            .filter(|conn| !conn.client.is_closed())
+            .count()
+    }
+
+    /// Returns the number of open connections in the pool that don't have a
+    /// request in flight.
+    ///
+    /// Like [`stats`](Pool::stats), the count is a snapshot and may be stale
+    /// by the time it is used.
+    pub fn available(&self) -> usize {
+        let idle = self
+            .state
+            .conns()
+            .iter()
+            .filter(|conn| !conn.client.is_closed() && conn.in_flight.load(Relaxed) == 0)
+            .count();
+        idle.saturating_sub(self.queued())
+    }
+
+    /// Returns `true` if every connection in the pool has been closed.
+    ///
+    /// See [`Client::is_closed`] for more information.
+    pub fn is_closed(&self) -> bool {
+        self.state
+            .conns()
+            .iter()
+            .all(|conn| conn.client.is_closed())
+    }
+
+    /// Returns a snapshot of the request counts for the pool.
+    ///
+    /// Counts are updated without synchronization between connections, so a
+    /// snapshot taken while requests are in flight may be slightly stale.
+    ///
+    /// The per-connection counts only cover connections that are currently
+    /// part of the pool, so they may not sum to the total after a
+    /// [`resize`](Pool::resize).
+    pub fn stats(&self) -> PoolStats {
+        let conns = self.state.conns();
+        PoolStats {
+            num_conns: conns.len(),
+            total_requests: self.state.counter.load(Relaxed),
+            per_conn_requests: conns
+                .iter()
+                .map(|conn| conn.requests.load(Relaxed))
+                .collect(),
+        }
+    }
+
+    fn clients(&self) -> Vec<Client> {
+        let conns = self.state.conns();
+        conns.iter().map(|conn| conn.client.clone()).collect()
+    }
+
+    // Returns every client to fan a call out to, or an `Error::Closed` error
+    // in place of each once the pool is shutting down.
+    fn fan_out(&self) -> Vec<Result<Client, Error>> {
+        let shutdown = self.state.shutdown.load(Relaxed);
+        let conns = self.state.conns();
+        conns
+            .iter()
+            .map(|conn| {
+                if shutdown {
+                    return Err(Error::Closed);
+                }
+                Ok(conn.client.clone())
+            })
+            .collect()
+    }
+
+    // Returns the connection to dispatch a call to, first opening a new
+    // connection with `PoolBuilder::lazy` if every connection is busy.
+    async fn get(&self, write: bool) -> Result<(Client, InFlight), Error> {
+        match self.reserve(write) {
+            Some(index) => {
+                let res = self.state.client_builder(index).open().await;
+                self.add_reserved(res)
+            }
+            None => self.dispatch(write),
+        }
+    }
+
+    fn get_blocking(&self, write: bool) -> Result<(Client, InFlight), Error> {
+        match self.reserve(write) {
+            Some(index) => {
+                let res = self.state.client_builder(index).open_blocking();
+                self.add_reserved(res)
+            }
+            None => self.dispatch(write),
+        }
+    }
+
+    // Returns the connection with the fewest in-flight requests, using
+    // round-robin ordering to break ties, or the shared queue with
+    // `PoolBuilder::shared_queue`. Writes are dispatched to the writer with
+    // `PoolBuilder::wal_reader_pool`.
+    fn dispatch(&self, write: bool) -> Result<(Client, InFlight), Error> {
         let n = self.state.counter.fetch_add(1, Relaxed);
-        &self.state.clients[n as usize % self.state.clients.len()]
+        if write && self.state.builder.wal_reader_pool {
+            return self.select_writer(PoolConn::dispatch);
+        }
+        if let Some(queue) = &self.state.queue {
+            if self.state.shutdown.load(Relaxed) {
+                return Err(Error::Closed);
+            }
+            return Ok(queue.dispatch());
+        }
+        self.select(n, PoolConn::dispatch)
+    }
+
+    // Returns the number of calls in flight on the shared queue.
+    fn queued(&self) -> usize {
+        let queue = self.state.queue.as_ref();
+        queue.map_or(0, |queue| queue.in_flight.load(Relaxed))
+    }
+
+    // Reserves the index of a new connection to open if the pool is lazy, not
+    // yet full, and has no idle connection to dispatch the call to.
+    fn reserve(&self, write: bool) -> Option<usize> {
+        let builder = &self.state.builder;
+        if !builder.lazy || (write && builder.wal_reader_pool) {
+            return None;
+        }
+        if self.state.shutdown.load(Relaxed) {
+            return None;
+        }
+        let conns = self.state.conns();
+        let first = usize::from(builder.wal_reader_pool && conns.len() > 1);
+        let idle = conns[first..]
+            .iter()
+            .filter(|conn| conn.leases.load(Relaxed) == 0 && conn.in_flight.load(Relaxed) == 0)
+            .count();
+        if idle > self.queued() {
+            return None;
+        }
+        let index = conns.len() + self.state.opening.fetch_add(1, Relaxed);
+        if index >= self.state.max_conns.load(Relaxed) {
+            self.state.opening.fetch_sub(1, Relaxed);
+            return None;
+        }
+        Some(index)
+    }
+
+    // Adds a connection opened for a reservation to the pool and dispatches
+    // the call to it.
+    fn add_reserved(&self, res: Result<Client, Error>) -> Result<(Client, InFlight), Error> {
+        let mut conns = self.state.conns_mut();
+        self.state.opening.fetch_sub(1, Relaxed);
+        let client = res?;
+        // The client shuts down when dropped if the pool was shut down while
+        // it was being opened.
+        if self.state.shutdown.load(Relaxed) {
+            return Err(Error::Closed);
+        }
+        conns.push(PoolConn::new(client));
+        self.state.counter.fetch_add(1, Relaxed);
+        Ok(conns[conns.len() - 1].dispatch())
+    }
+
+    fn get_on(&self, index: usize) -> Result<(Client, InFlight), Error> {
+        if self.state.shutdown.load(Relaxed) {
+            return Err(Error::Closed);
+        }
+        let conns = self.state.conns();
+        let len = conns.len();
+        let conn = conns
+            .get(index)
+            .ok_or(Error::IndexOutOfRange { index, len })?;
+        self.state.counter.fetch_add(1, Relaxed);
+        Ok(conn.dispatch())
+    }
+
+    // Returns the connection for `hash`, skipping the writer with
+    // `PoolBuilder::wal_reader_pool` if there are readers.
+    fn get_hashed(&self, hash: u64) -> Result<(Client, InFlight), Error> {
+        if self.state.shutdown.load(Relaxed) {
+            return Err(Error::Closed);
+        }
+        let conns = self.state.conns();
+        let first = usize::from(self.state.builder.wal_reader_pool && conns.len() > 1);
+        let index = first + (hash % (conns.len() - first) as u64) as usize;
+        self.state.counter.fetch_add(1, Relaxed);
+        Ok(conns[index].dispatch())
     }
+
+    fn select_writer<F, R>(&self, func: F) -> Result<R, Error>
+    where
+        F: FnOnce(&PoolConn) -> R,
+    {
+        if self.state.shutdown.load(Relaxed) {
+            return Err(Error::Closed);
+        }
+        Ok(func(&self.state.conns()[0]))
+    }
+
+    // Picks the least busy connection, preferring connections that aren't
+    // leased and breaking ties in round-robin order starting from `n`. The
+    // writer is skipped with `PoolBuilder::wal_reader_pool` if there are
+    // readers.
+    fn select<F, R>(&self, n: u64, func: F) -> Result<R, Error>
+    where
+        F: FnOnce(&PoolConn) -> R,
+    {
+        if self.state.shutdown.load(Relaxed) {
+            return Err(Error::Closed);
+        }
+        let conns = self.state.conns();
+        let first = usize::from(self.state.builder.wal_reader_pool && conns.len() > 1);
+        let len = conns.len() - first;
+        let start = n as usize % len;
+        let index = (0..len)
+            .map(|i| first + (start + i) % len)
+            .min_by_key(|&i| {
+                let conn = &conns[i];
+                (conn.leases.load(Relaxed) > 0, conn.in_flight.load(Relaxed))
+            })
+            .unwrap_or(first + start);
+        Ok(func(&conns[index]))
+    }
+}
+
+// Runs a passive checkpoint on the first connection of the pool every
+// `interval` if the database was written to since the last checkpoint, until
+// the pool is closed or dropped.
+fn spawn_auto_checkpoint(pool: WeakPool, interval: Duration, stop: Receiver<()>) {
+    thread::spawn(move || {
+        // The data version changes on commits from other connections, while
+        // the total changes count those made on the connection itself.
+        let mut last: Option<(i64, u64)> = None;
+        while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(interval) {
+            let client = {
+                let Some(pool) = pool.upgrade() else {
+                    return;
+                };
+                if pool.state.shutdown.load(Relaxed) || pool.is_closed() {
+                    return;
+                }
+                let Some(client) = pool.clients().into_iter().next() else {
+                    continue;
+                };
+                client
+            };
+            let res = client.conn_blocking(move |conn| {
+                let version = conn.pragma_query_value(None, "data_version", |row| row.get(0))?;
+                let activity = (version, conn.total_changes());
+                if last != Some(activity) {
+                    checkpoint(conn, CheckpointMode::Passive)?;
+                }
+                Ok(activity)
+            });
+            match res {
+                Ok(activity) => last = Some(activity),
+                Err(Error::Closed) => return,
+                // Retry on the next tick, e.g. if the database was busy.
+                Err(_) => {}
+            }
+        }
+    });
+}
+
+// Runs `PRAGMA optimize` on every connection of the pool every `interval`,
+// until the pool is closed or dropped.
+fn spawn_auto_optimize(pool: WeakPool, interval: Duration, stop: Receiver<()>) {
+    thread::spawn(move || {
+        while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(interval) {
+            let clients = {
+                let Some(pool) = pool.upgrade() else {
+                    return;
+                };
+                if pool.state.shutdown.load(Relaxed) || pool.is_closed() {
+                    return;
+                }
+                pool.clients()
+            };
+            for client in clients {
+                // Retry on the next tick, e.g. if the database was busy.
+                _ = client.conn_blocking(|conn| conn.execute_batch("PRAGMA optimize"));
+            }
+        }
+    });
 }
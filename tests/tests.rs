@@ -1,4 +1,127 @@
-use async_sqlite::{ClientBuilder, Error, JournalMode, PoolBuilder};
+use async_sqlite::{
+    CheckpointMode, ClientBuilder, Error, JournalMode, Migration, Migrations, PoolBuilder,
+    Synchronous, TempStore,
+};
+
+#[test]
+fn test_io_error() {
+    use std::io::ErrorKind;
+
+    let err = std::io::Error::from(Error::Closed);
+    assert_eq!(err.kind(), ErrorKind::Other);
+    assert_eq!(err.to_string(), Error::Closed.to_string());
+    assert!(matches!(
+        err.into_inner().unwrap().downcast::<Error>().as_deref(),
+        Ok(Error::Closed)
+    ));
+
+    let no_rows = Error::Rusqlite(async_sqlite::rusqlite::Error::QueryReturnedNoRows);
+    let msg = no_rows.to_string();
+    let err = std::io::Error::from(no_rows);
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+    assert_eq!(err.to_string(), msg);
+
+    assert_eq!(
+        std::io::Error::from(Error::Timeout).kind(),
+        ErrorKind::TimedOut
+    );
+}
+
+#[test]
+fn test_flag_toggles() {
+    use async_sqlite::rusqlite::OpenFlags;
+
+    let default = OpenFlags::default();
+    let cases = [
+        (
+            ClientBuilder::new().create(false),
+            default - OpenFlags::SQLITE_OPEN_CREATE,
+        ),
+        (ClientBuilder::new().create(false).create(true), default),
+        (
+            ClientBuilder::new().read_write(false),
+            default - OpenFlags::SQLITE_OPEN_READ_WRITE,
+        ),
+        (
+            ClientBuilder::new().no_mutex(false),
+            default - OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        ),
+        (
+            ClientBuilder::new().shared_cache(true),
+            default | OpenFlags::SQLITE_OPEN_SHARED_CACHE,
+        ),
+        (
+            ClientBuilder::new().private_cache(true),
+            default | OpenFlags::SQLITE_OPEN_PRIVATE_CACHE,
+        ),
+        (
+            ClientBuilder::new().shared_cache(true).private_cache(true),
+            default | OpenFlags::SQLITE_OPEN_PRIVATE_CACHE,
+        ),
+        (
+            ClientBuilder::new()
+                .private_cache(true)
+                .private_cache(false),
+            default,
+        ),
+    ];
+    for (builder, flags) in cases {
+        assert_eq!(
+            format!("{builder:?}"),
+            format!("{:?}", ClientBuilder::new().flags(flags))
+        );
+    }
+
+    let pool = PoolBuilder::new()
+        .create(false)
+        .read_write(false)
+        .no_mutex(false)
+        .shared_cache(true);
+    let flags = (default | OpenFlags::SQLITE_OPEN_SHARED_CACHE)
+        - OpenFlags::SQLITE_OPEN_CREATE
+        - OpenFlags::SQLITE_OPEN_READ_WRITE
+        - OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    assert_eq!(
+        format!("{pool:?}"),
+        format!("{:?}", PoolBuilder::new().flags(flags))
+    );
+}
+
+#[test]
+fn test_blocking_reentrant() {
+    let client = ClientBuilder::new()
+        .open_blocking()
+        .expect("client unable to be opened");
+
+    let inner = client.clone();
+    let err = client
+        .conn_blocking(move |_| {
+            Ok(inner
+                .conn_blocking(|conn| conn.execute("SELECT 1", []))
+                .err())
+        })
+        .expect("calling outer function");
+    assert!(matches!(err, Some(Error::Reentrant)));
+
+    let inner = client.clone();
+    let err = client
+        .conn_blocking(move |_| Ok(inner.close_blocking().err()))
+        .expect("calling outer function");
+    assert!(matches!(err, Some(Error::Reentrant)));
+
+    // Other clients can still be used from the worker thread.
+    let other = ClientBuilder::new()
+        .open_blocking()
+        .expect("client unable to be opened");
+    let val: i64 = client
+        .conn_blocking(move |_| {
+            Ok(other.conn_blocking(|conn| conn.query_row("SELECT 1", [], |row| row.get(0))))
+        })
+        .expect("calling outer function")
+        .expect("calling other client");
+    assert_eq!(val, 1);
+    assert!(!client.is_closed());
+}
 
 #[test]
 fn test_blocking_client() {
@@ -60,6 +183,262 @@ fn test_blocking_pool() {
     pool.close_blocking().expect("closing client conn");
 }
 
+#[test]
+fn test_blocking_conn_and_then() {
+    let client = ClientBuilder::new()
+        .open_blocking()
+        .expect("client unable to be opened");
+
+    let vals = client
+        .conn_and_then_blocking(|conn| {
+            let mut stmt = conn.prepare("SELECT 1 UNION ALL SELECT 2")?;
+            let rows =
+                stmt.query_and_then([], |row| -> Result<i64, CustomError> { Ok(row.get(0)?) })?;
+            rows.collect::<Result<Vec<_>, _>>()
+        })
+        .expect("querying rows");
+    assert_eq!(vals, vec![1, 2]);
+
+    let res = client.conn_mut_and_then_blocking(|conn| {
+        let val: i64 = conn.query_row("SELECT -1", [], |row| row.get(0))?;
+        if val < 0 {
+            return Err(CustomError::Negative(val));
+        }
+        Ok(val)
+    });
+    assert!(matches!(res, Err(CustomError::Negative(-1))));
+
+    client.close_blocking().expect("closing client");
+    let res = client.conn_and_then_blocking(|_| Ok::<_, CustomError>(()));
+    assert!(matches!(res, Err(CustomError::Sqlite(Error::Closed))));
+}
+
+#[test]
+fn test_conn_scoped() {
+    let client = ClientBuilder::new()
+        .open_blocking()
+        .expect("client unable to be opened");
+
+    let names = vec!["alpha".to_owned(), "beta".to_owned(), "gamma".to_owned()];
+    client
+        .conn_scoped(|conn| {
+            conn.execute("CREATE TABLE testing (name TEXT NOT NULL)", [])?;
+            let mut stmt = conn.prepare("INSERT INTO testing (name) VALUES (?1)")?;
+            for name in &names {
+                stmt.execute([name])?;
+            }
+            Ok(())
+        })
+        .expect("inserting borrowed names");
+
+    let mut out = Vec::new();
+    let count = client
+        .conn_scoped(|conn| {
+            let mut stmt = conn.prepare("SELECT name FROM testing ORDER BY rowid")?;
+            let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+            for row in rows {
+                out.push(row?);
+            }
+            Ok(out.len())
+        })
+        .expect("reading names into a borrowed vec");
+    assert_eq!(count, 3);
+    assert_eq!(out, names);
+
+    let res = client.conn_scoped(|_| -> Result<(), _> { panic!("scoped panic") });
+    assert!(matches!(res, Err(Error::Panic(msg)) if msg == "scoped panic"));
+
+    client.close_blocking().expect("closing client");
+    let res = client.conn_scoped(|_| Ok(names.len()));
+    assert!(matches!(res, Err(Error::Closed)));
+}
+
+#[test]
+fn test_blocking_query_iter() {
+    let client = ClientBuilder::new()
+        .open_blocking()
+        .expect("client unable to be opened");
+    client
+        .conn_blocking(|conn| {
+            conn.execute_batch(
+                "CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL);
+                WITH RECURSIVE seq(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM seq WHERE n < 10000)
+                INSERT INTO testing SELECT n, 'value' || n FROM seq;",
+            )
+        })
+        .expect("writing schema and seed data");
+
+    let ids = client
+        .query_map_blocking("SELECT id FROM testing ORDER BY id", [], |row| {
+            row.get::<_, i64>(0)
+        })
+        .expect("collecting rows");
+    assert_eq!(ids, (1..=10_000).collect::<Vec<_>>());
+
+    let mut count = 0;
+    let mut sum = 0;
+    for row in client
+        .query_iter("SELECT id, val FROM testing ORDER BY id", [], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })
+        .expect("starting query")
+    {
+        let (id, val) = row.expect("reading row");
+        assert_eq!(val, format!("value{id}"));
+        count += 1;
+        sum += id;
+    }
+    assert_eq!(count, 10_000);
+    assert_eq!(sum, 10_000 * 10_001 / 2);
+
+    // Dropping the iterator early releases the connection.
+    let first: Vec<i64> = client
+        .query_iter("SELECT id FROM testing ORDER BY id", [], |row| row.get(0))
+        .expect("starting query")
+        .take(3)
+        .collect::<Result<_, Error>>()
+        .expect("reading rows");
+    assert_eq!(first, vec![1, 2, 3]);
+    let count: i64 = client
+        .conn_blocking(|conn| conn.query_row("SELECT COUNT(*) FROM testing", [], |row| row.get(0)))
+        .expect("counting rows");
+    assert_eq!(count, 10_000);
+
+    let mut iter = client
+        .query_iter("SELECT * FROM missing", [], |row| row.get::<_, i64>(0))
+        .expect("starting query");
+    assert!(matches!(iter.next(), Some(Err(Error::Rusqlite(_)))));
+    assert!(iter.next().is_none());
+
+    // A panic in the mapper ends the iterator with an `Error::Panic`.
+    let mut iter = client
+        .query_iter("SELECT id FROM testing ORDER BY id", [], |row| {
+            let id: i64 = row.get(0)?;
+            assert!(id < 2, "mapper panicked");
+            Ok(id)
+        })
+        .expect("starting query");
+    assert!(matches!(iter.next(), Some(Ok(1))));
+    assert!(matches!(iter.next(), Some(Err(Error::Panic(msg))) if msg == "mapper panicked"));
+    assert!(iter.next().is_none());
+    assert!(!client.is_closed());
+}
+
+#[test]
+fn test_error_classification() {
+    use async_sqlite::rusqlite::{ffi, ErrorCode};
+
+    let sqlite_err = |code| {
+        Error::Rusqlite(async_sqlite::rusqlite::Error::SqliteFailure(
+            ffi::Error::new(code),
+            None,
+        ))
+    };
+
+    let busy = sqlite_err(ffi::SQLITE_BUSY);
+    assert!(busy.is_busy());
+    assert!(!busy.is_locked());
+    assert!(!busy.is_closed());
+    assert_eq!(busy.sqlite_error_code(), Some(ErrorCode::DatabaseBusy));
+
+    let locked = sqlite_err(ffi::SQLITE_LOCKED);
+    assert!(!locked.is_busy());
+    assert!(locked.is_locked());
+    assert_eq!(locked.sqlite_error_code(), Some(ErrorCode::DatabaseLocked));
+
+    let readonly = sqlite_err(ffi::SQLITE_READONLY);
+    assert!(!readonly.is_busy());
+    assert!(!readonly.is_locked());
+    assert_eq!(readonly.sqlite_error_code(), Some(ErrorCode::ReadOnly));
+
+    let no_rows = Error::Rusqlite(async_sqlite::rusqlite::Error::QueryReturnedNoRows);
+    assert!(!no_rows.is_busy());
+    assert_eq!(no_rows.sqlite_error_code(), None);
+
+    assert!(Error::Closed.is_closed());
+    assert!(!Error::Closed.is_busy());
+    assert_eq!(Error::Closed.sqlite_error_code(), None);
+
+    let pragma = Error::PragmaUpdate {
+        name: "journal_mode".into(),
+        exp: "wal".into(),
+        got: "memory".to_owned(),
+    };
+    assert!(!pragma.is_closed());
+    assert_eq!(pragma.sqlite_error_code(), None);
+}
+
+#[test]
+fn test_error_source() {
+    use async_sqlite::rusqlite;
+    use std::error::Error as _;
+
+    let err = Error::Rusqlite(rusqlite::Error::QueryReturnedNoRows);
+    let source = err.source().expect("rusqlite error has a source");
+    assert!(matches!(
+        source.downcast_ref::<rusqlite::Error>(),
+        Some(rusqlite::Error::QueryReturnedNoRows)
+    ));
+
+    // Variants that don't wrap another error have no source.
+    let errs = [
+        Error::Closed,
+        Error::PragmaUpdate {
+            name: "journal_mode".into(),
+            exp: "wal".into(),
+            got: "memory".to_owned(),
+        },
+        Error::PragmaUpdate {
+            name: "cache_size".into(),
+            exp: "100".to_owned().into(),
+            got: "200".to_owned(),
+        },
+        Error::Timeout,
+        Error::Panic("boom".to_owned()),
+        Error::IndexOutOfRange { index: 2, len: 1 },
+        Error::Reentrant,
+    ];
+    for err in errs {
+        assert!(err.source().is_none(), "{err:?} has a source");
+    }
+
+    let boxed: Box<dyn std::error::Error + Send + Sync> =
+        Box::new(Error::Rusqlite(rusqlite::Error::QueryReturnedNoRows));
+    assert!(boxed.source().is_some());
+    assert!(boxed.downcast_ref::<Error>().is_some());
+}
+
+#[test]
+fn test_error_rusqlite_access() {
+    use async_sqlite::rusqlite::{self, ffi};
+
+    let busy = Error::Rusqlite(rusqlite::Error::SqliteFailure(
+        ffi::Error::new(ffi::SQLITE_BUSY),
+        None,
+    ));
+    assert!(matches!(
+        busy.as_rusqlite(),
+        Some(rusqlite::Error::SqliteFailure(err, None)) if err.extended_code == ffi::SQLITE_BUSY
+    ));
+    assert!(matches!(
+        busy.into_rusqlite(),
+        Ok(rusqlite::Error::SqliteFailure(err, None)) if err.extended_code == ffi::SQLITE_BUSY
+    ));
+
+    let no_rows = Error::Rusqlite(rusqlite::Error::QueryReturnedNoRows);
+    assert!(matches!(
+        no_rows.as_rusqlite(),
+        Some(rusqlite::Error::QueryReturnedNoRows)
+    ));
+
+    assert!(Error::Closed.as_rusqlite().is_none());
+    assert!(matches!(Error::Closed.into_rusqlite(), Err(Error::Closed)));
+    assert!(matches!(
+        Error::Timeout.into_rusqlite(),
+        Err(Error::Timeout)
+    ));
+}
+
 macro_rules! async_test {
     ($name:ident) => {
         paste::item! {
@@ -81,8 +460,120 @@ macro_rules! async_test {
 }
 
 async_test!(test_journal_mode);
+async_test!(test_synchronous);
 async_test!(test_concurrency);
+async_test!(test_drop_closes);
+async_test!(test_from_conn);
+async_test!(test_path);
+async_test!(test_panic);
+async_test!(test_conn_retry);
+async_test!(test_savepoint);
+async_test!(test_transaction);
+#[cfg(feature = "blob")]
+async_test!(test_blob);
 async_test!(test_pool);
+async_test!(test_pool_stats);
+async_test!(test_pool_least_busy);
+async_test!(test_pool_acquire);
+async_test!(test_pool_shared_memory);
+async_test!(test_pool_conn_on);
+async_test!(test_pool_conn_and_then);
+async_test!(test_query_row);
+async_test!(test_query_opt);
+async_test!(test_query_map);
+async_test!(test_validate_sql);
+async_test!(test_execute);
+async_test!(test_named_params);
+async_test!(test_execute_returning);
+async_test!(test_execute_batch_params);
+async_test!(test_execute_batch);
+async_test!(test_pool_shutdown);
+async_test!(test_pool_resize);
+async_test!(test_pool_open_retry);
+async_test!(test_open_timeout);
+async_test!(test_is_closed);
+async_test!(test_vacuum);
+async_test!(test_auto_checkpoint);
+async_test!(test_pool_close_shared);
+async_test!(test_auto_reconnect);
+async_test!(test_checkpoint);
+async_test!(test_clear_cache);
+async_test!(test_statement_cache_capacity);
+async_test!(test_soft_heap_limit);
+async_test!(test_cache_size);
+async_test!(test_pragmas);
+async_test!(test_verify_pragmas);
+async_test!(test_read_only);
+async_test!(test_must_exist);
+async_test!(test_uri);
+async_test!(test_full_mutex);
+#[cfg(feature = "hooks")]
+async_test!(test_hooks);
+#[cfg(feature = "hooks")]
+async_test!(test_authorizer);
+#[cfg(feature = "hooks")]
+async_test!(test_progress_handler);
+#[cfg(feature = "hooks")]
+async_test!(test_statement_timeout);
+#[cfg(feature = "preupdate_hook")]
+async_test!(test_preupdate_hook);
+#[cfg(feature = "backup")]
+async_test!(test_backup);
+#[cfg(feature = "backup")]
+async_test!(test_restore);
+#[cfg(feature = "load_extension")]
+async_test!(test_extension_load_failure);
+#[cfg(feature = "tracing")]
+async_test!(test_tracing);
+#[cfg(feature = "serde")]
+async_test!(test_query_as);
+#[cfg(feature = "trace")]
+async_test!(test_profile);
+#[cfg(feature = "trace")]
+async_test!(test_trace);
+#[cfg(feature = "trace")]
+async_test!(test_auto_optimize);
+#[cfg(feature = "functions")]
+async_test!(test_on_open);
+async_test!(test_builder_clone);
+async_test!(test_foreign_keys);
+async_test!(test_db_config);
+#[cfg(feature = "functions")]
+async_test!(test_pool_conn_for_each);
+async_test!(test_pool_try_conn_for_each);
+async_test!(test_pool_health_check);
+async_test!(test_temp_store);
+async_test!(test_mmap_size);
+async_test!(test_conn_for_each_limited);
+async_test!(test_migrate);
+async_test!(test_migrate_partial);
+async_test!(test_migrate_failure);
+async_test!(test_pool_available);
+async_test!(test_bulk_insert);
+async_test!(test_queue_capacity);
+#[cfg(feature = "serialize")]
+async_test!(test_serialize);
+async_test!(test_page_size);
+async_test!(test_pool_conn_fold);
+async_test!(test_attach);
+async_test!(test_query_stream);
+async_test!(test_drop_interrupts);
+async_test!(test_wal_reader_pool);
+async_test!(test_pool_lazy);
+async_test!(test_conn_affinity);
+async_test!(test_on_close);
+async_test!(test_close_timeout);
+async_test!(test_map_spread);
+async_test!(test_busy_handler);
+async_test!(test_execute_ddl);
+async_test!(test_dyn_params);
+async_test!(test_weak_pool);
+async_test!(test_thread_name);
+async_test!(test_shared_queue);
+#[cfg(feature = "functions")]
+async_test!(test_aggregate_function);
+#[cfg(feature = "window")]
+async_test!(test_window_function);
 
 async fn test_journal_mode() {
     let tmp_dir = tempfile::tempdir().unwrap();
@@ -99,6 +590,28 @@ async fn test_journal_mode() {
     assert_eq!(mode, "wal");
 }
 
+async fn test_synchronous() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let client = ClientBuilder::new()
+        .journal_mode(JournalMode::Wal)
+        .synchronous(Synchronous::Normal)
+        .path(tmp_dir.path().join("sqlite.db"))
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let (mode, synchronous): (String, i64) = client
+        .conn(|conn| {
+            Ok((
+                conn.query_row("PRAGMA journal_mode", (), |row| row.get(0))?,
+                conn.query_row("PRAGMA synchronous", (), |row| row.get(0))?,
+            ))
+        })
+        .await
+        .expect("client unable to fetch pragmas");
+    assert_eq!(mode, "wal");
+    assert_eq!(synchronous, Synchronous::Normal as i64);
+}
+
 async fn test_concurrency() {
     let tmp_dir = tempfile::tempdir().unwrap();
     let client = ClientBuilder::new()
@@ -133,6 +646,376 @@ async fn test_concurrency() {
         .expect("collecting query results");
 }
 
+async fn test_drop_closes() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let path = tmp_dir.path().join("sqlite.db");
+    let client = ClientBuilder::new()
+        .journal_mode(JournalMode::Wal)
+        .path(&path)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute(
+            "CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL)",
+            [],
+        )
+        .await
+        .expect("writing schema");
+
+    let wal_path = tmp_dir.path().join("sqlite.db-wal");
+    assert!(wal_path.exists());
+    let clone = client.clone();
+    drop(client);
+    clone
+        .execute("INSERT INTO testing VALUES (1, 'value1')", [])
+        .await
+        .expect("clone still usable after drop");
+    drop(clone);
+
+    // The connection is closed asynchronously on the worker thread.
+    for _ in 0..100 {
+        if !wal_path.exists() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(!wal_path.exists());
+
+    let client = ClientBuilder::new()
+        .path(&path)
+        .open()
+        .await
+        .expect("client unable to be reopened");
+    let count: i64 = client
+        .query_row("SELECT COUNT(*) FROM testing", [], |row| row.get(0))
+        .await
+        .expect("counting rows");
+    assert_eq!(count, 1);
+}
+
+async fn test_from_conn() {
+    let conn = async_sqlite::rusqlite::Connection::open_in_memory().unwrap();
+    conn.execute_batch(
+        "CREATE TABLE testing (id INTEGER PRIMARY KEY); INSERT INTO testing VALUES (1), (2);",
+    )
+    .unwrap();
+
+    let client = async_sqlite::Client::from_conn(conn);
+    let count: i64 = client
+        .query_row("SELECT COUNT(*) FROM testing", [], |row| row.get(0))
+        .await
+        .expect("querying existing connection");
+    assert_eq!(count, 2);
+
+    client.close().await.expect("closing client");
+    assert!(client.is_closed());
+}
+
+async fn test_path() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let path = tmp_dir.path().join("sqlite.db");
+
+    let client = ClientBuilder::new()
+        .path(&path)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    assert_eq!(client.path(), Some(path.as_path()));
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    assert_eq!(client.path(), None);
+
+    let conn = async_sqlite::rusqlite::Connection::open(&path).unwrap();
+    let client = async_sqlite::Client::from_conn(conn);
+    assert_eq!(client.path(), Some(path.as_path()));
+
+    let pool = PoolBuilder::new()
+        .path(&path)
+        .num_conns(3)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    assert_eq!(pool.path(), Some(path.as_path()));
+    assert_eq!(pool.num_conns(), 3);
+    pool.resize(1).await.expect("resizing pool");
+    assert_eq!(pool.num_conns(), 1);
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    assert_eq!(pool.path(), None);
+
+    // Named in-memory databases have no path either.
+    let name = format!("test_path_{:?}", std::thread::current().id());
+    let pool = PoolBuilder::new()
+        .shared_memory(&name)
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    assert_eq!(pool.path(), None);
+    let pool = PoolBuilder::new()
+        .shared_memory(&name)
+        .path(&path)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    assert_eq!(pool.path(), Some(path.as_path()));
+}
+
+async fn test_panic() {
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute_batch("CREATE TABLE testing (id INTEGER PRIMARY KEY);")
+        .await
+        .expect("writing schema");
+
+    let res = client.conn(|_| -> Result<(), _> { panic!("boom") }).await;
+    assert!(matches!(res, Err(Error::Panic(msg)) if msg == "boom"));
+    let res =
+        client.conn_mut_blocking(|_| -> Result<(), _> { panic!("{} {}", "formatted", "boom") });
+    assert!(matches!(res, Err(Error::Panic(msg)) if msg == "formatted boom"));
+    let res: Result<(), Error> = client.conn_and_then(|_| std::panic::panic_any(42)).await;
+    assert!(matches!(res, Err(Error::Panic(msg)) if msg == "unknown panic"));
+
+    // The worker thread is still alive, and the connection is unchanged.
+    client
+        .execute("INSERT INTO testing VALUES (1)", [])
+        .await
+        .expect("inserting after panic");
+    assert!(!client.is_closed());
+}
+
+async fn test_conn_retry() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let path = tmp_dir.path().join("sqlite.db");
+    let writer = ClientBuilder::new()
+        .path(&path)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    writer
+        .execute_batch(
+            "CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL);
+            BEGIN EXCLUSIVE;
+            INSERT INTO testing VALUES (1, 'value1');",
+        )
+        .await
+        .expect("starting exclusive transaction");
+
+    // Disable rusqlite's default busy timeout so the insert fails immediately.
+    let client = ClientBuilder::new()
+        .path(&path)
+        .pragma("busy_timeout", "0")
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let attempts_func = attempts.clone();
+    let retry = client.conn_retry(10, move |conn| {
+        attempts_func.fetch_add(1, Ordering::SeqCst);
+        conn.execute("INSERT INTO testing VALUES (2, 'value2')", [])
+    });
+    let commit = writer.conn(|conn| {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        conn.execute_batch("COMMIT")
+    });
+    let (retry, commit) = futures_util::future::join(retry, commit).await;
+    commit.expect("committing exclusive transaction");
+    assert_eq!(retry.expect("retrying insert"), 1);
+    assert!(attempts.load(Ordering::SeqCst) > 1);
+
+    // Errors that aren't busy or locked aren't retried.
+    attempts.store(0, Ordering::SeqCst);
+    let attempts_func = attempts.clone();
+    let res = client
+        .conn_retry(10, move |conn| {
+            attempts_func.fetch_add(1, Ordering::SeqCst);
+            conn.execute("INSERT INTO testing VALUES (1, 'duplicate')", [])
+        })
+        .await;
+    assert!(matches!(res, Err(Error::Rusqlite(_))));
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+}
+
+async fn test_transaction() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let pool = PoolBuilder::new()
+        .path(tmp_dir.path().join("sqlite.db"))
+        .journal_mode(JournalMode::Wal)
+        .num_conns(4)
+        .open_blocking()
+        .expect("pool unable to be opened");
+    pool.execute_batch(
+        "CREATE TABLE accounts (id INTEGER PRIMARY KEY, balance INTEGER NOT NULL);
+        INSERT INTO accounts VALUES (1, 100), (2, 100);",
+    )
+    .await
+    .expect("writing schema and seed data");
+
+    // Concurrently move funds between accounts; the total is unchanged after
+    // every transaction.
+    let transfers = (0..20).map(|i| {
+        let pool = pool.clone();
+        async move {
+            let (from, to) = if i % 2 == 0 { (1, 2) } else { (2, 1) };
+            pool.transaction(move |tx| {
+                tx.execute(
+                    "UPDATE accounts SET balance = balance - 10 WHERE id = ?",
+                    [from],
+                )?;
+                tx.execute(
+                    "UPDATE accounts SET balance = balance + 10 WHERE id = ?",
+                    [to],
+                )?;
+                tx.query_row("SELECT SUM(balance) FROM accounts", [], |row| {
+                    row.get::<_, i64>(0)
+                })
+            })
+            .await
+        }
+    });
+    let totals = futures_util::future::join_all(transfers).await;
+    assert!(totals.into_iter().all(|total| total.unwrap() == 200));
+
+    let res = pool
+        .transaction(|tx| {
+            tx.execute("UPDATE accounts SET balance = 0 WHERE id = 1", [])?;
+            tx.execute("INSERT INTO accounts VALUES (1, 0)", [])
+        })
+        .await;
+    assert!(res.is_err());
+
+    let client = ClientBuilder::new()
+        .path(tmp_dir.path().join("sqlite.db"))
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let balances = client
+        .transaction(|tx| {
+            let mut stmt = tx.prepare("SELECT balance FROM accounts ORDER BY id")?;
+            let rows = stmt.query_map([], |row| row.get::<_, i64>(0))?;
+            rows.collect::<Result<Vec<_>, _>>()
+        })
+        .await
+        .expect("reading balances");
+    assert_eq!(balances, vec![100, 100]);
+}
+
+async fn test_savepoint() {
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute_batch(
+            "CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL);
+            BEGIN;
+            INSERT INTO testing VALUES (1, 'outer');",
+        )
+        .await
+        .expect("starting outer transaction");
+
+    let res = client
+        .savepoint("inner", |sp| {
+            sp.execute("INSERT INTO testing VALUES (2, 'inner')", [])?;
+            sp.execute("INSERT INTO testing VALUES (1, 'duplicate')", [])
+        })
+        .await;
+    assert!(matches!(res, Err(Error::Rusqlite(_))));
+
+    client
+        .savepoint("nested", |sp| {
+            sp.execute("INSERT INTO testing VALUES (3, 'nested')", [])?;
+            let mut inner = sp.savepoint()?;
+            inner.execute("INSERT INTO testing VALUES (4, 'discarded')", [])?;
+            inner.rollback()
+        })
+        .await
+        .expect("running nested savepoint");
+
+    client
+        .execute_batch("COMMIT")
+        .await
+        .expect("committing outer transaction");
+
+    let vals = client
+        .conn(|conn| {
+            conn.prepare("SELECT val FROM testing ORDER BY id")?
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .await
+        .expect("querying rows");
+    assert_eq!(vals, vec!["outer", "nested"]);
+}
+
+#[cfg(feature = "blob")]
+async fn test_blob() {
+    use futures_util::StreamExt;
+
+    const SIZE: usize = 1024 * 1024;
+    const CHUNK: usize = 64 * 1024;
+
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute_batch(
+            "CREATE TABLE blobs (id INTEGER PRIMARY KEY, data BLOB NOT NULL);
+            INSERT INTO blobs VALUES (1, zeroblob(1048576));",
+        )
+        .await
+        .expect("writing schema and seed data");
+
+    let data: Vec<u8> = (0..SIZE).map(|i| (i % 251) as u8).collect();
+    let chunks: Vec<Vec<u8>> = data.chunks(CHUNK).map(<[u8]>::to_vec).collect();
+    client
+        .blob_write("main", "blobs", "data", 1, chunks)
+        .await
+        .expect("writing blob");
+
+    let mut stream = client
+        .blob_read("main", "blobs", "data", 1, true, CHUNK)
+        .await
+        .expect("opening blob");
+    let mut read = Vec::with_capacity(SIZE);
+    let mut num_chunks = 0;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.expect("reading chunk");
+        assert!(chunk.len() <= CHUNK);
+        read.extend_from_slice(&chunk);
+        num_chunks += 1;
+    }
+    assert_eq!(num_chunks, SIZE / CHUNK);
+    assert!(read == data);
+    drop(stream);
+
+    // Writing past the end of the blob fails.
+    let res = client
+        .blob_write("main", "blobs", "data", 1, [vec![0; SIZE + 1]])
+        .await;
+    assert!(matches!(res, Err(Error::Rusqlite(_))));
+
+    let res = client
+        .blob_read("main", "blobs", "data", 2, false, CHUNK)
+        .await;
+    assert!(matches!(res, Err(Error::Rusqlite(_))));
+}
+
 async fn test_pool() {
     let tmp_dir = tempfile::tempdir().unwrap();
     let pool = PoolBuilder::new()
@@ -166,3 +1049,4137 @@ async fn test_pool() {
         .collect::<Result<(), Error>>()
         .expect("collecting query results");
 }
+
+#[cfg(feature = "hooks")]
+async fn test_hooks() {
+    use async_sqlite::rusqlite::hooks::Action;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .conn(|conn| {
+            conn.execute(
+                "CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL)",
+                (),
+            )
+        })
+        .await
+        .expect("writing schema");
+
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    let commits = Arc::new(AtomicUsize::new(0));
+    let rollbacks = Arc::new(AtomicUsize::new(0));
+
+    let updates_hook = updates.clone();
+    client
+        .update_hook(Some(move |action, _db: &str, table: &str, rowid| {
+            updates_hook
+                .lock()
+                .unwrap()
+                .push((action, table.to_owned(), rowid));
+        }))
+        .await
+        .expect("registering update hook");
+    let commits_hook = commits.clone();
+    client
+        .commit_hook(Some(move || {
+            commits_hook.fetch_add(1, Ordering::SeqCst);
+            false
+        }))
+        .await
+        .expect("registering commit hook");
+    let rollbacks_hook = rollbacks.clone();
+    client
+        .rollback_hook(Some(move || {
+            rollbacks_hook.fetch_add(1, Ordering::SeqCst);
+        }))
+        .await
+        .expect("registering rollback hook");
+
+    client
+        .conn_mut(|conn| {
+            conn.execute("INSERT INTO testing VALUES (1, ?)", ["value1"])?;
+            conn.execute("UPDATE testing SET val = ? WHERE id = 1", ["value2"])?;
+            let tx = conn.transaction()?;
+            tx.execute("INSERT INTO testing VALUES (2, ?)", ["value3"])?;
+            tx.rollback()
+        })
+        .await
+        .expect("writing rows");
+
+    assert_eq!(
+        *updates.lock().unwrap(),
+        vec![
+            (Action::SQLITE_INSERT, "testing".to_owned(), 1),
+            (Action::SQLITE_UPDATE, "testing".to_owned(), 1),
+            (Action::SQLITE_INSERT, "testing".to_owned(), 2),
+        ]
+    );
+    assert_eq!(commits.load(Ordering::SeqCst), 2);
+    assert_eq!(rollbacks.load(Ordering::SeqCst), 1);
+
+    client
+        .update_hook(None::<fn(Action, &str, &str, i64)>)
+        .await
+        .expect("removing update hook");
+    client
+        .conn(|conn| conn.execute("DELETE FROM testing", ()))
+        .await
+        .expect("deleting rows");
+    assert_eq!(updates.lock().unwrap().len(), 3);
+}
+
+#[cfg(feature = "hooks")]
+async fn test_authorizer() {
+    use async_sqlite::rusqlite::hooks::{AuthAction, AuthContext, Authorization};
+
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute_batch("CREATE TABLE testing (id INTEGER PRIMARY KEY);")
+        .await
+        .expect("writing schema");
+
+    client
+        .set_authorizer(Some(
+            |ctx: async_sqlite::rusqlite::hooks::AuthContext<'_>| match ctx.action {
+                AuthAction::DropTable { .. } => Authorization::Deny,
+                _ => Authorization::Allow,
+            },
+        ))
+        .await
+        .expect("setting authorizer");
+
+    let res = client
+        .conn(|conn| conn.prepare("DROP TABLE testing").map(|_| ()))
+        .await;
+    assert_eq!(
+        res.unwrap_err().sqlite_error_code(),
+        Some(async_sqlite::rusqlite::ErrorCode::AuthorizationForStatementDenied)
+    );
+    client
+        .execute("INSERT INTO testing VALUES (1)", [])
+        .await
+        .expect("inserting row");
+
+    client
+        .set_authorizer(None::<fn(AuthContext<'_>) -> Authorization>)
+        .await
+        .expect("removing authorizer");
+    client
+        .execute("DROP TABLE testing", [])
+        .await
+        .expect("dropping table");
+}
+
+#[cfg(feature = "hooks")]
+async fn test_progress_handler() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    client
+        .set_progress_handler(
+            100,
+            Some(move || counter.fetch_add(1, Ordering::SeqCst) + 1 >= 5),
+        )
+        .await
+        .expect("setting progress handler");
+
+    let heavy = "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c LIMIT 10000000)
+        SELECT COUNT(*) FROM c";
+    let res = client
+        .query_row(heavy, [], |row| row.get::<_, i64>(0))
+        .await;
+    assert_eq!(
+        res.unwrap_err().sqlite_error_code(),
+        Some(async_sqlite::rusqlite::ErrorCode::OperationInterrupted)
+    );
+    assert_eq!(calls.load(Ordering::SeqCst), 5);
+
+    client
+        .set_progress_handler(0, None::<fn() -> bool>)
+        .await
+        .expect("removing progress handler");
+    let count: i64 = client
+        .query_row(
+            "SELECT COUNT(*) FROM (SELECT 1 UNION ALL SELECT 2)",
+            [],
+            |row| row.get(0),
+        )
+        .await
+        .expect("querying without progress handler");
+    assert_eq!(count, 2);
+    assert_eq!(calls.load(Ordering::SeqCst), 5);
+}
+
+#[cfg(feature = "backup")]
+async fn test_backup() {
+    use std::sync::{Arc, Mutex};
+
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .conn(|conn| {
+            conn.execute_batch(
+                "CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL);
+                INSERT INTO testing VALUES (1, 'value1'), (2, 'value2'), (3, 'value3');",
+            )
+        })
+        .await
+        .expect("writing schema and seed data");
+
+    let dest = tmp_dir.path().join("backup.db");
+    client.backup_to(&dest).await.expect("backing up database");
+
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let progress_cb = progress.clone();
+    let dest_progress = tmp_dir.path().join("backup_progress.db");
+    client
+        .backup_to_with_progress(&dest_progress, 1, std::time::Duration::ZERO, move |p| {
+            progress_cb.lock().unwrap().push(p.remaining)
+        })
+        .await
+        .expect("backing up database with progress");
+    assert_eq!(progress.lock().unwrap().last(), Some(&0));
+
+    for path in [dest, dest_progress] {
+        let backup = ClientBuilder::new()
+            .path(path)
+            .open()
+            .await
+            .expect("backup unable to be opened");
+        let count: i64 = backup
+            .conn(|conn| conn.query_row("SELECT COUNT(*) FROM testing", (), |row| row.get(0)))
+            .await
+            .expect("counting rows");
+        assert_eq!(count, 3);
+    }
+}
+
+#[cfg(feature = "backup")]
+async fn test_restore() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .conn(|conn| {
+            conn.execute_batch(
+                "CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL);
+                INSERT INTO testing VALUES (1, 'value1'), (2, 'value2');",
+            )
+        })
+        .await
+        .expect("writing schema and seed data");
+    let snapshot = tmp_dir.path().join("snapshot.db");
+    client
+        .backup_to(&snapshot)
+        .await
+        .expect("backing up database");
+
+    let path = tmp_dir.path().join("sqlite.db");
+    let restored = ClientBuilder::new()
+        .journal_mode(JournalMode::Wal)
+        .path(&path)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    restored
+        .restore_from(&snapshot)
+        .await
+        .expect("restoring database");
+    let count: i64 = restored
+        .conn(|conn| conn.query_row("SELECT COUNT(*) FROM testing", (), |row| row.get(0)))
+        .await
+        .expect("counting rows");
+    assert_eq!(count, 2);
+    let wal_len = std::fs::metadata(tmp_dir.path().join("sqlite.db-wal"))
+        .map(|m| m.len())
+        .unwrap_or(0);
+    assert_eq!(wal_len, 0);
+
+    let res = restored
+        .restore_from(tmp_dir.path().join("missing.db"))
+        .await;
+    assert!(matches!(res, Err(Error::Rusqlite(_))));
+    assert!(!tmp_dir.path().join("missing.db").exists());
+}
+
+#[cfg(feature = "load_extension")]
+async fn test_extension_load_failure() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let missing = tmp_dir.path().join("missing_extension");
+
+    let res = ClientBuilder::new().extension(&missing, None).open().await;
+    assert!(matches!(res, Err(Error::Rusqlite(_))));
+
+    let res = PoolBuilder::new()
+        .num_conns(2)
+        .extension(&missing, Some("sqlite3_missing_init"))
+        .open()
+        .await;
+    assert!(matches!(res, Err(Error::Rusqlite(_))));
+}
+
+async fn test_pool_stats() {
+    let pool = PoolBuilder::new()
+        .num_conns(3)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    assert_eq!(pool.stats().total_requests, 0);
+
+    let fs = (0..10).map(|_| pool.conn(|conn| conn.query_row("SELECT 1", (), |_| Ok(()))));
+    futures_util::future::join_all(fs)
+        .await
+        .into_iter()
+        .collect::<Result<(), Error>>()
+        .expect("collecting query results");
+
+    let stats = pool.stats();
+    assert_eq!(stats.num_conns, 3);
+    assert_eq!(stats.total_requests, 10);
+    assert_eq!(stats.per_conn_requests.len(), 3);
+    assert_eq!(stats.per_conn_requests.iter().sum::<u64>(), 10);
+}
+
+async fn test_pool_least_busy() {
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+
+    let (started_tx, started_rx) = std::sync::mpsc::channel();
+    let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+    let slow_pool = pool.clone();
+    let slow = std::thread::spawn(move || {
+        slow_pool.conn_blocking(move |_| {
+            started_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+            Ok(())
+        })
+    });
+    started_rx.recv().unwrap();
+
+    for _ in 0..4 {
+        pool.conn(|conn| conn.query_row("SELECT 1", (), |_| Ok(())))
+            .await
+            .expect("querying pool");
+    }
+    release_tx.send(()).unwrap();
+    slow.join().unwrap().expect("running slow query");
+
+    let mut counts = pool.stats().per_conn_requests;
+    counts.sort();
+    assert_eq!(counts, vec![1, 4]);
+}
+
+async fn test_pool_acquire() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let pool = PoolBuilder::new()
+        .path(tmp_dir.path().join("sqlite.db"))
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    pool.execute("CREATE TABLE testing (id INTEGER PRIMARY KEY)", [])
+        .await
+        .expect("writing schema");
+    let before = pool.stats().per_conn_requests;
+
+    let lease = pool.acquire().expect("acquiring connection");
+    lease
+        .conn(|conn| conn.execute_batch("BEGIN; INSERT INTO testing VALUES (1);"))
+        .await
+        .expect("starting transaction");
+
+    // Requests to the pool don't run on the leased connection, so they don't
+    // see the uncommitted row.
+    for _ in 0..4 {
+        let count: i64 = pool
+            .query_row("SELECT COUNT(*) FROM testing", [], |row| row.get(0))
+            .await
+            .expect("querying rows");
+        assert_eq!(count, 0);
+    }
+    let after = pool.stats().per_conn_requests;
+    let dispatched = (0..2).map(|i| after[i] - before[i]).collect::<Vec<_>>();
+    assert!(dispatched == [0, 4] || dispatched == [4, 0]);
+
+    lease
+        .conn(|conn| conn.execute_batch("INSERT INTO testing VALUES (2); COMMIT;"))
+        .await
+        .expect("committing transaction");
+    drop(lease);
+
+    let count: i64 = pool
+        .query_row("SELECT COUNT(*) FROM testing", [], |row| row.get(0))
+        .await
+        .expect("querying rows");
+    assert_eq!(count, 2);
+}
+
+async fn test_pool_shared_memory() {
+    // Each runtime's variant of this test needs its own database.
+    let name = format!("test_pool_shared_memory_{:?}", std::thread::current().id());
+    let pool = PoolBuilder::new()
+        .shared_memory(&name)
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    let lease = pool.acquire().expect("acquiring connection");
+    lease
+        .conn(|conn| {
+            conn.execute_batch(
+                "CREATE TABLE testing (id INTEGER PRIMARY KEY);
+                INSERT INTO testing VALUES (1), (2);",
+            )
+        })
+        .await
+        .expect("writing through one connection");
+
+    // The leased connection is skipped, so this reads through the other one.
+    let count: i64 = pool
+        .query_row("SELECT COUNT(*) FROM testing", [], |row| row.get(0))
+        .await
+        .expect("reading through another connection");
+    assert_eq!(count, 2);
+    drop(lease);
+    pool.close().await.expect("closing pool");
+
+    let pool = PoolBuilder::new()
+        .shared_memory(&name)
+        .num_conns(1)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    let res = pool.execute("SELECT * FROM testing", []).await;
+    assert!(res.is_err());
+}
+
+async fn test_pool_conn_on() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+
+    for index in 0..2 {
+        let path = tmp_dir.path().join(format!("shard{index}.db"));
+        pool.conn_on(index, move |conn| {
+            conn.execute("ATTACH DATABASE ?1 AS shard", [path.to_str().unwrap()])?;
+            conn.execute_batch("CREATE TABLE shard.testing (val INTEGER NOT NULL);")?;
+            conn.execute("INSERT INTO shard.testing VALUES (?1)", [index])
+        })
+        .await
+        .expect("attaching shard");
+    }
+
+    for index in 0..2 {
+        let val: usize = pool
+            .conn_on(index, |conn| {
+                conn.query_row("SELECT val FROM shard.testing", [], |row| row.get(0))
+            })
+            .await
+            .expect("reading from shard");
+        assert_eq!(val, index);
+    }
+
+    let res = pool.conn_on(2, |_| Ok(())).await;
+    assert!(matches!(
+        res,
+        Err(Error::IndexOutOfRange { index: 2, len: 2 })
+    ));
+}
+
+// A custom error type, as used with `conn_and_then`.
+#[derive(Debug)]
+enum CustomError {
+    Sqlite(Error),
+    Negative(i64),
+}
+
+impl From<Error> for CustomError {
+    fn from(err: Error) -> Self {
+        CustomError::Sqlite(err)
+    }
+}
+
+impl From<async_sqlite::rusqlite::Error> for CustomError {
+    fn from(err: async_sqlite::rusqlite::Error) -> Self {
+        CustomError::Sqlite(err.into())
+    }
+}
+
+async fn test_pool_conn_and_then() {
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+
+    let vals = pool
+        .conn_and_then(|conn| {
+            let mut stmt = conn.prepare("SELECT 1 UNION ALL SELECT 2")?;
+            let rows =
+                stmt.query_and_then([], |row| -> Result<i64, CustomError> { Ok(row.get(0)?) })?;
+            rows.collect::<Result<Vec<_>, _>>()
+        })
+        .await
+        .expect("querying rows");
+    assert_eq!(vals, vec![1, 2]);
+
+    let res = pool
+        .conn_mut_and_then(|conn| {
+            let val: i64 = conn.query_row("SELECT -1", [], |row| row.get(0))?;
+            if val < 0 {
+                return Err(CustomError::Negative(val));
+            }
+            Ok(val)
+        })
+        .await;
+    assert!(matches!(res, Err(CustomError::Negative(-1))));
+
+    let res = pool
+        .conn_and_then(|conn| -> Result<(), CustomError> {
+            conn.execute_batch("NOT VALID SQL")?;
+            Ok(())
+        })
+        .await;
+    assert!(matches!(res, Err(CustomError::Sqlite(Error::Rusqlite(_)))));
+
+    pool.shutdown().await.expect("shutting down pool");
+    let res = pool.conn_and_then(|_| Ok::<_, CustomError>(())).await;
+    assert!(matches!(res, Err(CustomError::Sqlite(Error::Closed))));
+}
+
+async fn test_query_row() {
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .conn(|conn| {
+            conn.execute_batch(
+                "CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL);
+                INSERT INTO testing VALUES (1, 'value1');",
+            )
+        })
+        .await
+        .expect("writing schema and seed data");
+
+    let val: String = client
+        .query_row("SELECT val FROM testing WHERE id=?", [1], |row| row.get(0))
+        .await
+        .expect("querying for result");
+    assert_eq!(val, "value1");
+
+    let res = client
+        .query_row("SELECT val FROM testing WHERE id=?", [2], |row| {
+            row.get::<_, String>(0)
+        })
+        .await;
+    assert!(matches!(
+        res,
+        Err(Error::Rusqlite(
+            async_sqlite::rusqlite::Error::QueryReturnedNoRows
+        ))
+    ));
+
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    let val: i64 = pool
+        .query_row("SELECT ? + 1", [41], |row| row.get(0))
+        .await
+        .expect("querying pool for result");
+    assert_eq!(val, 42);
+}
+
+async fn test_query_map() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let path = tmp_dir.path().join("sqlite.db");
+    let client = ClientBuilder::new()
+        .path(&path)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute_batch("CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL)")
+        .await
+        .expect("writing schema");
+
+    let ids = client
+        .query_map(
+            "INSERT INTO testing (val) VALUES ('a'), ('b'), ('c') RETURNING id",
+            [],
+            |row| row.get::<_, i64>(0),
+        )
+        .await
+        .expect("inserting rows");
+    assert_eq!(ids, vec![1, 2, 3]);
+
+    let none = client
+        .query_map("SELECT val FROM testing WHERE id > ?1", [3], |row| {
+            row.get::<_, String>(0)
+        })
+        .await
+        .expect("querying for no rows");
+    assert!(none.is_empty());
+
+    let pool = PoolBuilder::new()
+        .path(&path)
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    let ids = pool
+        .query_map(
+            "INSERT INTO testing (val) VALUES ('d'), ('e') RETURNING id",
+            [],
+            |row| row.get::<_, i64>(0),
+        )
+        .await
+        .expect("inserting rows");
+    assert_eq!(ids, vec![4, 5]);
+    let vals = pool
+        .query_map("SELECT val FROM testing ORDER BY id", [], |row| {
+            row.get::<_, String>(0)
+        })
+        .await
+        .expect("querying rows");
+    assert_eq!(vals, vec!["a", "b", "c", "d", "e"]);
+}
+
+async fn test_validate_sql() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let pool = PoolBuilder::new()
+        .path(tmp_dir.path().join("sqlite.db"))
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    pool.execute_batch("CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL)")
+        .await
+        .expect("writing schema");
+
+    pool.validate_sql("INSERT INTO testing (val) VALUES (?1)")
+        .await
+        .expect("validating insert");
+    pool.validate_sql("SELECT val FROM testing WHERE id = ?1")
+        .await
+        .expect("validating select");
+    let err = pool
+        .validate_sql("SELECT missing FROM testing")
+        .await
+        .expect_err("missing column should fail");
+    assert!(matches!(
+        err,
+        Error::Rusqlite(async_sqlite::rusqlite::Error::SqlInputError { .. })
+    ));
+
+    // Validating doesn't execute the statement.
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .validate_sql("CREATE TABLE testing (id INTEGER PRIMARY KEY)")
+        .await
+        .expect("validating create");
+    client
+        .validate_sql("SELECT * FROM testing")
+        .await
+        .expect_err("table should not exist");
+    let err = client
+        .validate_sql("SELEKT 1")
+        .await
+        .expect_err("syntax error should fail");
+    assert!(matches!(err, Error::Rusqlite(_)));
+}
+
+async fn test_query_opt() {
+    const SQL: &str = "SELECT val FROM testing WHERE id=?";
+
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let path = tmp_dir.path().join("sqlite.db");
+    let client = ClientBuilder::new()
+        .path(&path)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute_batch(
+            "CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL);
+            INSERT INTO testing VALUES (1, 'value1');",
+        )
+        .await
+        .expect("writing schema and seed data");
+
+    let val = client
+        .query_opt(SQL, [1], |row| row.get::<_, String>(0))
+        .await
+        .expect("querying for present row");
+    assert_eq!(val.as_deref(), Some("value1"));
+    let val = client
+        .query_opt(SQL, [2], |row| row.get::<_, String>(0))
+        .await
+        .expect("querying for absent row");
+    assert_eq!(val, None);
+    let res = client
+        .query_opt("SELECT missing FROM testing", [], |row| {
+            row.get::<_, String>(0)
+        })
+        .await;
+    assert!(matches!(res, Err(Error::Rusqlite(_))));
+
+    let pool = PoolBuilder::new()
+        .path(&path)
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    let val = pool
+        .query_opt(SQL, [1], |row| row.get::<_, String>(0))
+        .await
+        .expect("querying pool for present row");
+    assert_eq!(val.as_deref(), Some("value1"));
+    let val = pool
+        .query_opt(SQL, [2], |row| row.get::<_, String>(0))
+        .await
+        .expect("querying pool for absent row");
+    assert_eq!(val, None);
+}
+
+async fn test_execute() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let pool = PoolBuilder::new()
+        .path(tmp_dir.path().join("sqlite.db"))
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+
+    let changed = pool
+        .execute(
+            "CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL)",
+            [],
+        )
+        .await
+        .expect("writing schema");
+    assert_eq!(changed, 0);
+
+    let changed = pool
+        .execute("INSERT INTO testing VALUES (?, ?)", (1, "value1"))
+        .await
+        .expect("inserting row");
+    assert_eq!(changed, 1);
+
+    let changed = pool
+        .execute(
+            "INSERT INTO testing VALUES (:id, :val)",
+            &[(":id", &"2"), (":val", &"value2")],
+        )
+        .await
+        .expect("inserting row with named params");
+    assert_eq!(changed, 1);
+
+    let client = ClientBuilder::new()
+        .path(tmp_dir.path().join("sqlite.db"))
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let changed = client
+        .execute("UPDATE testing SET val = ?", ["updated"])
+        .await
+        .expect("updating rows");
+    assert_eq!(changed, 2);
+
+    // Borrowed named parameters are converted before the call is sent.
+    use async_sqlite::rusqlite::named_params;
+    let val = String::from("value3");
+    let changed = pool
+        .execute_named(
+            "INSERT INTO testing VALUES (:id, :val)",
+            named_params! { ":id": 3, ":val": val },
+        )
+        .await
+        .expect("inserting row with named_params!");
+    assert_eq!(changed, 1);
+    let changed = client
+        .execute_named(
+            "UPDATE testing SET val = :val WHERE id > :id",
+            named_params! { ":id": 1, ":val": &val },
+        )
+        .await
+        .expect("updating rows with named_params!");
+    assert_eq!(changed, 2);
+    let id = 3;
+    let got: String = pool
+        .query_row_named(
+            "SELECT val FROM testing WHERE id = :id",
+            named_params! { ":id": id },
+            |row| row.get(0),
+        )
+        .await
+        .expect("querying row with named_params!");
+    assert_eq!(got, val);
+    let count: i64 = client
+        .query_row_named(
+            "SELECT COUNT(*) FROM testing WHERE val = :val",
+            named_params! { ":val": val },
+            |row| row.get(0),
+        )
+        .await
+        .expect("querying row with named_params!");
+    assert_eq!(count, 2);
+}
+
+async fn test_execute_returning() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let pool = PoolBuilder::new()
+        .path(tmp_dir.path().join("sqlite.db"))
+        .journal_mode(JournalMode::Wal)
+        .num_conns(4)
+        .open_blocking()
+        .expect("pool unable to be opened");
+    pool.execute(
+        "CREATE TABLE testing (id INTEGER PRIMARY KEY, val INTEGER NOT NULL)",
+        [],
+    )
+    .await
+    .expect("writing schema");
+
+    let inserts = (0..20).map(|i| {
+        let pool = pool.clone();
+        async move {
+            let rowid = pool
+                .execute_returning_rowid("INSERT INTO testing (val) VALUES (?)", (i,))
+                .await
+                .expect("inserting row");
+            (i, rowid)
+        }
+    });
+    for (i, rowid) in futures_util::future::join_all(inserts).await {
+        let val: i64 = pool
+            .query_row("SELECT val FROM testing WHERE id = ?", (rowid,), |row| {
+                row.get(0)
+            })
+            .await
+            .expect("querying inserted row");
+        assert_eq!(val, i);
+    }
+
+    let changed = pool
+        .execute_batch_returning_changes(
+            "UPDATE testing SET val = val + 1 WHERE val < 5;
+            DELETE FROM testing WHERE val >= 15;",
+        )
+        .await
+        .expect("updating rows");
+    assert_eq!(changed, 10);
+}
+
+async fn test_named_params() {
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute_batch(
+            "CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL);
+            INSERT INTO testing VALUES (1, 'value1');",
+        )
+        .await
+        .expect("writing schema and seed data");
+
+    let changed = client
+        .execute(
+            "INSERT INTO testing VALUES (:id, :val)",
+            &[(":val", &"value2"), (":id", &"2")],
+        )
+        .await
+        .expect("inserting row with named params");
+    assert_eq!(changed, 1);
+
+    let val: String = client
+        .query_row(
+            "SELECT val FROM testing WHERE id = :id",
+            &[(":id", &2)],
+            |row| row.get(0),
+        )
+        .await
+        .expect("querying with named params");
+    assert_eq!(val, "value2");
+
+    let res = client
+        .execute("UPDATE testing SET val = :val", &[(":missing", &"value")])
+        .await;
+    assert!(matches!(
+        res,
+        Err(Error::Rusqlite(
+            async_sqlite::rusqlite::Error::InvalidParameterName(name)
+        )) if name == ":missing"
+    ));
+
+    let val: String = client
+        .conn(|conn| {
+            conn.query_row(
+                "SELECT val FROM testing WHERE id = :id",
+                async_sqlite::rusqlite::named_params! { ":id": 1 },
+                |row| row.get(0),
+            )
+        })
+        .await
+        .expect("querying with named_params!");
+    assert_eq!(val, "value1");
+
+    // The parameters are converted up front, so the future can be spawned.
+    fn assert_send<T: Send>(value: T) -> T {
+        value
+    }
+    let changed = assert_send(client.execute_named(
+        "INSERT INTO testing VALUES (:id, :val)",
+        async_sqlite::rusqlite::named_params! { ":id": 3, ":val": "value3" },
+    ))
+    .await
+    .expect("inserting row with named_params!");
+    assert_eq!(changed, 1);
+
+    let val: String = client
+        .query_row_named(
+            "SELECT val FROM testing WHERE id = :id",
+            async_sqlite::rusqlite::named_params! { ":id": 3 },
+            |row| row.get(0),
+        )
+        .await
+        .expect("querying row with named_params!");
+    assert_eq!(val, "value3");
+
+    let res = client
+        .execute_named(
+            "UPDATE testing SET val = :val",
+            async_sqlite::rusqlite::named_params! { ":missing": "value" },
+        )
+        .await;
+    assert!(matches!(
+        res,
+        Err(Error::Rusqlite(
+            async_sqlite::rusqlite::Error::InvalidParameterName(name)
+        )) if name == ":missing"
+    ));
+}
+
+async fn test_execute_batch_params() {
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute(
+            "CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL)",
+            [],
+        )
+        .await
+        .expect("writing schema");
+
+    let rows = (0..10_000).map(|i| (i, format!("value{i}")));
+    let changed = client
+        .execute_batch_params("INSERT INTO testing VALUES (?, ?)", rows)
+        .await
+        .expect("inserting rows");
+    assert_eq!(changed, 10_000);
+
+    // The duplicate primary key fails the batch, rolling back all rows.
+    let rows = [(10_000, "new"), (10_001, "new"), (10_000, "duplicate")];
+    let res = client
+        .execute_batch_params("INSERT INTO testing VALUES (?, ?)", rows)
+        .await;
+    assert!(matches!(res, Err(Error::Rusqlite(_))));
+
+    let count: i64 = client
+        .query_row("SELECT COUNT(*) FROM testing", [], |row| row.get(0))
+        .await
+        .expect("counting rows");
+    assert_eq!(count, 10_000);
+}
+
+async fn test_execute_batch() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let pool = PoolBuilder::new()
+        .path(tmp_dir.path().join("sqlite.db"))
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    pool.execute_batch(
+        "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+        CREATE TABLE posts (id INTEGER PRIMARY KEY, user_id INTEGER NOT NULL);
+        INSERT INTO users VALUES (1, 'user1');
+        INSERT INTO posts VALUES (1, 1), (2, 1);",
+    )
+    .await
+    .expect("applying schema");
+
+    let (users, posts): (i64, i64) = pool
+        .query_row(
+            "SELECT (SELECT COUNT(*) FROM users), (SELECT COUNT(*) FROM posts)",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .await
+        .expect("querying both tables");
+    assert_eq!((users, posts), (1, 2));
+
+    let res = pool
+        .execute_batch("INSERT INTO users VALUES (2, 'user2'); INSERT INTO missing VALUES (1);")
+        .await;
+    assert!(matches!(res, Err(Error::Rusqlite(_))));
+}
+
+async fn test_pool_shutdown() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let pool = PoolBuilder::new()
+        .path(tmp_dir.path().join("sqlite.db"))
+        .num_conns(2)
+        .pragma("busy_timeout", "5000")
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    pool.execute(
+        "CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL)",
+        [],
+    )
+    .await
+    .expect("writing schema");
+
+    let writes = (0..10).map(|i| {
+        pool.conn(move |conn| {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            conn.execute("INSERT INTO testing VALUES (?, 'value')", [i])
+        })
+    });
+    let (writes, shutdown) =
+        futures_util::future::join(futures_util::future::join_all(writes), pool.shutdown()).await;
+    shutdown.expect("shutting down pool");
+    writes
+        .into_iter()
+        .collect::<Result<Vec<_>, Error>>()
+        .expect("writing rows before shutdown");
+
+    let res = pool
+        .execute("INSERT INTO testing VALUES (10, 'value')", [])
+        .await;
+    assert!(matches!(res, Err(Error::Closed)));
+    let res = pool.conn_for_each(|_| Ok(())).await;
+    assert_eq!(res.len(), 2);
+    assert!(res.iter().all(|res| matches!(res, Err(Error::Closed))));
+    let res = pool.conn_for_each_limited(1, |_| Ok(())).await;
+    assert!(res.iter().all(|res| matches!(res, Err(Error::Closed))));
+    let res = pool.conn_mut_for_each(|_| Ok(())).await;
+    assert!(res.iter().all(|res| matches!(res, Err(Error::Closed))));
+
+    let client = ClientBuilder::new()
+        .path(tmp_dir.path().join("sqlite.db"))
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let count: i64 = client
+        .query_row("SELECT COUNT(*) FROM testing", [], |row| row.get(0))
+        .await
+        .expect("counting rows");
+    assert_eq!(count, 10);
+}
+
+async fn test_pool_resize() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let pool = PoolBuilder::new()
+        .path(tmp_dir.path().join("sqlite.db"))
+        .num_conns(1)
+        .pragma("busy_timeout", "5000")
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    pool.execute(
+        "CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL)",
+        [],
+    )
+    .await
+    .expect("writing schema");
+
+    pool.resize(3).await.expect("growing pool");
+    assert_eq!(pool.stats().num_conns, 3);
+
+    let writes = (0..9).map(|i| {
+        pool.conn(move |conn| {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            conn.execute("INSERT INTO testing VALUES (?, 'value')", [i])
+        })
+    });
+    let (writes, resize) =
+        futures_util::future::join(futures_util::future::join_all(writes), pool.resize(1)).await;
+    resize.expect("shrinking pool");
+    writes
+        .into_iter()
+        .collect::<Result<Vec<_>, Error>>()
+        .expect("writing rows while shrinking");
+    assert_eq!(pool.stats().num_conns, 1);
+
+    let count: i64 = pool
+        .query_row("SELECT COUNT(*) FROM testing", [], |row| row.get(0))
+        .await
+        .expect("counting rows");
+    assert_eq!(count, 9);
+}
+
+async fn test_pool_open_retry() {
+    for _ in 0..20 {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let pool = PoolBuilder::new()
+            .path(tmp_dir.path().join("sqlite.db"))
+            .journal_mode(JournalMode::Wal)
+            .num_conns(4)
+            .open_retry(10, std::time::Duration::from_millis(5))
+            .open()
+            .await
+            .expect("pool unable to be opened");
+        pool.close().await.expect("closing pool");
+    }
+}
+
+async fn test_checkpoint() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let client = ClientBuilder::new()
+        .path(tmp_dir.path().join("sqlite.db"))
+        .journal_mode(JournalMode::Wal)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute_batch(
+            "CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL);
+            INSERT INTO testing VALUES (1, 'value1'), (2, 'value2');",
+        )
+        .await
+        .expect("writing schema and seed data");
+
+    let wal_path = tmp_dir.path().join("sqlite.db-wal");
+    assert!(std::fs::metadata(&wal_path).unwrap().len() > 0);
+
+    let res = client
+        .checkpoint(CheckpointMode::Truncate)
+        .await
+        .expect("checkpointing");
+    assert!(!res.busy);
+    assert_eq!(res.log_frames, 0);
+    assert_eq!(std::fs::metadata(&wal_path).unwrap().len(), 0);
+
+    let pool = PoolBuilder::new()
+        .path(tmp_dir.path().join("sqlite.db"))
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    pool.execute("INSERT INTO testing VALUES (3, 'value3')", [])
+        .await
+        .expect("writing row");
+    let res = pool
+        .checkpoint(CheckpointMode::Passive)
+        .await
+        .expect("checkpointing");
+    assert!(!res.busy);
+    assert_eq!(res.log_frames, res.checkpointed_frames);
+
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let res = client
+        .checkpoint(CheckpointMode::Passive)
+        .await
+        .expect("checkpointing");
+    assert_eq!((res.log_frames, res.checkpointed_frames), (-1, -1));
+}
+
+async fn test_open_timeout() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let path = tmp_dir.path().join("sqlite.db");
+    let locker = ClientBuilder::new()
+        .path(&path)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    locker
+        .execute_batch("CREATE TABLE testing (id INTEGER PRIMARY KEY); BEGIN EXCLUSIVE;")
+        .await
+        .expect("locking database");
+
+    // Changing the journal mode waits on the busy timeout while the database
+    // is locked.
+    let res = ClientBuilder::new()
+        .path(&path)
+        .journal_mode(JournalMode::Wal)
+        .open_timeout(std::time::Duration::from_millis(50))
+        .open()
+        .await;
+    assert!(matches!(res, Err(Error::Timeout)));
+
+    let res = PoolBuilder::new()
+        .path(&path)
+        .num_conns(2)
+        .journal_mode(JournalMode::Wal)
+        .open_timeout(std::time::Duration::from_millis(50))
+        .open_blocking();
+    assert!(matches!(res, Err(Error::Timeout)));
+
+    locker
+        .execute_batch("COMMIT")
+        .await
+        .expect("unlocking database");
+    ClientBuilder::new()
+        .path(&path)
+        .journal_mode(JournalMode::Wal)
+        .open_timeout(std::time::Duration::from_secs(5))
+        .open()
+        .await
+        .expect("client unable to be opened");
+}
+
+async fn test_pool_close_shared() {
+    let pool = std::sync::Arc::new(
+        PoolBuilder::new()
+            .num_conns(2)
+            .open()
+            .await
+            .expect("pool unable to be opened"),
+    );
+    let shared = std::sync::Arc::clone(&pool);
+    shared.close().await.expect("closing pool");
+
+    assert!(pool.is_closed());
+    let res = pool.conn(|conn| conn.execute("SELECT 1", [])).await;
+    assert!(matches!(res, Err(Error::Closed)));
+}
+
+async fn test_auto_checkpoint() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let path = tmp_dir.path().join("sqlite.db");
+    let wal = tmp_dir.path().join("sqlite.db-wal");
+
+    let open = |interval: Option<std::time::Duration>| {
+        let mut builder = PoolBuilder::new()
+            .path(&path)
+            .journal_mode(JournalMode::Wal)
+            .synchronous(Synchronous::Normal)
+            .pragma("wal_autocheckpoint", "0")
+            .num_conns(2);
+        if let Some(interval) = interval {
+            builder = builder.auto_checkpoint(interval);
+        }
+        builder.open()
+    };
+    // Returns the maximum size of the WAL while writing rows in bursts. A
+    // passive checkpoint can only reset the WAL once it has caught up with
+    // the writers, so each burst is followed by a short pause.
+    async fn write(pool: async_sqlite::Pool, wal: &std::path::Path) -> u64 {
+        let mut max = 0;
+        for _ in 0..10 {
+            for _ in 0..20 {
+                pool.execute("INSERT INTO testing (val) VALUES (randomblob(8192))", [])
+                    .await
+                    .expect("inserting row");
+                max = max.max(std::fs::metadata(wal).map_or(0, |meta| meta.len()));
+            }
+            std::thread::sleep(std::time::Duration::from_millis(25));
+        }
+        pool.close().await.expect("closing pool");
+        max
+    }
+
+    let pool = open(None).await.expect("pool unable to be opened");
+    pool.execute_batch("CREATE TABLE testing (id INTEGER PRIMARY KEY, val BLOB NOT NULL)")
+        .await
+        .expect("writing schema");
+    let unbounded = write(pool, &wal).await;
+
+    let pool = open(Some(std::time::Duration::from_millis(5)))
+        .await
+        .expect("pool unable to be opened");
+    let bounded = write(pool, &wal).await;
+    assert!(bounded < unbounded / 2, "{bounded} >= {unbounded} / 2");
+}
+
+async fn test_vacuum() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let path = tmp_dir.path().join("sqlite.db");
+    let pool = PoolBuilder::new()
+        .path(&path)
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    pool.execute_batch(
+        "CREATE TABLE testing (id INTEGER PRIMARY KEY, val BLOB NOT NULL);
+        WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 256)
+        INSERT INTO testing (val) SELECT randomblob(4096) FROM n;
+        DELETE FROM testing;",
+    )
+    .await
+    .expect("writing and deleting rows");
+
+    let before = std::fs::metadata(&path).unwrap().len();
+    pool.vacuum().await.expect("vacuuming pool");
+    let after = std::fs::metadata(&path).unwrap().len();
+    assert!(after < before, "{after} >= {before}");
+
+    let client = ClientBuilder::new()
+        .path(&path)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute_batch("BEGIN")
+        .await
+        .expect("beginning transaction");
+    assert!(client.vacuum().await.is_err());
+    client
+        .execute_batch("ROLLBACK")
+        .await
+        .expect("rolling back transaction");
+    client.vacuum().await.expect("vacuuming client");
+
+    let dest = tmp_dir.path().join("copy.db");
+    client
+        .vacuum_into(&dest)
+        .await
+        .expect("vacuuming into copy");
+    let copy = ClientBuilder::new()
+        .path(&dest)
+        .must_exist(true)
+        .open()
+        .await
+        .expect("copy unable to be opened");
+    let count: i64 = copy
+        .query_row("SELECT COUNT(*) FROM testing", [], |row| row.get(0))
+        .await
+        .expect("reading copy");
+    assert_eq!(count, 0);
+}
+
+async fn test_is_closed() {
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let clone = client.clone();
+    assert!(!client.is_closed());
+    assert_eq!(format!("{client:?}"), "Client { closed: false }");
+    client.close().await.expect("closing client");
+    assert!(client.is_closed());
+    assert_eq!(format!("{client:?}"), "Client { closed: true }");
+    assert!(clone.is_closed());
+
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    assert!(!pool.is_closed());
+    assert_eq!(format!("{pool:?}"), "Pool { num_conns: 2, closed: false }");
+    pool.close().await.expect("closing pool");
+    assert!(pool.is_closed());
+}
+
+// A panic payload that panics again each time it is dropped, which stops the
+// worker thread despite its panic handling.
+struct Bomb(usize);
+
+impl Drop for Bomb {
+    fn drop(&mut self) {
+        if self.0 > 0 {
+            std::panic::panic_any(Bomb(self.0 - 1));
+        }
+    }
+}
+
+async fn test_pool_health_check() {
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    assert!(pool.is_healthy().await);
+
+    let res = pool
+        .conn_on(1, |_| -> Result<(), _> { std::panic::panic_any(Bomb(2)) })
+        .await;
+    assert!(matches!(res, Err(Error::Closed)));
+
+    let health = pool.health_check().await;
+    assert!(health[0].is_ok());
+    assert!(matches!(health[1], Err(Error::Closed)));
+    assert!(!pool.is_healthy().await);
+}
+
+async fn test_temp_store() {
+    let client = ClientBuilder::new()
+        .temp_store(TempStore::Memory)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let temp_store: i64 = client
+        .conn(|conn| conn.pragma_query_value(None, "temp_store", |row| row.get(0)))
+        .await
+        .expect("unable to query temp_store");
+    assert_eq!(temp_store, TempStore::Memory as i64);
+
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .temp_store(TempStore::Memory)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    let res = pool
+        .conn_for_each(|conn| conn.pragma_query_value(None, "temp_store", |row| row.get(0)))
+        .await;
+    for temp_store in res {
+        let temp_store: i64 = temp_store.expect("unable to query temp_store");
+        assert_eq!(temp_store, TempStore::Memory as i64);
+    }
+}
+
+async fn test_mmap_size() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let pool = PoolBuilder::new()
+        .path(tmp_dir.path().join("sqlite.db"))
+        .num_conns(2)
+        .mmap_size(256 * 1024 * 1024)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    let res = pool
+        .conn_for_each(|conn| conn.pragma_query_value(None, "mmap_size", |row| row.get(0)))
+        .await;
+    for mmap_size in res {
+        let mmap_size: i64 = mmap_size.expect("unable to query mmap_size");
+        assert!(mmap_size > 0 && mmap_size <= 256 * 1024 * 1024);
+    }
+
+    let client = ClientBuilder::new()
+        .path(tmp_dir.path().join("sqlite.db"))
+        .mmap_size(0)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let mmap_size: i64 = client
+        .conn(|conn| conn.pragma_query_value(None, "mmap_size", |row| row.get(0)))
+        .await
+        .expect("unable to query mmap_size");
+    assert_eq!(mmap_size, 0);
+}
+
+async fn test_conn_for_each_limited() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let pool = PoolBuilder::new()
+        .num_conns(4)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+
+    let running = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let res = {
+        let running = running.clone();
+        let peak = peak.clone();
+        pool.conn_for_each_limited(2, move |conn| {
+            let current = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(current, Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            running.fetch_sub(1, Ordering::SeqCst);
+            conn.query_row("SELECT 1", [], |row| row.get::<_, i64>(0))
+        })
+        .await
+    };
+    assert_eq!(res.len(), 4);
+    for value in res {
+        assert_eq!(value.expect("unable to query"), 1);
+    }
+    let peak = peak.load(Ordering::SeqCst);
+    assert!((1..=2).contains(&peak), "peak concurrency was {peak}");
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        up_sql: "CREATE TABLE users (id INTEGER PRIMARY KEY);",
+    },
+    Migration {
+        version: 2,
+        up_sql: "ALTER TABLE users ADD COLUMN name TEXT;",
+    },
+    Migration {
+        version: 3,
+        up_sql: "CREATE INDEX users_name ON users (name);",
+    },
+];
+
+async fn user_version(client: &async_sqlite::Client) -> u32 {
+    client
+        .conn(|conn| conn.pragma_query_value(None, "user_version", |row| row.get(0)))
+        .await
+        .expect("unable to query user_version")
+}
+
+async fn test_migrate() {
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let migrations = Migrations::new(MIGRATIONS);
+    assert_eq!(migrations.latest_version(), 3);
+
+    let version = client
+        .migrate(&migrations)
+        .await
+        .expect("unable to migrate");
+    assert_eq!(version, 3);
+    assert_eq!(user_version(&client).await, 3);
+    client
+        .execute("INSERT INTO users (id, name) VALUES (1, 'one')", [])
+        .await
+        .expect("unable to insert");
+
+    // Running the migrations again is a no-op.
+    let version = client
+        .migrate(&migrations)
+        .await
+        .expect("unable to migrate");
+    assert_eq!(version, 3);
+}
+
+async fn test_migrate_partial() {
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let version = client
+        .migrate(&Migrations::new(&MIGRATIONS[..1]))
+        .await
+        .expect("unable to migrate");
+    assert_eq!(version, 1);
+
+    let version = client
+        .migrate(&Migrations::new(MIGRATIONS))
+        .await
+        .expect("unable to migrate");
+    assert_eq!(version, 3);
+    assert_eq!(user_version(&client).await, 3);
+}
+
+async fn test_migrate_failure() {
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .migrate(&Migrations::new(&MIGRATIONS[..1]))
+        .await
+        .expect("unable to migrate");
+
+    let migrations = Migrations::new(&[
+        MIGRATIONS[1],
+        Migration {
+            version: 3,
+            up_sql: "CREATE INDEX users_missing ON users (missing);",
+        },
+    ]);
+    let res = client.migrate(&migrations).await;
+    assert!(matches!(res, Err(Error::Rusqlite(_))));
+    assert_eq!(user_version(&client).await, 1);
+
+    // The successful migration in the failed batch was rolled back too.
+    let res = client
+        .execute("INSERT INTO users (id, name) VALUES (1, 'one')", [])
+        .await;
+    assert!(res.is_err());
+}
+
+#[test]
+#[should_panic(expected = "duplicate migration version 2")]
+fn test_migrations_duplicate_version() {
+    Migrations::new(&[
+        MIGRATIONS[0],
+        MIGRATIONS[1],
+        Migration {
+            version: 2,
+            up_sql: "CREATE INDEX users_name ON users (name);",
+        },
+    ]);
+}
+
+async fn test_pool_available() {
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    assert_eq!(pool.size(), 2);
+    assert_eq!(pool.available(), 2);
+
+    let (started_tx, started_rx) = std::sync::mpsc::channel();
+    let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+    let handle = {
+        let pool = pool.clone();
+        std::thread::spawn(move || {
+            pool.conn_blocking(move |_| {
+                started_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+                Ok(())
+            })
+        })
+    };
+    started_rx.recv().unwrap();
+    assert_eq!(pool.size(), 2);
+    assert_eq!(pool.available(), 1);
+
+    release_tx.send(()).unwrap();
+    handle.join().unwrap().expect("unable to run query");
+    assert_eq!(pool.available(), 2);
+
+    let res = pool
+        .conn_on(1, |_| -> Result<(), _> { std::panic::panic_any(Bomb(2)) })
+        .await;
+    assert!(matches!(res, Err(Error::Closed)));
+
+    // The worker is marked as closed asynchronously as its thread exits.
+    for _ in 0..100 {
+        if pool.size() == 1 {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert_eq!(pool.size(), 1);
+    assert_eq!(pool.available(), 1);
+}
+
+async fn test_bulk_insert() {
+    use async_sqlite::rusqlite::types::Value;
+
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute_batch("CREATE TABLE testing (id INTEGER PRIMARY KEY, name TEXT, score REAL);")
+        .await
+        .expect("unable to create table");
+
+    const NUM_ROWS: i64 = 50_000;
+    let rows = |offset: i64| {
+        (0..NUM_ROWS).map(move |i| {
+            let id = offset + i;
+            vec![
+                Value::Integer(id),
+                Value::Text(format!("name-{id}")),
+                Value::Real(id as f64 / 2.0),
+            ]
+        })
+    };
+
+    // Both chunk sizes leave a final partial chunk, and the second is larger
+    // than the variable limit allows.
+    for (offset, chunk_size) in [(0, 300), (NUM_ROWS, usize::MAX)] {
+        let inserted = client
+            .bulk_insert(
+                "testing",
+                &["id", "name", "score"],
+                rows(offset),
+                chunk_size,
+            )
+            .await
+            .expect("unable to bulk insert");
+        assert_eq!(inserted, NUM_ROWS as usize);
+    }
+
+    let (count, sum, names): (i64, i64, i64) = client
+        .conn(|conn| {
+            conn.query_row(
+                "SELECT COUNT(*), SUM(id), SUM(name = 'name-' || id AND score = id / 2.0) FROM testing",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+        })
+        .await
+        .expect("unable to query");
+    assert_eq!(count, 2 * NUM_ROWS);
+    assert_eq!(sum, (2 * NUM_ROWS - 1) * 2 * NUM_ROWS / 2);
+    assert_eq!(names, 2 * NUM_ROWS);
+
+    // A row with the wrong number of values rolls back the whole insert.
+    let rows = vec![
+        vec![Value::Integer(-1), Value::Null, Value::Null],
+        vec![Value::Integer(-2)],
+    ];
+    let res = client
+        .bulk_insert("testing", &["id", "name", "score"], rows, 1)
+        .await;
+    assert!(matches!(
+        res,
+        Err(Error::Rusqlite(
+            async_sqlite::rusqlite::Error::InvalidParameterCount(1, 3)
+        ))
+    ));
+    let count: i64 = client
+        .conn(|conn| conn.query_row("SELECT COUNT(*) FROM testing", [], |row| row.get(0)))
+        .await
+        .expect("unable to query");
+    assert_eq!(count, 2 * NUM_ROWS);
+}
+
+async fn test_queue_capacity() {
+    let client = ClientBuilder::new()
+        .queue_capacity(1)
+        .open()
+        .await
+        .expect("client unable to be opened");
+
+    // Occupy the worker until released.
+    let (started_tx, started_rx) = std::sync::mpsc::channel();
+    let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+    let slow = {
+        let client = client.clone();
+        std::thread::spawn(move || {
+            client.conn_blocking(move |_| {
+                started_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+                Ok(())
+            })
+        })
+    };
+    started_rx.recv().unwrap();
+
+    // Blocking producers wait for space rather than growing the queue.
+    let producers: Vec<_> = (0..3)
+        .map(|i| {
+            let client = client.clone();
+            std::thread::spawn(move || client.conn_blocking(move |_| Ok(i)))
+        })
+        .collect();
+    for _ in 0..100 {
+        if client.queue_len() == 1 {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    assert_eq!(client.queue_len(), 1);
+
+    // Async callers wait for space too.
+    let mut call = Box::pin(client.conn(|_| Ok(3)));
+    assert!(futures_util::poll!(call.as_mut()).is_pending());
+    assert_eq!(client.queue_len(), 1);
+
+    release_tx.send(()).unwrap();
+    slow.join().unwrap().expect("unable to run slow call");
+    let mut values: Vec<i32> = producers
+        .into_iter()
+        .map(|p| p.join().unwrap().expect("unable to run call"))
+        .collect();
+    values.push(call.await.expect("unable to run call"));
+    values.sort();
+    assert_eq!(values, [0, 1, 2, 3]);
+
+    // Closing doesn't take a slot in the queue, and runs after the queued
+    // calls.
+    let (started_tx, started_rx) = std::sync::mpsc::channel();
+    let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+    let mut slow = Box::pin(client.conn(move |_| {
+        started_tx.send(()).unwrap();
+        release_rx.recv().unwrap();
+        Ok(())
+    }));
+    assert!(futures_util::poll!(slow.as_mut()).is_pending());
+    started_rx.recv().unwrap();
+    let mut queued = Box::pin(client.conn(|_| Ok(4)));
+    assert!(futures_util::poll!(queued.as_mut()).is_pending());
+    let mut close = Box::pin(client.close());
+    assert!(futures_util::poll!(close.as_mut()).is_pending());
+    assert_eq!(client.queue_len(), 2);
+    release_tx.send(()).unwrap();
+    slow.await.expect("unable to run slow call");
+    assert_eq!(queued.await.expect("unable to run call"), 4);
+    close.await.expect("unable to close client");
+    assert!(matches!(client.conn(|_| Ok(())).await, Err(Error::Closed)));
+    assert!(matches!(
+        client.conn_blocking(|_| Ok(())),
+        Err(Error::Closed)
+    ));
+}
+
+#[cfg(feature = "serialize")]
+async fn test_serialize() {
+    let src = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    src.execute_batch(
+        "CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT);
+        INSERT INTO testing VALUES (1, 'one'), (2, 'two');",
+    )
+    .await
+    .expect("unable to populate database");
+    let bytes = src.serialize(None).await.expect("unable to serialize");
+    assert!(bytes.starts_with(b"SQLite format 3\0"));
+
+    let dst = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    dst.deserialize(None, bytes)
+        .await
+        .expect("unable to deserialize");
+    let vals: Vec<String> = dst
+        .conn(|conn| {
+            conn.prepare("SELECT val FROM testing ORDER BY id")?
+                .query_map([], |row| row.get(0))?
+                .collect()
+        })
+        .await
+        .expect("unable to query");
+    assert_eq!(vals, ["one", "two"]);
+
+    // The deserialized database can be written to.
+    dst.execute("INSERT INTO testing VALUES (3, 'three')", [])
+        .await
+        .expect("unable to insert");
+
+    // Attached databases can be serialized by name.
+    dst.execute_batch("ATTACH DATABASE ':memory:' AS other; CREATE TABLE other.t (x);")
+        .await
+        .expect("unable to attach database");
+    let other = dst
+        .serialize(Some("other"))
+        .await
+        .expect("unable to serialize attached database");
+    assert!(!other.is_empty());
+    assert!(dst.serialize(Some("missing")).await.is_err());
+}
+
+async fn test_page_size() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let path = tmp_dir.path().join("sqlite.db");
+    let pool = PoolBuilder::new()
+        .path(&path)
+        .page_size(8192)
+        .journal_mode(JournalMode::Wal)
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    pool.execute_batch("CREATE TABLE testing (id INTEGER PRIMARY KEY);")
+        .await
+        .expect("unable to create table");
+    let res = pool
+        .conn_for_each(|conn| conn.pragma_query_value(None, "page_size", |row| row.get(0)))
+        .await;
+    for page_size in res {
+        let page_size: u32 = page_size.expect("unable to query page_size");
+        assert_eq!(page_size, 8192);
+    }
+    pool.close().await.expect("unable to close pool");
+
+    // The page size of an existing WAL database can't be changed.
+    let res = ClientBuilder::new()
+        .path(&path)
+        .page_size(4096)
+        .open()
+        .await;
+    assert!(matches!(res, Err(Error::PragmaUpdate { .. })));
+}
+
+async fn test_pool_conn_fold() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let pool = PoolBuilder::new()
+        .path(tmp_dir.path().join("sqlite.db"))
+        .num_conns(3)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    pool.execute_batch(
+        "CREATE TABLE testing (id INTEGER PRIMARY KEY); INSERT INTO testing VALUES (1), (2);",
+    )
+    .await
+    .expect("unable to populate table");
+
+    let total = pool
+        .conn_fold(
+            0,
+            |conn| {
+                conn.query_row("SELECT COUNT(*) FROM testing", [], |row| {
+                    row.get::<_, i64>(0)
+                })
+            },
+            |acc, count| acc + count,
+        )
+        .await
+        .expect("unable to fold");
+    assert_eq!(total, 6);
+
+    // Values are combined in connection order.
+    for index in 0..3 {
+        pool.conn_on(index, move |conn| {
+            conn.pragma_update(None, "cache_size", -100 - index as i64)
+        })
+        .await
+        .expect("unable to set cache_size");
+    }
+    let cache_sizes = pool
+        .conn_fold(
+            Vec::new(),
+            |conn| conn.pragma_query_value(None, "cache_size", |row| row.get::<_, i64>(0)),
+            |mut acc, cache_size| {
+                acc.push(cache_size);
+                acc
+            },
+        )
+        .await
+        .expect("unable to fold");
+    assert_eq!(cache_sizes, [-100, -101, -102]);
+
+    let res = pool
+        .conn_fold(
+            0,
+            |conn| conn.execute("SELECT * FROM missing", []),
+            |acc, n| acc + n,
+        )
+        .await;
+    assert!(matches!(res, Err(Error::Rusqlite(_))));
+}
+
+async fn test_attach() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let other = tmp_dir.path().join("other.db");
+    let pool = PoolBuilder::new()
+        .path(tmp_dir.path().join("sqlite.db"))
+        .attach(&other, "other")
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    pool.execute_batch(
+        "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);
+        CREATE TABLE other.scores (user_id INTEGER, score INTEGER);
+        INSERT INTO users VALUES (1, 'one'), (2, 'two');
+        INSERT INTO other.scores VALUES (1, 10), (2, 20), (2, 5);",
+    )
+    .await
+    .expect("unable to populate databases");
+
+    // Every connection can query across both databases.
+    let res = pool
+        .conn_for_each(|conn| {
+            conn.query_row(
+                "SELECT SUM(s.score) FROM users u JOIN other.scores s ON s.user_id = u.id
+                WHERE u.name = 'two'",
+                [],
+                |row| row.get(0),
+            )
+        })
+        .await;
+    for total in res {
+        let total: i64 = total.expect("unable to query");
+        assert_eq!(total, 25);
+    }
+
+    let res = ClientBuilder::new()
+        .attach(&other, "other")
+        .attach(tmp_dir.path().join("another.db"), "other")
+        .open()
+        .await;
+    match res {
+        Err(Error::Rusqlite(err)) => assert!(err.to_string().contains("already in use")),
+        res => panic!("unexpected result: {:?}", res.map(|_| ())),
+    }
+}
+
+async fn test_auto_reconnect() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let path = tmp_dir.path().join("sqlite.db");
+    let client = ClientBuilder::new()
+        .path(&path)
+        .auto_reconnect(true)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute_batch(
+            "CREATE TABLE testing (id INTEGER PRIMARY KEY); INSERT INTO testing VALUES (1);",
+        )
+        .await
+        .expect("writing schema and seed data");
+
+    let res = client
+        .conn(|_| -> Result<(), _> { std::panic::panic_any(Bomb(2)) })
+        .await;
+    assert!(matches!(res, Err(Error::Closed)));
+    while !client.is_closed() {
+        std::thread::yield_now();
+    }
+
+    let count: i64 = client
+        .query_row("SELECT COUNT(*) FROM testing", [], |row| row.get(0))
+        .await
+        .expect("querying after reconnect");
+    assert_eq!(count, 1);
+    assert!(!client.is_closed());
+
+    client.close().await.expect("closing client");
+    let res = client.conn(|_| Ok(())).await;
+    assert!(matches!(res, Err(Error::Closed)));
+
+    let client = ClientBuilder::new()
+        .path(&path)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let res = client
+        .conn(|_| -> Result<(), _> { std::panic::panic_any(Bomb(2)) })
+        .await;
+    assert!(matches!(res, Err(Error::Closed)));
+    while !client.is_closed() {
+        std::thread::yield_now();
+    }
+    let res = client.conn(|_| Ok(())).await;
+    assert!(matches!(res, Err(Error::Closed)));
+}
+
+async fn test_must_exist() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let path = tmp_dir.path().join("missing.db");
+
+    let res = ClientBuilder::new()
+        .path(&path)
+        .must_exist(true)
+        .open()
+        .await;
+    assert!(matches!(
+        res,
+        Err(Error::Rusqlite(async_sqlite::rusqlite::Error::SqliteFailure(err, _)))
+            if err.code == async_sqlite::rusqlite::ErrorCode::CannotOpen
+    ));
+    let res = PoolBuilder::new()
+        .path(&path)
+        .num_conns(2)
+        .must_exist(true)
+        .open()
+        .await;
+    assert!(res.is_err());
+    assert!(!path.exists());
+
+    let client = ClientBuilder::new()
+        .path(&path)
+        .must_exist(true)
+        .must_exist(false)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client.close().await.expect("closing client");
+    assert!(path.exists());
+
+    PoolBuilder::new()
+        .path(&path)
+        .must_exist(true)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+}
+
+async fn test_uri() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let path = tmp_dir.path().join("sqlite.db");
+    let client = ClientBuilder::new()
+        .path(&path)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute_batch(
+            "CREATE TABLE testing (id INTEGER PRIMARY KEY); INSERT INTO testing VALUES (1);",
+        )
+        .await
+        .expect("writing schema and seed data");
+    client.close().await.expect("closing client");
+
+    let uri = format!("file:{}?mode=ro&immutable=1", path.display());
+    let pool = PoolBuilder::new()
+        .path(&uri)
+        .flags(async_sqlite::rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .uri(true)
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    let count: i64 = pool
+        .query_row("SELECT COUNT(*) FROM testing", [], |row| row.get(0))
+        .await
+        .expect("reading immutable database");
+    assert_eq!(count, 1);
+    let res = pool.execute("INSERT INTO testing VALUES (2)", []).await;
+    assert_eq!(
+        res.unwrap_err().sqlite_error_code(),
+        Some(async_sqlite::rusqlite::ErrorCode::ReadOnly)
+    );
+}
+
+async fn test_full_mutex() {
+    use async_sqlite::rusqlite::{ffi, OpenFlags};
+
+    // SAFETY: the handle is only used on the worker thread that owns it.
+    let has_mutex = |conn: &async_sqlite::rusqlite::Connection| unsafe {
+        Ok(!ffi::sqlite3_db_mutex(conn.handle()).is_null())
+    };
+
+    let client = ClientBuilder::new()
+        .flags(OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    assert!(!client.conn(has_mutex).await.unwrap());
+
+    let client = ClientBuilder::new()
+        .full_mutex(true)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    assert!(client.conn(has_mutex).await.unwrap());
+
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .full_mutex(true)
+        .full_mutex(false)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    assert!(pool
+        .conn_for_each(has_mutex)
+        .await
+        .into_iter()
+        .all(|res| !res.unwrap()));
+}
+
+async fn test_clear_cache() {
+    use async_sqlite::rusqlite::{ffi, Connection};
+
+    // SAFETY: the handle is only used on the worker thread that owns it.
+    let has_statements = |conn: &Connection| unsafe {
+        Ok(!ffi::sqlite3_next_stmt(conn.handle(), std::ptr::null_mut()).is_null())
+    };
+
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    let results = pool
+        .conn_for_each(move |conn| {
+            conn.prepare_cached("SELECT 1")?
+                .query_row([], |row| row.get::<_, i64>(0))?;
+            has_statements(conn)
+        })
+        .await;
+    assert!(results.into_iter().all(|res| res.unwrap()));
+
+    pool.clear_cache().await.expect("clearing cache");
+    let results = pool.conn_for_each(has_statements).await;
+    assert!(results.into_iter().all(|res| !res.unwrap()));
+
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let cached = client
+        .conn(move |conn| {
+            conn.prepare_cached("SELECT 1")?;
+            has_statements(conn)
+        })
+        .await
+        .unwrap();
+    assert!(cached);
+    client.clear_cache().await.expect("clearing cache");
+    assert!(!client.conn(has_statements).await.unwrap());
+}
+
+async fn test_statement_cache_capacity() {
+    use async_sqlite::rusqlite::{ffi, Connection};
+
+    // Prepares several distinct cached statements, returning how many remain
+    // open in the cache.
+    fn cached_statements(conn: &Connection) -> Result<usize, async_sqlite::rusqlite::Error> {
+        for i in 0..4 {
+            conn.prepare_cached(&format!("SELECT {i}"))?;
+        }
+        let mut count = 0;
+        // SAFETY: the handle is only used on the worker thread that owns it.
+        unsafe {
+            let mut stmt = ffi::sqlite3_next_stmt(conn.handle(), std::ptr::null_mut());
+            while !stmt.is_null() {
+                count += 1;
+                stmt = ffi::sqlite3_next_stmt(conn.handle(), stmt);
+            }
+        }
+        Ok(count)
+    }
+
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    assert_eq!(client.conn(cached_statements).await.unwrap(), 4);
+
+    let client = ClientBuilder::new()
+        .statement_cache_capacity(0)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    assert_eq!(client.conn(cached_statements).await.unwrap(), 0);
+
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .statement_cache_capacity(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    let results = pool.conn_for_each(cached_statements).await;
+    assert!(results.into_iter().all(|res| res.unwrap() == 2));
+}
+
+async fn test_soft_heap_limit() {
+    // The limit is global to the process, so only a single large value is used
+    // across tests.
+    const LIMIT: i64 = 1 << 30;
+
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .soft_heap_limit(LIMIT)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    let results = pool
+        .conn_for_each(|conn| conn.pragma_query_value(None, "soft_heap_limit", |row| row.get(0)))
+        .await;
+    assert!(results
+        .into_iter()
+        .all(|res: Result<i64, _>| res.unwrap() == LIMIT));
+
+    let client = ClientBuilder::new()
+        .soft_heap_limit(LIMIT)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let limit: i64 = client
+        .conn(|conn| conn.pragma_query_value(None, "soft_heap_limit", |row| row.get(0)))
+        .await
+        .expect("querying soft_heap_limit");
+    assert_eq!(limit, LIMIT);
+}
+
+async fn test_cache_size() {
+    let client = ClientBuilder::new()
+        .cache_size(-4096)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let cache_size: i64 = client
+        .conn(|conn| conn.query_row("PRAGMA cache_size", (), |row| row.get(0)))
+        .await
+        .expect("client unable to fetch cache_size");
+    assert_eq!(cache_size, -4096);
+
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .cache_size(500)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    for _ in 0..2 {
+        let cache_size: i64 = pool
+            .conn(|conn| conn.query_row("PRAGMA cache_size", (), |row| row.get(0)))
+            .await
+            .expect("pool unable to fetch cache_size");
+        assert_eq!(cache_size, 500);
+    }
+}
+
+async fn test_pragmas() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let pool = PoolBuilder::new()
+        .path(tmp_dir.path().join("sqlite.db"))
+        .num_conns(2)
+        .pragma("synchronous", "NORMAL")
+        .pragma("journal_mode", "wal")
+        .pragma("foreign_keys", "ON")
+        .pragma("busy_timeout", "1234")
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    for _ in 0..2 {
+        let (synchronous, journal_mode, foreign_keys, busy_timeout): (i64, String, bool, i64) =
+            pool.conn(|conn| {
+                Ok((
+                    conn.query_row("PRAGMA synchronous", (), |row| row.get(0))?,
+                    conn.query_row("PRAGMA journal_mode", (), |row| row.get(0))?,
+                    conn.query_row("PRAGMA foreign_keys", (), |row| row.get(0))?,
+                    conn.query_row("PRAGMA busy_timeout", (), |row| row.get(0))?,
+                ))
+            })
+            .await
+            .expect("pool unable to fetch pragmas");
+        assert_eq!(synchronous, 1);
+        assert_eq!(journal_mode, "wal");
+        assert!(foreign_keys);
+        assert_eq!(busy_timeout, 1234);
+    }
+
+    // In-memory databases can only use the "memory" or "off" journal modes.
+    let res = ClientBuilder::new()
+        .pragma("journal_mode", "wal")
+        .open()
+        .await;
+    match res {
+        Err(Error::PragmaUpdate { name, exp, got }) => {
+            assert_eq!(name, "journal_mode");
+            assert_eq!(exp, "wal");
+            assert_eq!(got, "memory");
+        }
+        _ => panic!("expected pragma update error"),
+    }
+}
+
+async fn test_verify_pragmas() {
+    for verify in [true, false] {
+        let client = ClientBuilder::new()
+            .journal_mode(JournalMode::Memory)
+            .verify_pragmas(verify)
+            .open()
+            .await
+            .expect("client unable to be opened");
+        let mode: String = client
+            .conn(|conn| conn.pragma_query_value(None, "journal_mode", |row| row.get(0)))
+            .await
+            .expect("querying journal_mode");
+        assert_eq!(mode, "memory");
+    }
+
+    // In-memory databases always report the memory journal mode.
+    let res = ClientBuilder::new()
+        .journal_mode(JournalMode::Wal)
+        .open()
+        .await;
+    assert!(matches!(
+        res,
+        Err(Error::PragmaUpdate { name, got, .. }) if name == "journal_mode" && got == "memory"
+    ));
+    PoolBuilder::new()
+        .num_conns(2)
+        .journal_mode(JournalMode::Wal)
+        .pragma("journal_mode", "truncate")
+        .verify_pragmas(false)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+}
+
+async fn test_read_only() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let path = tmp_dir.path().join("sqlite.db");
+    let client = ClientBuilder::new()
+        .path(&path)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .conn(|conn| {
+            conn.execute(
+                "CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL)",
+                (),
+            )
+        })
+        .await
+        .expect("writing schema");
+    client.close().await.expect("closing client");
+
+    let pool = PoolBuilder::new()
+        .path(&path)
+        .num_conns(2)
+        .read_only(true)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    for _ in 0..2 {
+        let res = pool
+            .conn(|conn| conn.execute("INSERT INTO testing VALUES (1, ?)", ["value1"]))
+            .await;
+        match res {
+            Err(Error::Rusqlite(err)) => assert_eq!(
+                err.sqlite_error_code(),
+                Some(async_sqlite::rusqlite::ErrorCode::ReadOnly)
+            ),
+            _ => panic!("expected read-only error"),
+        }
+    }
+
+    let res = ClientBuilder::new()
+        .path(tmp_dir.path().join("missing.db"))
+        .read_only(true)
+        .open()
+        .await;
+    assert!(matches!(res, Err(Error::Rusqlite(_))));
+}
+
+#[cfg(feature = "serde")]
+async fn test_query_as() {
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct User {
+        id: i64,
+        name: String,
+        score: Option<f64>,
+    }
+
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute_batch(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, score REAL, extra TEXT);
+            INSERT INTO users VALUES (1, 'one', 1.5, 'x'), (2, 'two', NULL, 'y');",
+        )
+        .await
+        .expect("writing schema and seed data");
+
+    // Columns without a matching field are ignored.
+    let users: Vec<User> = client
+        .query_as("SELECT * FROM users ORDER BY id", [])
+        .await
+        .expect("querying users");
+    assert_eq!(
+        users,
+        vec![
+            User {
+                id: 1,
+                name: "one".to_owned(),
+                score: Some(1.5),
+            },
+            User {
+                id: 2,
+                name: "two".to_owned(),
+                score: None,
+            },
+        ]
+    );
+
+    let rows: Vec<(i64, String)> = client
+        .query_as("SELECT id, name FROM users WHERE id > ?", [1])
+        .await
+        .expect("querying tuples");
+    assert_eq!(rows, vec![(2, "two".to_owned())]);
+    let ids: Vec<i64> = client
+        .query_as("SELECT id FROM users ORDER BY id", [])
+        .await
+        .expect("querying scalars");
+    assert_eq!(ids, vec![1, 2]);
+    let scores: Vec<Option<f64>> = client
+        .query_as("SELECT score FROM users ORDER BY id", [])
+        .await
+        .expect("querying optional scalars");
+    assert_eq!(scores, vec![Some(1.5), None]);
+
+    let res = client
+        .query_as::<User, _>("SELECT id, score FROM users", [])
+        .await;
+    match res {
+        Err(Error::Deserialize(msg)) => assert!(msg.contains("missing field `name`"), "{msg}"),
+        res => panic!("unexpected result: {res:?}"),
+    }
+    let res = client
+        .query_as::<User, _>("SELECT name AS id, name, score FROM users", [])
+        .await;
+    match res {
+        Err(Error::Deserialize(msg)) => assert!(msg.contains("column: id"), "{msg}"),
+        res => panic!("unexpected result: {res:?}"),
+    }
+    let res = client.query_as::<i64, _>("SELECT * FROM missing", []).await;
+    assert!(matches!(res, Err(Error::Rusqlite(_))));
+
+    let pool = PoolBuilder::new()
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    let rows: Vec<(bool, Option<String>)> = pool
+        .query_as("SELECT 1, NULL UNION ALL SELECT 0, 'text'", [])
+        .await
+        .expect("querying pool");
+    assert_eq!(rows, vec![(true, None), (false, Some("text".to_owned()))]);
+}
+
+// A minimal subscriber that records spans and events, so that tests can
+// assert on what was emitted without depending on tracing-subscriber.
+#[cfg(feature = "tracing")]
+#[derive(Clone, Default)]
+struct Recorder(std::sync::Arc<std::sync::Mutex<Recorded>>);
+
+#[cfg(feature = "tracing")]
+#[derive(Default)]
+struct Recorded {
+    spans: Vec<RecordedSpan>,
+    events: Vec<RecordedEvent>,
+    stacks: std::collections::HashMap<std::thread::ThreadId, Vec<u64>>,
+}
+
+#[cfg(feature = "tracing")]
+struct RecordedSpan {
+    name: &'static str,
+    parent: Option<u64>,
+    fields: Vec<&'static str>,
+    entered_on: Vec<std::thread::ThreadId>,
+}
+
+#[cfg(feature = "tracing")]
+struct RecordedEvent {
+    level: tracing::Level,
+    span: Option<u64>,
+    message: String,
+}
+
+#[cfg(feature = "tracing")]
+impl Recorded {
+    fn current(&self) -> Option<u64> {
+        let stack = self.stacks.get(&std::thread::current().id())?;
+        stack.last().copied()
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl tracing::Subscriber for Recorder {
+    fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attrs: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        let mut recorded = self.0.lock().unwrap();
+        let parent = match attrs.parent() {
+            Some(id) => Some(id.into_u64()),
+            None if attrs.is_contextual() => recorded.current(),
+            None => None,
+        };
+        recorded.spans.push(RecordedSpan {
+            name: attrs.metadata().name(),
+            parent,
+            fields: Vec::new(),
+            entered_on: Vec::new(),
+        });
+        tracing::span::Id::from_u64(recorded.spans.len() as u64)
+    }
+
+    fn record(&self, span: &tracing::span::Id, values: &tracing::span::Record<'_>) {
+        struct Fields<'a>(&'a mut Vec<&'static str>);
+        impl tracing::field::Visit for Fields<'_> {
+            fn record_debug(&mut self, field: &tracing::field::Field, _: &dyn std::fmt::Debug) {
+                self.0.push(field.name());
+            }
+        }
+        let mut recorded = self.0.lock().unwrap();
+        let span = &mut recorded.spans[span.into_u64() as usize - 1];
+        values.record(&mut Fields(&mut span.fields));
+    }
+
+    fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+
+    fn event(&self, event: &tracing::Event<'_>) {
+        struct Message<'a>(&'a mut String);
+        impl tracing::field::Visit for Message<'_> {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    *self.0 = format!("{value:?}");
+                }
+            }
+        }
+        let mut message = String::new();
+        event.record(&mut Message(&mut message));
+        let mut recorded = self.0.lock().unwrap();
+        let span = recorded.current();
+        recorded.events.push(RecordedEvent {
+            level: *event.metadata().level(),
+            span,
+            message,
+        });
+    }
+
+    fn enter(&self, span: &tracing::span::Id) {
+        let thread = std::thread::current().id();
+        let mut recorded = self.0.lock().unwrap();
+        recorded.spans[span.into_u64() as usize - 1]
+            .entered_on
+            .push(thread);
+        recorded
+            .stacks
+            .entry(thread)
+            .or_default()
+            .push(span.into_u64());
+    }
+
+    fn exit(&self, _span: &tracing::span::Id) {
+        let thread = std::thread::current().id();
+        let mut recorded = self.0.lock().unwrap();
+        recorded.stacks.entry(thread).or_default().pop();
+    }
+}
+
+#[cfg(feature = "tracing")]
+async fn test_tracing() {
+    use tracing::Instrument;
+
+    let recorder = Recorder::default();
+    let _guard = tracing::subscriber::set_default(recorder.clone());
+
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let path = tmp_dir.path().join("sqlite.db");
+    let client = ClientBuilder::new()
+        .path(&path)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let caller = tracing::info_span!("caller");
+    client
+        .conn(|conn| conn.execute_batch("CREATE TABLE testing (id INTEGER PRIMARY KEY)"))
+        .instrument(caller.clone())
+        .await
+        .expect("writing schema");
+    client
+        .conn_blocking(|conn| conn.execute_batch("SELECT * FROM missing"))
+        .expect_err("querying a missing table");
+    client
+        .conn_and_then_blocking(|conn| -> Result<(), Error> {
+            Ok(conn.execute_batch("SELECT * FROM missing")?)
+        })
+        .expect_err("querying a missing table");
+
+    // Hold an exclusive lock so that a second connection finds the database
+    // busy.
+    let locker = ClientBuilder::new()
+        .path(&path)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    locker
+        .conn(|conn| conn.execute_batch("BEGIN EXCLUSIVE"))
+        .await
+        .expect("locking database");
+    client
+        .conn(|conn| {
+            conn.busy_timeout(std::time::Duration::ZERO)?;
+            conn.execute_batch("BEGIN EXCLUSIVE")
+        })
+        .instrument(caller.clone())
+        .await
+        .expect_err("database should be busy");
+
+    let recorded = recorder.0.lock().unwrap();
+    let caller_id = caller.id().expect("caller span enabled").into_u64();
+    let spans: Vec<_> = recorded
+        .spans
+        .iter()
+        .filter(|span| span.name == "async_sqlite.conn")
+        .collect();
+    assert_eq!(spans.len(), 5);
+    assert_eq!(spans[0].parent, Some(caller_id));
+    assert_eq!(spans[1].parent, None);
+    assert_eq!(spans[2].parent, None);
+    assert_eq!(spans[3].parent, None);
+    assert_eq!(spans[4].parent, Some(caller_id));
+    for span in &spans {
+        assert_eq!(span.fields, ["queue_us", "exec_us"]);
+        assert_eq!(span.entered_on.len(), 1);
+        assert_ne!(span.entered_on[0], std::thread::current().id());
+    }
+
+    let warnings: Vec<_> = recorded
+        .events
+        .iter()
+        .filter(|event| event.level == tracing::Level::WARN)
+        .collect();
+    assert_eq!(warnings.len(), 3);
+    assert_eq!(warnings[0].message, "call failed");
+    assert_eq!(warnings[1].message, "call failed");
+    assert_eq!(warnings[2].message, "database is busy");
+    for event in warnings {
+        let span = &recorded.spans[event.span.expect("event in span") as usize - 1];
+        assert_eq!(span.name, "async_sqlite.conn");
+    }
+}
+
+#[cfg(feature = "functions")]
+async fn test_on_open() {
+    use async_sqlite::rusqlite::functions::FunctionFlags;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    let opened = Arc::new(AtomicUsize::new(0));
+    let counter = opened.clone();
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .on_open(Arc::new(move |conn| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            conn.create_scalar_function(
+                "double",
+                1,
+                FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+                |ctx| Ok(ctx.get::<i64>(0)? * 2),
+            )
+        }))
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    assert_eq!(opened.load(Ordering::SeqCst), 2);
+
+    for i in 0..4 {
+        let val: i64 = pool
+            .query_row("SELECT double(?1)", (i,), |row| row.get(0))
+            .await
+            .expect("calling custom function");
+        assert_eq!(val, i * 2);
+    }
+    pool.close().await.expect("closing pool");
+
+    let res = ClientBuilder::new()
+        .on_open(Arc::new(|conn| conn.execute_batch("NOT VALID SQL")))
+        .open()
+        .await;
+    assert!(matches!(res, Err(Error::Rusqlite(_))));
+}
+
+async fn test_db_config() {
+    use async_sqlite::rusqlite::config::DbConfig;
+
+    const SQL: &str = "PRAGMA writable_schema = ON;
+        UPDATE sqlite_schema SET sql = sql WHERE name = 'testing';
+        PRAGMA writable_schema = OFF;";
+
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let path = tmp_dir.path().join("sqlite.db");
+    let client = ClientBuilder::new()
+        .path(&path)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute_batch("CREATE TABLE testing (id INTEGER PRIMARY KEY)")
+        .await
+        .expect("writing schema");
+    client
+        .execute_batch(SQL)
+        .await
+        .expect("writing schema table");
+
+    let pool = PoolBuilder::new()
+        .path(&path)
+        .db_config(DbConfig::SQLITE_DBCONFIG_DEFENSIVE, true)
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    for _ in 0..2 {
+        let res = pool.execute_batch(SQL).await;
+        assert!(matches!(res, Err(Error::Rusqlite(_))));
+    }
+    let enabled = pool
+        .try_conn_for_each(|conn| conn.db_config(DbConfig::SQLITE_DBCONFIG_DEFENSIVE))
+        .await
+        .expect("querying db config");
+    assert_eq!(enabled, vec![true; 2]);
+}
+
+async fn test_foreign_keys() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let pool = PoolBuilder::new()
+        .path(tmp_dir.path().join("sqlite.db"))
+        .foreign_keys(true)
+        .num_conns(3)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    let enabled: Vec<bool> = pool
+        .try_conn_for_each(|conn| conn.pragma_query_value(None, "foreign_keys", |row| row.get(0)))
+        .await
+        .expect("querying foreign_keys");
+    assert_eq!(enabled, vec![true; 3]);
+
+    pool.execute_batch(
+        "CREATE TABLE parent (id INTEGER PRIMARY KEY);
+        CREATE TABLE child (id INTEGER PRIMARY KEY, parent INTEGER REFERENCES parent (id));",
+    )
+    .await
+    .expect("writing schema");
+    for _ in 0..3 {
+        let res = pool.execute("INSERT INTO child VALUES (NULL, 1)", []).await;
+        assert_eq!(
+            res.unwrap_err().sqlite_error_code(),
+            Some(async_sqlite::rusqlite::ErrorCode::ConstraintViolation)
+        );
+    }
+
+    let client = ClientBuilder::new()
+        .foreign_keys(true)
+        .foreign_keys(false)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let enabled: bool = client
+        .conn(|conn| conn.pragma_query_value(None, "foreign_keys", |row| row.get(0)))
+        .await
+        .expect("querying foreign_keys");
+    assert!(!enabled);
+}
+
+async fn test_builder_clone() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    let opened = Arc::new(AtomicUsize::new(0));
+    let counter = opened.clone();
+    let on_open: Arc<dyn Fn(&mut async_sqlite::rusqlite::Connection) -> _ + Send + Sync> =
+        Arc::new(move |conn| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            conn.execute_batch("CREATE TEMP TABLE opened (id INTEGER PRIMARY KEY)")
+        });
+
+    let builder = ClientBuilder::new().on_open(on_open.clone());
+    let client = builder
+        .clone()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute("INSERT INTO opened VALUES (1)", [])
+        .await
+        .expect("writing to table created on open");
+    // Callbacks are skipped when formatting the builder.
+    assert!(format!("{builder:?}").ends_with(", .. }"));
+
+    let builder = PoolBuilder::new().num_conns(2).on_open(on_open);
+    let pool = builder
+        .clone()
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    pool.try_conn_for_each(|conn| conn.execute("INSERT INTO opened VALUES (1)", []))
+        .await
+        .expect("writing to table created on open");
+    assert!(format!("{builder:?}").contains(", .. }"));
+    assert_eq!(opened.load(Ordering::SeqCst), 3);
+}
+
+#[cfg(feature = "functions")]
+async fn test_pool_conn_for_each() {
+    use async_sqlite::rusqlite::functions::FunctionFlags;
+
+    let pool = PoolBuilder::new()
+        .num_conns(3)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+
+    let results = pool
+        .conn_mut_for_each(|conn| {
+            conn.create_scalar_function("triple", 1, FunctionFlags::SQLITE_UTF8, |ctx| {
+                Ok(ctx.get::<i64>(0)? * 3)
+            })
+        })
+        .await;
+    assert_eq!(results.len(), 3);
+    assert!(results.iter().all(Result::is_ok));
+
+    let results = pool
+        .conn_for_each(|conn| conn.query_row("SELECT triple(?1)", [2], |row| row.get::<_, i64>(0)))
+        .await;
+    let values = results
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("calling function on each connection");
+    assert_eq!(values, vec![6, 6, 6]);
+}
+
+async fn test_pool_try_conn_for_each() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let pool = PoolBuilder::new()
+        .num_conns(3)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+
+    let values = pool
+        .try_conn_for_each(|conn| conn.query_row("SELECT 1", [], |row| row.get::<_, i64>(0)))
+        .await
+        .expect("querying each connection");
+    assert_eq!(values, vec![1, 1, 1]);
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let res = pool
+        .try_conn_for_each(move |conn| {
+            if counter.fetch_add(1, Ordering::SeqCst) == 1 {
+                conn.execute_batch("NOT VALID SQL")?;
+            }
+            Ok(())
+        })
+        .await;
+    assert!(matches!(res, Err(Error::Rusqlite(_))));
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+}
+
+async fn test_drop_interrupts() {
+    use futures_util::FutureExt;
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+    use std::time::{Duration, Instant};
+
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+
+    // The query never completes unless it's interrupted.
+    let started = Arc::new(AtomicBool::new(false));
+    let flag = started.clone();
+    let mut fut = Box::pin(client.conn(move |conn| {
+        flag.store(true, Ordering::SeqCst);
+        conn.query_row(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n)
+            SELECT COUNT(*) FROM n",
+            [],
+            |row| row.get::<_, i64>(0),
+        )
+    }));
+    assert!((&mut fut).now_or_never().is_none());
+    while !started.load(Ordering::SeqCst) {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let start = Instant::now();
+    drop(fut);
+
+    let val: i64 = client
+        .query_row("SELECT 1", [], |row| row.get(0))
+        .await
+        .expect("querying after interrupt");
+    assert_eq!(val, 1);
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    // A dropped call that hasn't started yet is skipped.
+    let ran = Arc::new(AtomicBool::new(false));
+    let flag = ran.clone();
+    let blocker = client.conn(|_| {
+        std::thread::sleep(Duration::from_millis(50));
+        Ok(())
+    });
+    let mut blocker = Box::pin(blocker);
+    assert!((&mut blocker).now_or_never().is_none());
+    let mut fut = Box::pin(client.conn(move |_| {
+        flag.store(true, Ordering::SeqCst);
+        Ok(())
+    }));
+    assert!((&mut fut).now_or_never().is_none());
+    drop(fut);
+    blocker.await.expect("running blocker");
+    client.conn(|_| Ok(())).await.expect("running call");
+    assert!(!ran.load(Ordering::SeqCst));
+}
+
+async fn test_query_stream() {
+    use futures_util::StreamExt;
+
+    const SQL: &str =
+        "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 1000)
+        SELECT i FROM n";
+
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let vals: Vec<i64> = client
+        .query_stream(SQL, [], |row| row.get::<_, i64>(0))
+        .await
+        .map(|res| res.expect("reading row"))
+        .collect()
+        .await;
+    assert_eq!(vals, (1..=1000).collect::<Vec<_>>());
+
+    let mut stream = client
+        .query_stream("SELECT * FROM missing", [], |row| row.get::<_, i64>(0))
+        .await;
+    assert!(matches!(stream.next().await, Some(Err(Error::Rusqlite(_)))));
+    assert!(stream.next().await.is_none());
+
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    let mut stream = pool.query_stream(SQL, [], |row| row.get::<_, i64>(0)).await;
+    assert_eq!(stream.next().await.unwrap().unwrap(), 1);
+
+    // While the stream is alive, requests are dispatched to the other
+    // connection.
+    let query = || pool.query_row("SELECT 1", [], |row| row.get::<_, i64>(0));
+    let before = pool.stats().per_conn_requests;
+    for _ in 0..4 {
+        query().await.expect("querying pool");
+    }
+    let after = pool.stats().per_conn_requests;
+    let deltas: Vec<u64> = after.iter().zip(&before).map(|(a, b)| a - b).collect();
+    assert!(deltas.contains(&0) && deltas.contains(&4), "{deltas:?}");
+
+    // Dropping the stream early stops the query and releases the connection.
+    drop(stream);
+    let before = pool.stats().per_conn_requests;
+    for _ in 0..4 {
+        query().await.expect("querying pool");
+    }
+    let after = pool.stats().per_conn_requests;
+    assert!(after.iter().zip(&before).all(|(a, b)| a - b == 2));
+}
+
+#[cfg(feature = "trace")]
+async fn test_profile() {
+    use std::sync::{Arc, Mutex};
+
+    let profiled = Arc::new(Mutex::new(Vec::new()));
+    let log = profiled.clone();
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .profile(Arc::new(move |sql, duration| {
+            log.lock().unwrap().push((sql.to_owned(), duration));
+        }))
+        .open()
+        .await
+        .expect("pool unable to be opened");
+
+    let heavy = "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c LIMIT 1000000)
+        SELECT COUNT(*) FROM c";
+    let count: i64 = pool
+        .query_row(heavy, [], |row| row.get(0))
+        .await
+        .expect("running heavy query");
+    assert_eq!(count, 1000000);
+
+    let profiled = profiled.lock().unwrap();
+    let (_, duration) = profiled
+        .iter()
+        .find(|(sql, _)| sql == heavy)
+        .expect("heavy query was profiled");
+    assert!(!duration.is_zero());
+}
+
+#[cfg(feature = "trace")]
+async fn test_trace() {
+    use std::sync::{Arc, Mutex};
+
+    let traced = Arc::new(Mutex::new(Vec::new()));
+    let log = traced.clone();
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .synchronous(Synchronous::Normal)
+        .trace(Arc::new(move |sql| {
+            log.lock().unwrap().push(sql.to_owned())
+        }))
+        .open()
+        .await
+        .expect("pool unable to be opened");
+
+    let val: String = pool
+        .query_row("SELECT ?1 || ?2", ("traced", "value"), |row| row.get(0))
+        .await
+        .expect("querying for result");
+    assert_eq!(val, "tracedvalue");
+
+    let traced = traced.lock().unwrap();
+    assert_eq!(
+        traced
+            .iter()
+            .filter(|sql| *sql == "PRAGMA synchronous='NORMAL'")
+            .count(),
+        2
+    );
+    assert!(traced.iter().any(|sql| sql == "SELECT 'traced' || 'value'"));
+}
+
+#[cfg(feature = "functions")]
+async fn test_aggregate_function() {
+    use async_sqlite::rusqlite::functions::{Aggregate, Context, FunctionFlags};
+
+    #[derive(Clone)]
+    struct Median;
+
+    impl Aggregate<Vec<f64>, Option<f64>> for Median {
+        fn init(&self, _: &mut Context<'_>) -> async_sqlite::rusqlite::Result<Vec<f64>> {
+            Ok(Vec::new())
+        }
+
+        fn step(
+            &self,
+            ctx: &mut Context<'_>,
+            values: &mut Vec<f64>,
+        ) -> async_sqlite::rusqlite::Result<()> {
+            values.push(ctx.get(0)?);
+            Ok(())
+        }
+
+        fn finalize(
+            &self,
+            _: &mut Context<'_>,
+            values: Option<Vec<f64>>,
+        ) -> async_sqlite::rusqlite::Result<Option<f64>> {
+            let Some(mut values) = values.filter(|v| !v.is_empty()) else {
+                return Ok(None);
+            };
+            values.sort_by(f64::total_cmp);
+            let mid = values.len() / 2;
+            Ok(Some(if values.len() % 2 == 0 {
+                (values[mid - 1] + values[mid]) / 2.0
+            } else {
+                values[mid]
+            }))
+        }
+    }
+
+    let flags = FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC;
+    const SQL: &str = "SELECT median(column1) FROM (VALUES (3), (1), (4), (1), (5))";
+
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .create_aggregate_function("median", 1, flags, Median)
+        .await
+        .expect("registering aggregate");
+    let median: Option<f64> = client
+        .query_row(SQL, [], |row| row.get(0))
+        .await
+        .expect("calling aggregate");
+    assert_eq!(median, Some(3.0));
+    let empty: Option<f64> = client
+        .query_row("SELECT median(1) WHERE 0", [], |row| row.get(0))
+        .await
+        .expect("calling aggregate on no rows");
+    assert_eq!(empty, None);
+
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    pool.create_aggregate_function("median", 1, flags, Median)
+        .await
+        .expect("registering aggregate");
+    let medians = pool
+        .try_conn_for_each(|conn| conn.query_row(SQL, [], |row| row.get::<_, f64>(0)))
+        .await
+        .expect("calling aggregate on each connection");
+    assert_eq!(medians, vec![3.0, 3.0]);
+}
+
+#[cfg(feature = "window")]
+async fn test_window_function() {
+    use async_sqlite::rusqlite::functions::{Aggregate, Context, FunctionFlags, WindowAggregate};
+
+    #[derive(Clone)]
+    struct Sum;
+
+    impl Aggregate<i64, i64> for Sum {
+        fn init(&self, _: &mut Context<'_>) -> async_sqlite::rusqlite::Result<i64> {
+            Ok(0)
+        }
+
+        fn step(&self, ctx: &mut Context<'_>, sum: &mut i64) -> async_sqlite::rusqlite::Result<()> {
+            *sum += ctx.get::<i64>(0)?;
+            Ok(())
+        }
+
+        fn finalize(
+            &self,
+            _: &mut Context<'_>,
+            sum: Option<i64>,
+        ) -> async_sqlite::rusqlite::Result<i64> {
+            Ok(sum.unwrap_or(0))
+        }
+    }
+
+    impl WindowAggregate<i64, i64> for Sum {
+        fn value(&self, sum: Option<&mut i64>) -> async_sqlite::rusqlite::Result<i64> {
+            Ok(sum.copied().unwrap_or(0))
+        }
+
+        fn inverse(
+            &self,
+            ctx: &mut Context<'_>,
+            sum: &mut i64,
+        ) -> async_sqlite::rusqlite::Result<()> {
+            *sum -= ctx.get::<i64>(0)?;
+            Ok(())
+        }
+    }
+
+    const SQL: &str =
+        "SELECT sumint(column1) OVER (ORDER BY column1 ROWS BETWEEN 1 PRECEDING AND CURRENT ROW)
+        FROM (VALUES (1), (2), (3), (4))";
+
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    pool.create_window_function("sumint", 1, FunctionFlags::SQLITE_UTF8, Sum)
+        .await
+        .expect("registering window function");
+    for _ in 0..2 {
+        let sums = pool
+            .conn(|conn| {
+                conn.prepare(SQL)?
+                    .query_map([], |row| row.get::<_, i64>(0))?
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .await
+            .expect("calling window function");
+        assert_eq!(sums, vec![1, 3, 5, 7]);
+    }
+}
+
+async fn test_wal_reader_pool() {
+    use async_sqlite::rusqlite::ErrorCode;
+
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let path = tmp_dir.path().join("sqlite.db");
+    let client = ClientBuilder::new()
+        .path(&path)
+        .journal_mode(JournalMode::Wal)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute_batch("CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL)")
+        .await
+        .expect("creating table");
+    client.close().await.expect("closing client");
+
+    let pool = PoolBuilder::new()
+        .path(&path)
+        .journal_mode(JournalMode::Wal)
+        .wal_reader_pool(true)
+        .num_conns(4)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+
+    let err = pool
+        .conn_on(1, |conn| {
+            conn.execute("INSERT INTO testing (val) VALUES ('reader')", [])
+        })
+        .await
+        .expect_err("reader should be read-only");
+    assert_eq!(err.sqlite_error_code(), Some(ErrorCode::ReadOnly));
+
+    let fs = (0..200).map(|i| {
+        let pool = pool.clone();
+        async move {
+            if i % 10 == 0 {
+                pool.execute("INSERT INTO testing (val) VALUES (?1)", [i.to_string()])
+                    .await
+                    .map(|_| ())
+            } else {
+                pool.query_row("SELECT COUNT(*) FROM testing", [], |row| {
+                    row.get::<_, i64>(0)
+                })
+                .await
+                .map(|_| ())
+            }
+        }
+    });
+    for res in futures_util::future::join_all(fs).await {
+        res.expect("running concurrent call");
+    }
+
+    let count: i64 = pool
+        .query_row("SELECT COUNT(*) FROM testing", [], |row| row.get(0))
+        .await
+        .expect("counting rows");
+    assert_eq!(count, 20);
+    let stats = pool.stats();
+    assert_eq!(stats.per_conn_requests[0], 20);
+    assert_eq!(stats.per_conn_requests.iter().sum::<u64>(), 202);
+
+    pool.acquire()
+        .expect("acquiring connection")
+        .conn(|conn| conn.execute("DELETE FROM testing", []))
+        .await
+        .expect("writing through leased connection");
+    pool.close().await.expect("closing pool");
+
+    let pool = PoolBuilder::new()
+        .path(&path)
+        .wal_reader_pool(true)
+        .num_conns(1)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    pool.execute("INSERT INTO testing (val) VALUES ('writer')", [])
+        .await
+        .expect("writing to single connection pool");
+    let count: i64 = pool
+        .query_row("SELECT COUNT(*) FROM testing", [], |row| row.get(0))
+        .await
+        .expect("counting rows");
+    assert_eq!(count, 1);
+}
+
+async fn test_pool_lazy() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc,
+    };
+
+    let pool = PoolBuilder::new()
+        .lazy(true)
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    assert_eq!(pool.num_conns(), 1);
+    for _ in 0..3 {
+        pool.conn(|conn| conn.execute_batch("SELECT 1"))
+            .await
+            .expect("running sequential call");
+    }
+    assert_eq!(pool.num_conns(), 1);
+
+    // The first call blocks its connection until the second call, which has
+    // to open another connection, has completed.
+    let (tx, rx) = mpsc::channel::<()>();
+    let blocked = pool.conn(move |_| {
+        rx.recv().unwrap();
+        Ok(())
+    });
+    let forced = async {
+        let res = pool.conn(|conn| conn.execute_batch("SELECT 1")).await;
+        tx.send(()).unwrap();
+        res
+    };
+    let (blocked, forced) = futures_util::future::join(blocked, forced).await;
+    blocked.expect("running blocked call");
+    forced.expect("running call on new connection");
+    assert_eq!(pool.num_conns(), 2);
+
+    // The pool doesn't grow past `num_conns`.
+    let fs = (0..8).map(|_| pool.conn(|conn| conn.execute_batch("SELECT 1")));
+    for res in futures_util::future::join_all(fs).await {
+        res.expect("running concurrent call");
+    }
+    assert_eq!(pool.num_conns(), 2);
+    pool.close().await.expect("closing pool");
+
+    let opened = Arc::new(AtomicUsize::new(0));
+    let counter = opened.clone();
+    let pool = PoolBuilder::new()
+        .lazy(true)
+        .num_conns(2)
+        .on_open(Arc::new(move |conn| {
+            if counter.fetch_add(1, Ordering::SeqCst) > 0 {
+                conn.execute_batch("NOT VALID SQL")?;
+            }
+            Ok(())
+        }))
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    let (tx, rx) = mpsc::channel::<()>();
+    let blocked = pool.conn(move |_| {
+        rx.recv().unwrap();
+        Ok(())
+    });
+    let failed = async {
+        let res = pool.conn(|_| Ok(())).await;
+        tx.send(()).unwrap();
+        res
+    };
+    let (blocked, failed) = futures_util::future::join(blocked, failed).await;
+    blocked.expect("running blocked call");
+    assert!(matches!(failed, Err(Error::Rusqlite(_))));
+    assert_eq!(pool.num_conns(), 1);
+    assert_eq!(opened.load(Ordering::SeqCst), 2);
+}
+
+async fn test_conn_affinity() {
+    let pool = PoolBuilder::new()
+        .num_conns(4)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+
+    let mut indexes = Vec::new();
+    for tenant in ["a", "b", "c", "d", "e"] {
+        let before = pool.stats().per_conn_requests;
+        for _ in 0..3 {
+            pool.conn_affinity(tenant, |conn| conn.execute_batch("SELECT 1"))
+                .await
+                .expect("running call with affinity");
+        }
+        let after = pool.stats().per_conn_requests;
+        let used = (0..after.len())
+            .filter(|&i| after[i] != before[i])
+            .collect::<Vec<_>>();
+        assert_eq!(used.len(), 1);
+        assert_eq!(after[used[0]] - before[used[0]], 3);
+        indexes.push(used[0]);
+    }
+
+    // The same key keeps routing to the same connection.
+    for (tenant, index) in ["a", "b", "c", "d", "e"].into_iter().zip(indexes) {
+        let before = pool.stats().per_conn_requests[index];
+        pool.conn_affinity(tenant, |conn| conn.execute_batch("SELECT 1"))
+            .await
+            .expect("running call with affinity");
+        assert_eq!(pool.stats().per_conn_requests[index], before + 1);
+    }
+}
+
+#[cfg(feature = "preupdate_hook")]
+async fn test_preupdate_hook() {
+    use async_sqlite::rusqlite::{
+        hooks::{Action, PreUpdateCase},
+        types::Value,
+    };
+    use std::sync::{Arc, Mutex};
+
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute_batch(
+            "CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT NOT NULL);
+            INSERT INTO testing VALUES (1, 'old');",
+        )
+        .await
+        .expect("writing schema and seed data");
+
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let captured = changes.clone();
+    client
+        .preupdate_hook(Some(
+            move |action: Action, _: &str, table: &str, case: &PreUpdateCase| {
+                if let PreUpdateCase::Update {
+                    old_value_accessor,
+                    new_value_accessor,
+                } = case
+                {
+                    let old = Value::from(old_value_accessor.get_old_column_value(1).unwrap());
+                    let new = Value::from(new_value_accessor.get_new_column_value(1).unwrap());
+                    captured
+                        .lock()
+                        .unwrap()
+                        .push((action, table.to_owned(), old, new));
+                }
+            },
+        ))
+        .await
+        .expect("registering preupdate hook");
+
+    client
+        .execute("UPDATE testing SET val = 'new' WHERE id = 1", [])
+        .await
+        .expect("updating row");
+    assert_eq!(
+        *changes.lock().unwrap(),
+        vec![(
+            Action::SQLITE_UPDATE,
+            "testing".to_owned(),
+            Value::Text("old".to_owned()),
+            Value::Text("new".to_owned()),
+        )]
+    );
+
+    client
+        .preupdate_hook(None::<fn(Action, &str, &str, &PreUpdateCase)>)
+        .await
+        .expect("removing preupdate hook");
+    client
+        .execute("UPDATE testing SET val = 'newer' WHERE id = 1", [])
+        .await
+        .expect("updating row");
+    assert_eq!(changes.lock().unwrap().len(), 1);
+}
+
+async fn test_on_close() {
+    use std::sync::Arc;
+
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let path = tmp_dir.path().join("sqlite.db");
+    let on_close: Arc<dyn Fn(&mut async_sqlite::rusqlite::Connection) + Send + Sync> =
+        Arc::new(|conn| {
+            conn.execute("INSERT INTO closed (val) VALUES ('sentinel')", [])
+                .expect("writing sentinel row");
+        });
+
+    let client = ClientBuilder::new()
+        .path(&path)
+        .on_close(on_close.clone())
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute_batch("CREATE TABLE closed (val TEXT NOT NULL)")
+        .await
+        .expect("writing schema");
+    client.close().await.expect("closing client");
+
+    let pool = PoolBuilder::new()
+        .path(&path)
+        .num_conns(2)
+        .on_close(on_close)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    pool.close().await.expect("closing pool");
+
+    let client = ClientBuilder::new()
+        .path(&path)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let count: i64 = client
+        .query_row(
+            "SELECT COUNT(*) FROM closed WHERE val = 'sentinel'",
+            [],
+            |row| row.get(0),
+        )
+        .await
+        .expect("counting sentinel rows");
+    assert_eq!(count, 3);
+}
+
+#[cfg(feature = "trace")]
+async fn test_auto_optimize() {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    let traced = Arc::new(Mutex::new(Vec::new()));
+    let log = traced.clone();
+    let optimized = move || {
+        log.lock()
+            .unwrap()
+            .iter()
+            .filter(|sql| *sql == "PRAGMA optimize")
+            .count()
+    };
+
+    let trace = {
+        let traced = traced.clone();
+        Arc::new(move |sql: &str| traced.lock().unwrap().push(sql.to_owned()))
+    };
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .auto_optimize(true)
+        .trace(trace.clone())
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    pool.execute_batch("CREATE TABLE testing (id INTEGER PRIMARY KEY)")
+        .await
+        .expect("writing schema");
+    assert_eq!(optimized(), 0);
+    pool.close().await.expect("closing pool");
+    assert_eq!(optimized(), 2);
+
+    traced.lock().unwrap().clear();
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .auto_optimize_interval(Duration::from_millis(20))
+        .trace(trace)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    for _ in 0..100 {
+        if optimized() >= 2 {
+            break;
+        }
+        pool.conn(|_| {
+            std::thread::sleep(Duration::from_millis(10));
+            Ok(())
+        })
+        .await
+        .expect("waiting for optimize");
+    }
+    assert!(optimized() >= 2);
+    pool.close().await.expect("closing pool");
+}
+
+async fn test_close_timeout() {
+    use async_sqlite::rusqlite::ErrorCode;
+    use std::time::{Duration, Instant};
+
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+
+    let start = Instant::now();
+    let slow = client.query_row(
+        "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c)
+        SELECT COUNT(*) FROM c",
+        [],
+        |row| row.get::<_, i64>(0),
+    );
+    let close = client.close_timeout(Duration::from_millis(50));
+    let (slow, close) = futures_util::future::join(slow, close).await;
+    close.expect("closing client");
+    assert_eq!(
+        slow.expect_err("slow query should be interrupted")
+            .sqlite_error_code(),
+        Some(ErrorCode::OperationInterrupted)
+    );
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(client.is_closed());
+
+    // Closing an idle or already closed client doesn't wait for the timeout.
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let start = Instant::now();
+    client
+        .close_timeout(Duration::from_secs(10))
+        .await
+        .expect("closing idle client");
+    client
+        .close_timeout(Duration::from_secs(10))
+        .await
+        .expect("closing closed client");
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+async fn test_map_spread() {
+    let pool = PoolBuilder::new()
+        .num_conns(4)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+
+    let results = pool
+        .map_spread(0..100i64, |conn, i| {
+            conn.query_row("SELECT ?1 * ?1", [i], |row| row.get::<_, i64>(0))
+        })
+        .await;
+    let squares = results
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("running spread calls");
+    assert_eq!(squares, (0..100).map(|i| i * i).collect::<Vec<_>>());
+    assert_eq!(pool.stats().per_conn_requests, vec![25; 4]);
+
+    let results = pool
+        .map_spread(["SELECT 1", "NOT VALID SQL"], |conn, sql| {
+            conn.query_row(sql, [], |row| row.get::<_, i64>(0))
+        })
+        .await;
+    assert!(matches!(results[0], Ok(1)));
+    assert!(matches!(results[1], Err(Error::Rusqlite(_))));
+
+    pool.shutdown().await.expect("shutting down pool");
+    let results = pool.map_spread(0..2, |_, i| Ok(i)).await;
+    assert!(results.iter().all(|res| matches!(res, Err(Error::Closed))));
+}
+
+async fn test_busy_handler() {
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let path = tmp_dir.path().join("sqlite.db");
+    let locker = ClientBuilder::new()
+        .path(&path)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    locker
+        .execute_batch("CREATE TABLE testing (id INTEGER PRIMARY KEY)")
+        .await
+        .expect("writing schema");
+
+    let attempts = Arc::new(Mutex::new(Vec::new()));
+    let log = attempts.clone();
+    let client = ClientBuilder::new()
+        .path(&path)
+        .busy_handler(Arc::new(move |count| {
+            log.lock().unwrap().push(count);
+            thread::sleep(Duration::from_millis(5));
+            count < 1000
+        }))
+        .open()
+        .await
+        .expect("client unable to be opened");
+
+    // The lock is released once the handler has retried a few times.
+    locker
+        .execute_batch("BEGIN IMMEDIATE")
+        .await
+        .expect("locking database");
+    let write = client.execute("INSERT INTO testing VALUES (1)", []);
+    let release = async {
+        let retried = attempts.clone();
+        locker
+            .conn(move |_| {
+                while retried.lock().unwrap().len() < 3 {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                Ok(())
+            })
+            .await?;
+        locker.execute_batch("COMMIT").await
+    };
+    let (write, release) = futures_util::future::join(write, release).await;
+    release.expect("releasing lock");
+    assert_eq!(write.expect("writing after contention"), 1);
+    let attempts = attempts.lock().unwrap().clone();
+    assert!(attempts.len() >= 3);
+    assert_eq!(attempts[..3], [0, 1, 2]);
+
+    // Returning false gives up immediately.
+    let client = ClientBuilder::new()
+        .path(&path)
+        .busy_handler(Arc::new(|_| false))
+        .open()
+        .await
+        .expect("client unable to be opened");
+    locker
+        .execute_batch("BEGIN IMMEDIATE")
+        .await
+        .expect("locking database");
+    let err = client
+        .execute("INSERT INTO testing VALUES (2)", [])
+        .await
+        .expect_err("write should fail while locked");
+    assert!(err.is_busy());
+    locker
+        .execute_batch("COMMIT")
+        .await
+        .expect("releasing lock");
+}
+
+#[cfg(feature = "hooks")]
+async fn test_statement_timeout() {
+    use async_sqlite::rusqlite::ErrorCode;
+    use futures_util::StreamExt;
+    use std::time::{Duration, Instant};
+
+    const SLOW: &str = "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c)
+        SELECT COUNT(*) FROM c";
+
+    let client = ClientBuilder::new()
+        .statement_timeout(Duration::from_millis(50))
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let start = Instant::now();
+    let err = client
+        .query_row(SLOW, [], |row| row.get::<_, i64>(0))
+        .await
+        .expect_err("slow query should be aborted");
+    assert_eq!(
+        err.sqlite_error_code(),
+        Some(ErrorCode::OperationInterrupted)
+    );
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    // Later calls get a fresh budget.
+    let val: i64 = client
+        .query_row("SELECT 1", [], |row| row.get(0))
+        .await
+        .expect("running fast query");
+    assert_eq!(val, 1);
+
+    // Streaming queries restart the budget for each row, so a slow consumer
+    // doesn't abort a query that is fast to step through, even once the
+    // buffered rows have filled up and the worker waits on the consumer.
+    let rows = client
+        .query_stream(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 100)
+            SELECT i, (WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c
+            WHERE x < 1000) SELECT COUNT(*) FROM c) FROM n",
+            [],
+            |row| row.get::<_, i64>(0),
+        )
+        .await
+        .then(|res| async move {
+            std::thread::sleep(Duration::from_millis(3));
+            res
+        })
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("streaming rows to a slow consumer");
+    assert_eq!(rows, (1..=100).collect::<Vec<_>>());
+
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .statement_timeout(Duration::from_millis(50))
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    for res in pool
+        .conn_for_each(|conn| conn.query_row(SLOW, [], |row| row.get::<_, i64>(0)))
+        .await
+    {
+        let err = res.expect_err("slow query should be aborted");
+        assert_eq!(
+            err.sqlite_error_code(),
+            Some(ErrorCode::OperationInterrupted)
+        );
+    }
+}
+
+async fn test_execute_ddl() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let pool = PoolBuilder::new()
+        .path(tmp_dir.path().join("sqlite.db"))
+        .num_conns(3)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+
+    pool.execute_ddl("CREATE TABLE testing (id INTEGER PRIMARY KEY)")
+        .await
+        .expect("creating table");
+    pool.execute("INSERT INTO testing VALUES (1)", [])
+        .await
+        .expect("inserting row");
+
+    let select_all = |conn: &async_sqlite::rusqlite::Connection| {
+        let mut stmt = conn.prepare_cached("SELECT * FROM testing")?;
+        let columns = stmt.column_count();
+        stmt.query_row([], |_| Ok(columns))
+    };
+    for index in 0..3 {
+        let columns = pool
+            .conn_on(index, select_all)
+            .await
+            .expect("querying table on each connection");
+        assert_eq!(columns, 1);
+    }
+
+    pool.execute_ddl("ALTER TABLE testing ADD COLUMN val TEXT")
+        .await
+        .expect("altering table");
+    for index in 0..3 {
+        let columns = pool
+            .conn_on(index, select_all)
+            .await
+            .expect("querying altered table on each connection");
+        assert_eq!(columns, 2);
+    }
+
+    let err = pool
+        .execute_ddl("CREATE TABLE testing (id INTEGER PRIMARY KEY)")
+        .await
+        .expect_err("creating existing table should fail");
+    assert!(matches!(err, Error::Rusqlite(_)));
+}
+
+async fn test_dyn_params() {
+    use async_sqlite::rusqlite::types::Value;
+
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let path = tmp_dir.path().join("sqlite.db");
+    let client = ClientBuilder::new()
+        .path(&path)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    client
+        .execute_batch("CREATE TABLE testing (id INTEGER PRIMARY KEY, val TEXT, num INTEGER)")
+        .await
+        .expect("writing schema");
+
+    let changed = client
+        .execute_dyn(
+            "INSERT INTO testing VALUES (?1, ?2, ?3), (?4, ?5, ?6)",
+            vec![
+                Value::Integer(1),
+                Value::Text("one".to_owned()),
+                Value::Null,
+                Value::Integer(2),
+                Value::Null,
+                Value::Integer(20),
+            ],
+        )
+        .await
+        .expect("inserting rows");
+    assert_eq!(changed, 2);
+
+    let rows = client
+        .query_map_dyn(
+            "SELECT val, num FROM testing WHERE id >= ?1 ORDER BY id",
+            vec![Value::Integer(1)],
+            |row| {
+                Ok((
+                    row.get::<_, Option<String>>(0)?,
+                    row.get::<_, Option<i64>>(1)?,
+                ))
+            },
+        )
+        .await
+        .expect("querying rows");
+    assert_eq!(rows, vec![(Some("one".to_owned()), None), (None, Some(20))]);
+
+    let pool = PoolBuilder::new()
+        .path(&path)
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    let changed = pool
+        .execute_dyn(
+            "UPDATE testing SET val = ?1 WHERE val IS NULL",
+            vec![Value::Text("two".to_owned())],
+        )
+        .await
+        .expect("updating rows");
+    assert_eq!(changed, 1);
+    let vals = pool
+        .query_map_dyn("SELECT val FROM testing ORDER BY id", Vec::new(), |row| {
+            row.get::<_, String>(0)
+        })
+        .await
+        .expect("querying rows");
+    assert_eq!(vals, vec!["one", "two"]);
+
+    let err = pool
+        .execute_dyn("INSERT INTO testing (id) VALUES (?1)", Vec::new())
+        .await
+        .expect_err("missing parameters should fail");
+    assert!(matches!(err, Error::Rusqlite(_)));
+}
+
+async fn test_weak_pool() {
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    let clone = pool.clone();
+    let weak = pool.downgrade();
+
+    let upgraded = weak.upgrade().expect("upgrading live pool");
+    let val: i64 = upgraded
+        .query_row("SELECT 1", [], |row| row.get(0))
+        .await
+        .expect("querying upgraded pool");
+    assert_eq!(val, 1);
+    drop(upgraded);
+
+    drop(pool);
+    assert!(weak.upgrade().is_some());
+    drop(clone);
+    assert!(weak.upgrade().is_none());
+    assert!(weak.clone().upgrade().is_none());
+}
+
+async fn test_thread_name() {
+    let thread_name = |_: &async_sqlite::rusqlite::Connection| {
+        Ok(std::thread::current().name().map(str::to_owned))
+    };
+
+    let client = ClientBuilder::new()
+        .thread_name("sqlite-worker")
+        .thread_stack_size(4 * 1024 * 1024)
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let name = client.conn(thread_name).await.expect("getting thread name");
+    assert_eq!(name.as_deref(), Some("sqlite-worker"));
+
+    let client = ClientBuilder::new()
+        .open()
+        .await
+        .expect("client unable to be opened");
+    let name = client.conn(thread_name).await.expect("getting thread name");
+    assert_eq!(name, None);
+
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    let names = pool
+        .try_conn_for_each(thread_name)
+        .await
+        .expect("getting thread names");
+    assert_eq!(
+        names,
+        vec![
+            Some("async-sqlite-0".to_owned()),
+            Some("async-sqlite-1".to_owned())
+        ]
+    );
+
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .thread_name("db")
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    pool.resize(3).await.expect("resizing pool");
+    let names = pool
+        .try_conn_for_each(thread_name)
+        .await
+        .expect("getting thread names");
+    assert_eq!(
+        names,
+        vec![
+            Some("db-0".to_owned()),
+            Some("db-1".to_owned()),
+            Some("db-2".to_owned())
+        ]
+    );
+}
+
+async fn test_shared_queue() {
+    use std::time::{Duration, Instant};
+
+    // Returns the longest latency of several short calls made alongside a
+    // long one on a pool of two connections.
+    async fn max_latency(pool: &async_sqlite::Pool) -> Duration {
+        let start = Instant::now();
+        let slow = pool.conn(|_| {
+            std::thread::sleep(Duration::from_millis(500));
+            Ok(())
+        });
+        let fast = (0..6).map(|_| async {
+            pool.conn(|_| {
+                std::thread::sleep(Duration::from_millis(20));
+                Ok(())
+            })
+            .await
+            .expect("running fast call");
+            start.elapsed()
+        });
+        let (slow, latencies) =
+            futures_util::future::join(slow, futures_util::future::join_all(fast)).await;
+        slow.expect("running slow call");
+        latencies.into_iter().max().unwrap()
+    }
+
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    let round_robin = max_latency(&pool).await;
+    // Some short calls are queued behind the long one.
+    assert!(round_robin >= Duration::from_millis(500));
+
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .shared_queue(true)
+        .open()
+        .await
+        .expect("pool unable to be opened");
+    let shared = max_latency(&pool).await;
+    // The short calls are all run by the free connection.
+    assert!(shared < Duration::from_millis(500));
+    assert!(shared < round_robin);
+
+    // Calls on a specific connection bypass the shared queue.
+    let ids = pool
+        .try_conn_for_each(|conn| conn.query_row("SELECT 1", [], |row| row.get::<_, i64>(0)))
+        .await
+        .expect("running on each connection");
+    assert_eq!(ids, vec![1, 1]);
+    let stats = pool.stats();
+    assert_eq!(stats.total_requests, 7);
+    assert_eq!(stats.per_conn_requests, vec![0, 0]);
+
+    pool.resize(3).await.expect("growing pool");
+    let res = pool.conn_on(2, |conn| conn.execute_batch("SELECT 1")).await;
+    res.expect("running on new connection");
+    pool.conn(|conn| conn.execute_batch("SELECT 1"))
+        .await
+        .expect("running on shared queue");
+
+    pool.close().await.expect("closing pool");
+    let res = pool.conn(|conn| conn.execute_batch("SELECT 1")).await;
+    assert!(matches!(res, Err(Error::Closed)));
+    let res = pool.conn_blocking(|conn| conn.execute_batch("SELECT 1"));
+    assert!(matches!(res, Err(Error::Closed)));
+
+    let pool = PoolBuilder::new()
+        .num_conns(2)
+        .shared_queue(true)
+        .open_blocking()
+        .expect("pool unable to be opened");
+    // Calls already in the shared queue run before the connections close.
+    let calls = (0..4).map(|_| {
+        pool.conn(|_| {
+            std::thread::sleep(Duration::from_millis(20));
+            Ok(())
+        })
+    });
+    let (results, shutdown) =
+        futures_util::future::join(futures_util::future::join_all(calls), pool.shutdown()).await;
+    shutdown.expect("shutting down pool");
+    for res in results {
+        res.expect("running call queued before shutdown");
+    }
+    let res = pool.conn(|conn| conn.execute_batch("SELECT 1")).await;
+    assert!(matches!(res, Err(Error::Closed)));
+}